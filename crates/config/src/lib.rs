@@ -6,6 +6,64 @@ pub struct Config {
     pub redis_url: String,
     pub rpc_url: String,
     pub x_token: Option<String>,
+    /// seconds between two keepalive pings sent to the geyser, 0 for none
+    pub geyser_ping_interval_secs: u64,
+    /// unanswered pings in a row after which the geyser connection is
+    /// dropped, 0 for never
+    pub geyser_max_missed_pongs: u32,
+    /// codec of the published payloads: json, bincode, msgpack or protobuf
+    pub wire_codec: String,
+    /// updates the adapter pipelines to redis per round trip at most
+    pub publish_batch_size: usize,
+    /// how long the adapter collects updates before publishing them
+    pub publish_flush_interval_ms: u64,
+    /// decoded updates waiting to be published at most
+    pub publish_queue_capacity: usize,
+    /// what happens on a full queue: block, drop-oldest or drop-by-kind:<kinds>
+    pub publish_queue_overflow: String,
+    /// attempts per batch before it is spilled to disk
+    pub publish_retries: u32,
+    /// delay before the first retry of a batch, doubled on every attempt
+    pub publish_retry_backoff_ms: u64,
+    /// directory batches that failed to publish are spilled to
+    pub publish_spill_dir: String,
+    /// disk space the spilled batches may take up, `0` turns spilling off
+    pub publish_spill_max_mb: u64,
+    pub consumers: ConsumerSwitches,
+    /// consumer group shared by every db consumer
+    pub redis_group: Option<String>,
+    /// name of this db consumer within the group, unique per process
+    pub redis_consumer: Option<String>,
+    /// approximate number of entries kept per redis stream, unset keeps them all
+    pub redis_stream_max_len: Option<usize>,
+    /// rows the db consumer flushes per insert at most
+    pub db_batch_size: usize,
+    /// how long the db consumer waits for a batch to fill up
+    pub db_flush_interval_ms: u64,
+    /// keep every account write in `accounts`, not only the latest state
+    pub db_account_history: bool,
+    /// delete the rows of dead or skipped slots instead of flagging them orphaned
+    pub db_delete_orphans: bool,
+}
+
+/// Which channels the db consumer subscribes to, every channel is
+/// consumed unless switched off with e.g. `CONSUME_ENTRIES=false`
+pub struct ConsumerSwitches {
+    pub accounts: bool,
+    pub blocks: bool,
+    pub blocks_meta: bool,
+    pub transactions: bool,
+    pub slots: bool,
+    pub entries: bool,
+    pub transaction_status: bool,
+}
+
+// reads a boolean switch from the env, falling back to the default when unset
+fn env_flag(key: &str, default: bool) -> bool {
+    match env::var(key) {
+        Ok(value) => matches!(value.to_lowercase().as_str(), "1" | "true" | "yes" | "on"),
+        Err(_) => default,
+    }
 }
 
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
@@ -15,5 +73,64 @@ pub static CONFIG: Lazy<Config> = Lazy::new(|| {
         redis_url: env::var("REDIS_URL").expect("REDIS_URL must be set"),
         rpc_url: env::var("RPC_URL").expect("API_URL must be set"),
         x_token: env::var("RPC_API_KEY").ok(),
+        geyser_ping_interval_secs: env::var("GEYSER_PING_INTERVAL_SECS")
+            .map(|v| {
+                v.parse()
+                    .expect("GEYSER_PING_INTERVAL_SECS must be a number")
+            })
+            .unwrap_or(10),
+        geyser_max_missed_pongs: env::var("GEYSER_MAX_MISSED_PONGS")
+            .map(|v| v.parse().expect("GEYSER_MAX_MISSED_PONGS must be a number"))
+            .unwrap_or(3),
+        wire_codec: env::var("WIRE_CODEC").unwrap_or_else(|_| "json".to_owned()),
+        publish_batch_size: env::var("PUBLISH_BATCH_SIZE")
+            .map(|v| v.parse().expect("PUBLISH_BATCH_SIZE must be a number"))
+            .unwrap_or(500),
+        publish_flush_interval_ms: env::var("PUBLISH_FLUSH_INTERVAL_MS")
+            .map(|v| {
+                v.parse()
+                    .expect("PUBLISH_FLUSH_INTERVAL_MS must be a number")
+            })
+            .unwrap_or(10),
+        publish_queue_capacity: env::var("PUBLISH_QUEUE_CAPACITY")
+            .map(|v| v.parse().expect("PUBLISH_QUEUE_CAPACITY must be a number"))
+            .unwrap_or(10_000),
+        publish_queue_overflow: env::var("PUBLISH_QUEUE_OVERFLOW")
+            .unwrap_or_else(|_| "block".to_owned()),
+        publish_retries: env::var("PUBLISH_RETRIES")
+            .map(|v| v.parse().expect("PUBLISH_RETRIES must be a number"))
+            .unwrap_or(3),
+        publish_retry_backoff_ms: env::var("PUBLISH_RETRY_BACKOFF_MS")
+            .map(|v| {
+                v.parse()
+                    .expect("PUBLISH_RETRY_BACKOFF_MS must be a number")
+            })
+            .unwrap_or(100),
+        publish_spill_dir: env::var("PUBLISH_SPILL_DIR").unwrap_or_else(|_| "spill".to_owned()),
+        publish_spill_max_mb: env::var("PUBLISH_SPILL_MAX_MB")
+            .map(|v| v.parse().expect("PUBLISH_SPILL_MAX_MB must be a number"))
+            .unwrap_or(1024),
+        consumers: ConsumerSwitches {
+            accounts: env_flag("CONSUME_ACCOUNTS", true),
+            blocks: env_flag("CONSUME_BLOCKS", true),
+            blocks_meta: env_flag("CONSUME_BLOCKS_META", true),
+            transactions: env_flag("CONSUME_TRANSACTIONS", true),
+            slots: env_flag("CONSUME_SLOTS", true),
+            entries: env_flag("CONSUME_ENTRIES", true),
+            transaction_status: env_flag("CONSUME_TRANSACTION_STATUS", true),
+        },
+        redis_group: env::var("REDIS_CONSUMER_GROUP").ok(),
+        redis_consumer: env::var("REDIS_CONSUMER_NAME").ok(),
+        redis_stream_max_len: env::var("REDIS_STREAM_MAXLEN")
+            .ok()
+            .map(|v| v.parse().expect("REDIS_STREAM_MAXLEN must be a number")),
+        db_batch_size: env::var("DB_BATCH_SIZE")
+            .map(|v| v.parse().expect("DB_BATCH_SIZE must be a number"))
+            .unwrap_or(500),
+        db_flush_interval_ms: env::var("DB_FLUSH_INTERVAL_MS")
+            .map(|v| v.parse().expect("DB_FLUSH_INTERVAL_MS must be a number"))
+            .unwrap_or(200),
+        db_account_history: env_flag("DB_ACCOUNT_HISTORY", true),
+        db_delete_orphans: env_flag("DB_DELETE_ORPHANS", false),
     }
 });
@@ -0,0 +1,8 @@
+// Channels the geyser adapter publishes to and the db consumes from
+pub const CH_ACCOUNTS: &str = "accounts";
+pub const CH_BLOCKS: &str = "blocks";
+pub const CH_BLOCKS_META: &str = "blocks_meta";
+pub const CH_TRANSACTIONS: &str = "transactions";
+pub const CH_SLOTS: &str = "slots";
+pub const CH_ENTRIES: &str = "entries";
+pub const CH_TRANSACTION_STATUS: &str = "transaction_status";
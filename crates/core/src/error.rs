@@ -0,0 +1,58 @@
+use thiserror::Error;
+
+/// Why a geyser update couldn't be converted into the core models
+#[derive(Debug, Error)]
+pub enum ConversionError {
+    #[error("Empty SubscribeUpdate received")]
+    EmptyUpdate,
+    #[error("{update} update is missing its {field}")]
+    MissingField {
+        update: &'static str,
+        field: &'static str,
+    },
+    #[error("Unknown slot status {0}")]
+    UnknownSlotStatus(i32),
+    #[error("Invalid token amount '{0}'")]
+    InvalidTokenAmount(String),
+}
+
+/// Why a payload couldn't be framed or read back
+#[derive(Debug, Error)]
+pub enum WireError {
+    #[error("Unknown codec '{0}', expected json, bincode, msgpack or protobuf")]
+    UnknownCodecName(String),
+    #[error("Unknown codec id {0}")]
+    UnknownCodec(u8),
+    #[error("Unknown update kind id {0}")]
+    UnknownKind(u8),
+    #[error("Unsupported schema version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Payload of {0} bytes is too short for a frame header")]
+    Truncated(usize),
+    #[error("{0} filter names don't fit a frame header, at most 255 do")]
+    TooManyFilters(usize),
+    #[error("Filter name '{0}' is longer than 255 bytes")]
+    FilterNameTooLong(String),
+    #[error("Filter name in the frame header isn't utf-8")]
+    InvalidFilterName,
+    #[error("Payload doesn't belong on the '{0}' channel")]
+    WrongKind(crate::UpdateKind),
+    #[error("Only the updates published on a channel can be framed")]
+    NotPublished,
+    #[error("The protobuf codec needs the geyser update the payload was converted from")]
+    MissingRaw,
+    #[error("Json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Bincode: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("MessagePack: {0}")]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+    #[error("MessagePack: {0}")]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+    #[error("Protobuf: {0}")]
+    ProtobufEncode(#[from] yellowstone_grpc_proto::prost::EncodeError),
+    #[error("Protobuf: {0}")]
+    ProtobufDecode(#[from] yellowstone_grpc_proto::prost::DecodeError),
+    #[error(transparent)]
+    Conversion(#[from] ConversionError),
+}
@@ -1,3 +1,9 @@
+pub mod channels;
+pub mod error;
 pub mod model;
+pub mod wire;
 
+pub use channels::*;
+pub use error::*;
 pub use model::*;
+pub use wire::*;
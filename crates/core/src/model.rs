@@ -1,7 +1,10 @@
-use anyhow::{Error, Ok};
+use crate::ConversionError;
 use bs58::encode;
 use serde::{Deserialize, Serialize};
-use yellowstone_grpc_proto::prelude::{self as yp};
+use yellowstone_grpc_proto::{
+    convert_from::create_tx_error,
+    prelude::{self as yp},
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BlockUpdate {
@@ -9,6 +12,12 @@ pub struct BlockUpdate {
     pub blockhash: String,
     pub parent_slot: u64,
     pub parent_blockhash: String,
+    /// unix timestamp of the block, when the validator knows it
+    pub block_time: Option<i64>,
+    pub block_height: Option<u64>,
+    /// rewards credited to validators and stakers in this block
+    #[serde(default)]
+    pub rewards: Vec<Reward>,
     pub executed_transaction_count: u64,
     pub transactions: Vec<TransactionUpdateInfo>,
     pub updated_account_count: u64,
@@ -17,11 +26,29 @@ pub struct BlockUpdate {
     pub entries: Vec<EntryUpdate>,
 }
 
+/// Block header without the transactions, accounts and entries of the block
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockMetaUpdate {
+    pub slot: u64,
+    pub blockhash: String,
+    pub parent_slot: u64,
+    pub parent_blockhash: String,
+    /// unix timestamp of the block, when the validator knows it
+    pub block_time: Option<i64>,
+    pub block_height: Option<u64>,
+    /// rewards credited to validators and stakers in this block
+    #[serde(default)]
+    pub rewards: Vec<Reward>,
+    pub executed_transaction_count: u64,
+    pub entries_count: u64,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct EntryUpdate {
     pub slot: u64,
     pub index: u64,
     pub num_hashes: u64,
+    #[serde(with = "serde_bytes")]
     pub hash: Vec<u8>,
     pub executed_transaction_count: u64,
     pub starting_transaction_index: u64,
@@ -35,26 +62,133 @@ pub struct TransactionUpdate {
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct TransactionUpdateInfo {
+    #[serde(with = "serde_bytes")]
     pub signature: Vec<u8>,
     pub is_vote: bool,
     pub index: u64,
+    #[serde(with = "byte_vecs")]
+    pub signatures: Vec<Vec<u8>>,
+    pub message: Option<TransactionMessage>,
     pub meta: Option<TransactionMeta>,
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageVersion {
+    Legacy,
+    V0,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TransactionMessage {
+    pub version: MessageVersion,
+    pub header: Option<MessageHeader>,
+    /// static account keys, for v0 messages the keys loaded from
+    /// address lookup tables follow these in the meta
+    #[serde(with = "byte_vecs")]
+    pub account_keys: Vec<Vec<u8>>,
+    #[serde(with = "serde_bytes")]
+    pub recent_blockhash: Vec<u8>,
+    pub instructions: Vec<CompiledInstruction>,
+    /// always empty for legacy messages
+    pub address_table_lookups: Vec<AddressTableLookup>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct MessageHeader {
+    pub num_required_signatures: u32,
+    pub num_readonly_signed_accounts: u32,
+    pub num_readonly_unsigned_accounts: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CompiledInstruction {
+    pub program_id_index: u32,
+    /// indexes into the message account keys
+    #[serde(with = "serde_bytes")]
+    pub accounts: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AddressTableLookup {
+    #[serde(with = "serde_bytes")]
+    pub account_key: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub writable_indexes: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub readonly_indexes: Vec<u8>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct TransactionMeta {
+    /// decoded transaction error, `None` when the transaction succeeded
+    pub err: Option<String>,
     pub fee: u64,
     pub compute_units_consumed: Option<u64>,
     pub pre_balances: Vec<u64>,
     pub post_balances: Vec<u64>,
+    pub inner_instructions: Vec<InnerInstructions>,
     pub log_messages: Vec<String>,
     pub pre_token_balance: Vec<TokenBalance>,
     pub post_token_balance: Vec<TokenBalance>,
+    pub rewards: Vec<Reward>,
+    #[serde(with = "byte_vecs")]
+    pub loaded_writable_addresses: Vec<Vec<u8>>,
+    #[serde(with = "byte_vecs")]
+    pub loaded_readonly_addresses: Vec<Vec<u8>>,
+    pub return_data: Option<ReturnData>,
+}
+
+/// instructions invoked through CPI by the top level instruction at `index`
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct InnerInstructions {
+    pub index: u32,
+    pub instructions: Vec<InnerInstruction>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct InnerInstruction {
+    pub program_id_index: u32,
+    #[serde(with = "serde_bytes")]
+    pub accounts: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    pub stack_height: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ReturnData {
+    #[serde(with = "serde_bytes")]
+    pub program_id: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardType {
+    Unspecified,
+    Fee,
+    Rent,
+    Staking,
+    Voting,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Reward {
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: u64,
+    pub reward_type: RewardType,
+    pub commission: Option<u8>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct TransactionStatusUpdate {
     pub slot: u64,
+    #[serde(with = "serde_bytes")]
     pub signature: Vec<u8>,
     pub is_vote: bool,
     pub index: u64,
@@ -67,37 +201,61 @@ pub struct TokenBalance {
     pub mint: String,
     pub owner: String,
     pub program_id: String,
+    /// raw amount in base units of the mint
+    pub amount: u64,
+    pub decimals: u32,
+    /// amount scaled by the mint decimals
+    pub ui_amount: f64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AccountUpdate {
     pub slot: u64,
+    /// the write comes from the snapshot loaded at validator startup
     pub is_startup: bool,
     pub info: AccountInfo,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AccountInfo {
+    #[serde(with = "serde_bytes")]
     pub pubkey: Vec<u8>,
     pub lamports: u64,
+    #[serde(with = "serde_bytes")]
     pub owner: Vec<u8>,
     pub executable: bool,
     pub rent_epoch: u64,
+    /// stored as a byte string by the binary codecs, as an array of numbers in json
+    #[serde(with = "serde_bytes")]
     pub data: Vec<u8>,
     pub write_version: u64,
+    #[serde(with = "serde_bytes")]
     pub txn_signature: Option<Vec<u8>>,
 }
 
+/// Lifecycle of a slot as reported by geyser
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotStatus {
+    Processed,
+    Confirmed,
+    Finalized,
+    FirstShredReceived,
+    Completed,
+    CreatedBank,
+    Dead,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SlotUpdate {
     pub slot: u64,
     pub parent: Option<u64>,
-    pub status: i32,
+    pub status: SlotStatus,
     pub dead_error: Option<String>,
 }
 
 impl TryFrom<yp::SubscribeUpdateAccount> for AccountUpdate {
-    type Error = Error;
+    type Error = ConversionError;
 
     fn try_from(value: yp::SubscribeUpdateAccount) -> Result<Self, Self::Error> {
         Ok(AccountUpdate {
@@ -115,7 +273,10 @@ impl TryFrom<yp::SubscribeUpdateAccount> for AccountUpdate {
                     write_version: acc.write_version,
                     txn_signature: acc.txn_signature,
                 })
-                .expect(&format!("Account info should not be None")),
+                .ok_or(ConversionError::MissingField {
+                    update: "Account",
+                    field: "account info",
+                })?,
         })
     }
 }
@@ -136,49 +297,287 @@ impl AccountInfo {
     }
 }
 
-impl TryFrom<yp::SubscribeUpdateTransaction> for TransactionUpdate {
-    type Error = Error;
-    fn try_from(value: yp::SubscribeUpdateTransaction) -> Result<Self, Self::Error> {
-        Ok(TransactionUpdate {
-            slot: value.slot,
-            transaction: value.transaction.map(|tx| TransactionUpdateInfo {
-                index: tx.index,
-                is_vote: tx.is_vote,
-                signature: tx.signature,
-                meta: tx.meta.map(|meta| TransactionMeta {
-                    fee: meta.fee,
-                    log_messages: meta.log_messages,
-                    pre_balances: meta.pre_balances,
-                    compute_units_consumed: meta.compute_units_consumed,
-                    post_balances: meta.post_balances,
-                    post_token_balance: meta
-                        .post_token_balances
-                        .into_iter()
-                        .map(|tb| TokenBalance {
-                            account_index: tb.account_index,
-                            mint: tb.mint,
-                            owner: tb.owner,
-                            program_id: tb.program_id,
-                        })
-                        .collect(),
-                    pre_token_balance: meta
-                        .pre_token_balances
+impl TransactionMessage {
+    pub fn recent_blockhash_string(&self) -> String {
+        encode(&self.recent_blockhash).into_string()
+    }
+
+    // resolves the program invoked by a top level instruction, programs
+    // can't be loaded from lookup tables so the static keys are enough
+    pub fn program_id(&self, instruction: &CompiledInstruction) -> Option<&[u8]> {
+        self.account_keys
+            .get(instruction.program_id_index as usize)
+            .map(Vec::as_slice)
+    }
+}
+
+impl TransactionUpdateInfo {
+    // static keys followed by the writable and readonly keys loaded from
+    // lookup tables, which is the list inner instructions index into
+    pub fn account_keys(&self) -> Vec<&[u8]> {
+        let static_keys = self.message.iter().flat_map(|m| m.account_keys.iter());
+        let loaded_keys = self.meta.iter().flat_map(|meta| {
+            meta.loaded_writable_addresses
+                .iter()
+                .chain(meta.loaded_readonly_addresses.iter())
+        });
+        static_keys.chain(loaded_keys).map(Vec::as_slice).collect()
+    }
+}
+
+impl From<yp::Message> for TransactionMessage {
+    fn from(value: yp::Message) -> Self {
+        TransactionMessage {
+            version: if value.versioned {
+                MessageVersion::V0
+            } else {
+                MessageVersion::Legacy
+            },
+            header: value.header.map(|header| MessageHeader {
+                num_required_signatures: header.num_required_signatures,
+                num_readonly_signed_accounts: header.num_readonly_signed_accounts,
+                num_readonly_unsigned_accounts: header.num_readonly_unsigned_accounts,
+            }),
+            account_keys: value.account_keys,
+            recent_blockhash: value.recent_blockhash,
+            instructions: value
+                .instructions
+                .into_iter()
+                .map(|ix| CompiledInstruction {
+                    program_id_index: ix.program_id_index,
+                    accounts: ix.accounts,
+                    data: ix.data,
+                })
+                .collect(),
+            address_table_lookups: value
+                .address_table_lookups
+                .into_iter()
+                .map(|lookup| AddressTableLookup {
+                    account_key: lookup.account_key,
+                    writable_indexes: lookup.writable_indexes,
+                    readonly_indexes: lookup.readonly_indexes,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<yp::TransactionStatusMeta> for TransactionMeta {
+    type Error = ConversionError;
+
+    fn try_from(meta: yp::TransactionStatusMeta) -> Result<Self, Self::Error> {
+        Ok(TransactionMeta {
+            err: meta.err.as_ref().map(decode_transaction_error),
+            fee: meta.fee,
+            log_messages: meta.log_messages,
+            pre_balances: meta.pre_balances,
+            compute_units_consumed: meta.compute_units_consumed,
+            post_balances: meta.post_balances,
+            inner_instructions: meta
+                .inner_instructions
+                .into_iter()
+                .map(|inner| InnerInstructions {
+                    index: inner.index,
+                    instructions: inner
+                        .instructions
                         .into_iter()
-                        .map(|tb| TokenBalance {
-                            account_index: tb.account_index,
-                            mint: tb.mint,
-                            owner: tb.owner,
-                            program_id: tb.program_id,
+                        .map(|ix| InnerInstruction {
+                            program_id_index: ix.program_id_index,
+                            accounts: ix.accounts,
+                            data: ix.data,
+                            stack_height: ix.stack_height,
                         })
                         .collect(),
+                })
+                .collect(),
+            post_token_balance: token_balances(meta.post_token_balances),
+            pre_token_balance: token_balances(meta.pre_token_balances),
+            rewards: meta.rewards.into_iter().map(Reward::from).collect(),
+            loaded_writable_addresses: meta.loaded_writable_addresses,
+            loaded_readonly_addresses: meta.loaded_readonly_addresses,
+            return_data: meta
+                .return_data
+                .filter(|_| !meta.return_data_none)
+                .map(|rd| ReturnData {
+                    program_id: rd.program_id,
+                    data: rd.data,
                 }),
-            }),
+        })
+    }
+}
+
+// a balance that can't be read is left out with a warning, so one bad field
+// doesn't cost the rest of the transaction, or of its block
+fn token_balances(balances: Vec<yp::TokenBalance>) -> Vec<TokenBalance> {
+    balances
+        .into_iter()
+        .filter_map(|balance| {
+            let account_index = balance.account_index;
+            TokenBalance::try_from(balance)
+                .inspect_err(|e| {
+                    eprintln!(
+                        "⚠️ Skipping token balance of account index {}: {}",
+                        account_index, e
+                    )
+                })
+                .ok()
+        })
+        .collect()
+}
+
+impl TryFrom<yp::TokenBalance> for TokenBalance {
+    type Error = ConversionError;
+
+    fn try_from(value: yp::TokenBalance) -> Result<Self, Self::Error> {
+        let ui_token_amount = value.ui_token_amount.ok_or(ConversionError::MissingField {
+            update: "TokenBalance",
+            field: "ui_token_amount",
+        })?;
+        // the proto carries the raw amount as a decimal string
+        let amount = ui_token_amount
+            .amount
+            .parse()
+            .map_err(|_| ConversionError::InvalidTokenAmount(ui_token_amount.amount.clone()))?;
+        Ok(TokenBalance {
+            account_index: value.account_index,
+            mint: value.mint,
+            owner: value.owner,
+            program_id: value.program_id,
+            amount,
+            decimals: ui_token_amount.decimals,
+            ui_amount: ui_token_amount.ui_amount,
+        })
+    }
+}
+
+impl RewardType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RewardType::Unspecified => "unspecified",
+            RewardType::Fee => "fee",
+            RewardType::Rent => "rent",
+            RewardType::Staking => "staking",
+            RewardType::Voting => "voting",
+        }
+    }
+}
+
+impl SlotStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlotStatus::Processed => "processed",
+            SlotStatus::Confirmed => "confirmed",
+            SlotStatus::Finalized => "finalized",
+            SlotStatus::FirstShredReceived => "first_shred_received",
+            SlotStatus::Completed => "completed",
+            SlotStatus::CreatedBank => "created_bank",
+            SlotStatus::Dead => "dead",
+        }
+    }
+}
+
+impl From<yp::SlotStatus> for SlotStatus {
+    fn from(value: yp::SlotStatus) -> Self {
+        match value {
+            yp::SlotStatus::SlotProcessed => SlotStatus::Processed,
+            yp::SlotStatus::SlotConfirmed => SlotStatus::Confirmed,
+            yp::SlotStatus::SlotFinalized => SlotStatus::Finalized,
+            yp::SlotStatus::SlotFirstShredReceived => SlotStatus::FirstShredReceived,
+            yp::SlotStatus::SlotCompleted => SlotStatus::Completed,
+            yp::SlotStatus::SlotCreatedBank => SlotStatus::CreatedBank,
+            yp::SlotStatus::SlotDead => SlotStatus::Dead,
+        }
+    }
+}
+
+impl From<SlotStatus> for yp::SlotStatus {
+    fn from(value: SlotStatus) -> Self {
+        match value {
+            SlotStatus::Processed => yp::SlotStatus::SlotProcessed,
+            SlotStatus::Confirmed => yp::SlotStatus::SlotConfirmed,
+            SlotStatus::Finalized => yp::SlotStatus::SlotFinalized,
+            SlotStatus::FirstShredReceived => yp::SlotStatus::SlotFirstShredReceived,
+            SlotStatus::Completed => yp::SlotStatus::SlotCompleted,
+            SlotStatus::CreatedBank => yp::SlotStatus::SlotCreatedBank,
+            SlotStatus::Dead => yp::SlotStatus::SlotDead,
+        }
+    }
+}
+
+impl From<yp::Reward> for Reward {
+    fn from(value: yp::Reward) -> Self {
+        Reward {
+            pubkey: value.pubkey,
+            lamports: value.lamports,
+            post_balance: value.post_balance,
+            reward_type: match yp::RewardType::try_from(value.reward_type)
+                .unwrap_or(yp::RewardType::Unspecified)
+            {
+                yp::RewardType::Unspecified => RewardType::Unspecified,
+                yp::RewardType::Fee => RewardType::Fee,
+                yp::RewardType::Rent => RewardType::Rent,
+                yp::RewardType::Staking => RewardType::Staking,
+                yp::RewardType::Voting => RewardType::Voting,
+            },
+            // the proto carries the commission as a string, empty when absent
+            commission: value.commission.parse().ok(),
+        }
+    }
+}
+
+fn block_rewards(rewards: Option<yp::Rewards>) -> Vec<Reward> {
+    rewards
+        .map(|r| r.rewards.into_iter().map(Reward::from).collect())
+        .unwrap_or_default()
+}
+
+// the error is a bincode encoded `TransactionError`, decode it to its
+// display form like "Error processing Instruction 0: custom program error: 0x1"
+pub fn decode_transaction_error(err: &yp::TransactionError) -> String {
+    match create_tx_error(Some(err)).ok().flatten() {
+        Some(err) => err.to_string(),
+        None => format!("Undecodable transaction error: {:?}", err.err),
+    }
+}
+
+// shared by transaction updates and the transactions embedded in blocks
+impl TryFrom<yp::SubscribeUpdateTransactionInfo> for TransactionUpdateInfo {
+    type Error = ConversionError;
+
+    fn try_from(tx: yp::SubscribeUpdateTransactionInfo) -> Result<Self, Self::Error> {
+        let (signatures, message) = match tx.transaction {
+            Some(transaction) => (
+                transaction.signatures,
+                transaction.message.map(TransactionMessage::from),
+            ),
+            None => (vec![], None),
+        };
+        Ok(TransactionUpdateInfo {
+            index: tx.index,
+            is_vote: tx.is_vote,
+            signature: tx.signature,
+            signatures,
+            message,
+            meta: tx.meta.map(TransactionMeta::try_from).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<yp::SubscribeUpdateTransaction> for TransactionUpdate {
+    type Error = ConversionError;
+    fn try_from(value: yp::SubscribeUpdateTransaction) -> Result<Self, Self::Error> {
+        Ok(TransactionUpdate {
+            slot: value.slot,
+            transaction: value
+                .transaction
+                .map(TransactionUpdateInfo::try_from)
+                .transpose()?,
         })
     }
 }
 
 impl TryFrom<yp::SubscribeUpdateBlock> for BlockUpdate {
-    type Error = Error;
+    type Error = ConversionError;
 
     fn try_from(value: yp::SubscribeUpdateBlock) -> Result<Self, Self::Error> {
         Ok(BlockUpdate {
@@ -186,43 +585,15 @@ impl TryFrom<yp::SubscribeUpdateBlock> for BlockUpdate {
             blockhash: value.blockhash,
             parent_slot: value.parent_slot,
             parent_blockhash: value.parent_blockhash,
+            block_time: value.block_time.map(|t| t.timestamp),
+            block_height: value.block_height.map(|h| h.block_height),
+            rewards: block_rewards(value.rewards),
             executed_transaction_count: value.executed_transaction_count,
             transactions: value
                 .transactions
                 .into_iter()
-                .map(|tx| TransactionUpdateInfo {
-                    index: tx.index,
-                    is_vote: tx.is_vote,
-                    signature: tx.signature,
-                    meta: tx.meta.map(|meta| TransactionMeta {
-                        fee: meta.fee,
-                        log_messages: meta.log_messages,
-                        pre_balances: meta.pre_balances,
-                        compute_units_consumed: meta.compute_units_consumed,
-                        post_balances: meta.post_balances,
-                        post_token_balance: meta
-                            .post_token_balances
-                            .into_iter()
-                            .map(|tb| TokenBalance {
-                                account_index: tb.account_index,
-                                mint: tb.mint,
-                                owner: tb.owner,
-                                program_id: tb.program_id,
-                            })
-                            .collect(),
-                        pre_token_balance: meta
-                            .pre_token_balances
-                            .into_iter()
-                            .map(|tb| TokenBalance {
-                                account_index: tb.account_index,
-                                mint: tb.mint,
-                                owner: tb.owner,
-                                program_id: tb.program_id,
-                            })
-                            .collect(),
-                    }),
-                })
-                .collect(),
+                .map(TransactionUpdateInfo::try_from)
+                .collect::<Result<_, _>>()?,
             updated_account_count: value.updated_account_count,
             accounts: value
                 .accounts
@@ -255,8 +626,26 @@ impl TryFrom<yp::SubscribeUpdateBlock> for BlockUpdate {
     }
 }
 
+impl TryFrom<yp::SubscribeUpdateBlockMeta> for BlockMetaUpdate {
+    type Error = ConversionError;
+
+    fn try_from(value: yp::SubscribeUpdateBlockMeta) -> Result<Self, Self::Error> {
+        Ok(BlockMetaUpdate {
+            slot: value.slot,
+            blockhash: value.blockhash,
+            parent_slot: value.parent_slot,
+            parent_blockhash: value.parent_blockhash,
+            block_time: value.block_time.map(|t| t.timestamp),
+            block_height: value.block_height.map(|h| h.block_height),
+            rewards: block_rewards(value.rewards),
+            executed_transaction_count: value.executed_transaction_count,
+            entries_count: value.entries_count,
+        })
+    }
+}
+
 impl TryFrom<yp::SubscribeUpdateEntry> for EntryUpdate {
-    type Error = Error;
+    type Error = ConversionError;
 
     fn try_from(value: yp::SubscribeUpdateEntry) -> Result<Self, Self::Error> {
         Ok(EntryUpdate {
@@ -271,20 +660,22 @@ impl TryFrom<yp::SubscribeUpdateEntry> for EntryUpdate {
 }
 
 impl TryFrom<yp::SubscribeUpdateSlot> for SlotUpdate {
-    type Error = Error;
+    type Error = ConversionError;
 
     fn try_from(value: yp::SubscribeUpdateSlot) -> Result<Self, Self::Error> {
         Ok(SlotUpdate {
             slot: value.slot,
             parent: value.parent,
-            status: value.status,
+            status: yp::SlotStatus::try_from(value.status)
+                .map_err(|_| ConversionError::UnknownSlotStatus(value.status))?
+                .into(),
             dead_error: value.dead_error,
         })
     }
 }
 
 impl TryFrom<yp::SubscribeUpdateTransactionStatus> for TransactionStatusUpdate {
-    type Error = Error;
+    type Error = ConversionError;
 
     fn try_from(value: yp::SubscribeUpdateTransactionStatus) -> Result<Self, Self::Error> {
         Ok(TransactionStatusUpdate {
@@ -292,54 +683,435 @@ impl TryFrom<yp::SubscribeUpdateTransactionStatus> for TransactionStatusUpdate {
             is_vote: value.is_vote,
             signature: value.signature,
             index: value.index,
-            err: None,
+            err: value.err.as_ref().map(decode_transaction_error),
         })
     }
 }
 
+/// An update along with the filter groups of the subscription it matched.
+/// Consumers not interested in the groups can deserialize the inner update
+/// directly, the extra `filters` field is ignored
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Filtered<T> {
+    /// names of the filter groups from filters.json that matched the update
+    #[serde(default)]
+    pub filters: Vec<String>,
+    #[serde(flatten)]
+    pub update: T,
+}
+
+impl<T> Filtered<T> {
+    pub fn new(filters: Vec<String>, update: T) -> Self {
+        Filtered { filters, update }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
 pub enum Update {
     Block(BlockUpdate),
+    BlockMeta(BlockMetaUpdate),
     Transaction(TransactionUpdate),
     Account(AccountUpdate),
     Entry(EntryUpdate),
     Slot(SlotUpdate),
     TransactionStatus(TransactionStatusUpdate),
+    Ping,
+    /// answer to a ping sent by the client, carrying the id of that ping
+    Pong(i32),
 }
 
-impl From<yp::SubscribeUpdate> for Update {
-    fn from(value: yp::SubscribeUpdate) -> Self {
-        match value.update_oneof {
-            Some(yp::subscribe_update::UpdateOneof::Block(b)) => {
-                Update::Block(BlockUpdate::try_from(b).expect("Failed to convert to BlockUpdate"))
-            }
-            Some(yp::subscribe_update::UpdateOneof::Transaction(t)) => Update::Transaction(
-                TransactionUpdate::try_from(t).expect("Failed to convert to TransactionUpdate"),
-            ),
-            Some(yp::subscribe_update::UpdateOneof::Account(a)) => Update::Account(
-                AccountUpdate::try_from(a).expect("Failed to convert to AccountUpdate"),
-            ),
-            Some(yp::subscribe_update::UpdateOneof::Entry(e)) => {
-                Update::Entry(EntryUpdate::try_from(e).expect("Failed to convert to EntryUpdate"))
-            }
-            Some(yp::subscribe_update::UpdateOneof::Slot(s)) => {
-                Update::Slot(SlotUpdate::try_from(s).expect("Failed to convert to SlotUpdate"))
-            }
-            Some(yp::subscribe_update::UpdateOneof::TransactionStatus(ts)) => {
-                Update::TransactionStatus(
-                    TransactionStatusUpdate::try_from(ts)
-                        .expect("Failed to convert to TransactionStatusUpdate"),
-                )
-            }
-            Some(yp::subscribe_update::UpdateOneof::Ping(_)) => {
-                unimplemented!()
-            }
-            Some(yp::subscribe_update::UpdateOneof::Pong(_)) => {
-                unimplemented!()
-            }
-            Some(yp::subscribe_update::UpdateOneof::BlockMeta(_)) => {
-                unimplemented!()
-            }
-            None => panic!("Empty SubscribeUpdate received"),
+impl TryFrom<yp::SubscribeUpdate> for Update {
+    type Error = ConversionError;
+
+    fn try_from(value: yp::SubscribeUpdate) -> Result<Self, Self::Error> {
+        use yp::subscribe_update::UpdateOneof;
+
+        Ok(
+            match value.update_oneof.ok_or(ConversionError::EmptyUpdate)? {
+                UpdateOneof::Block(b) => Update::Block(b.try_into()?),
+                UpdateOneof::BlockMeta(bm) => Update::BlockMeta(bm.try_into()?),
+                UpdateOneof::Transaction(t) => Update::Transaction(t.try_into()?),
+                UpdateOneof::Account(a) => Update::Account(a.try_into()?),
+                UpdateOneof::Entry(e) => Update::Entry(e.try_into()?),
+                UpdateOneof::Slot(s) => Update::Slot(s.try_into()?),
+                UpdateOneof::TransactionStatus(ts) => Update::TransactionStatus(ts.try_into()?),
+                UpdateOneof::Ping(_) => Update::Ping,
+                UpdateOneof::Pong(pong) => Update::Pong(pong.id),
+            },
+        )
+    }
+}
+
+// serde_bytes for lists of byte strings like keys and signatures, each one
+// goes out as bytes instead of a list of numbers
+mod byte_vecs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde_bytes::{ByteBuf, Bytes};
+
+    pub fn serialize<S: Serializer>(values: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(|value| Bytes::new(value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        let values = Vec::<ByteBuf>::deserialize(deserializer)?;
+        Ok(values.into_iter().map(ByteBuf::into_vec).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_balance(amount: &str) -> yp::TokenBalance {
+        yp::TokenBalance {
+            account_index: 1,
+            mint: "So11111111111111111111111111111111111111112".to_owned(),
+            ui_token_amount: Some(yp::UiTokenAmount {
+                amount: amount.to_owned(),
+                decimals: 9,
+                ..Default::default()
+            }),
+            ..Default::default()
         }
     }
+
+    #[test]
+    fn token_amount_keeps_the_whole_u64_range() {
+        let balance = TokenBalance::try_from(token_balance("18446744073709551615")).unwrap();
+        assert_eq!(balance.amount, u64::MAX);
+    }
+
+    #[test]
+    fn invalid_token_amount_is_an_error() {
+        for amount in ["", "-1", "1.5", "18446744073709551616"] {
+            let err = TokenBalance::try_from(token_balance(amount)).unwrap_err();
+            assert!(
+                matches!(&err, ConversionError::InvalidTokenAmount(a) if a == amount),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn missing_token_amount_is_an_error() {
+        let balance = yp::TokenBalance {
+            ui_token_amount: None,
+            ..token_balance("1")
+        };
+        assert!(matches!(
+            TokenBalance::try_from(balance),
+            Err(ConversionError::MissingField { .. })
+        ));
+    }
+
+    fn update(update: yp::subscribe_update::UpdateOneof) -> yp::SubscribeUpdate {
+        yp::SubscribeUpdate {
+            filters: vec![],
+            update_oneof: Some(update),
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn empty_update_is_an_error() {
+        assert!(matches!(
+            Update::try_from(yp::SubscribeUpdate::default()),
+            Err(ConversionError::EmptyUpdate)
+        ));
+    }
+
+    #[test]
+    fn account_update_without_an_account_is_an_error() {
+        let account = yp::SubscribeUpdateAccount {
+            account: None,
+            slot: 42,
+            is_startup: false,
+        };
+        assert!(matches!(
+            Update::try_from(update(yp::subscribe_update::UpdateOneof::Account(account))),
+            Err(ConversionError::MissingField {
+                update: "Account",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn pings_and_pongs_convert() {
+        let ping = update(yp::subscribe_update::UpdateOneof::Ping(
+            yp::SubscribeUpdatePing {},
+        ));
+        assert!(matches!(Update::try_from(ping), Ok(Update::Ping)));
+
+        let pong = update(yp::subscribe_update::UpdateOneof::Pong(
+            yp::SubscribeUpdatePong { id: 7 },
+        ));
+        assert!(matches!(Update::try_from(pong), Ok(Update::Pong(7))));
+    }
+
+    #[test]
+    fn block_meta_converts() {
+        let meta = yp::SubscribeUpdateBlockMeta {
+            slot: 42,
+            blockhash: "hash".to_owned(),
+            parent_slot: 41,
+            block_time: Some(yp::UnixTimestamp {
+                timestamp: 1_700_000_000,
+            }),
+            block_height: Some(yp::BlockHeight { block_height: 40 }),
+            executed_transaction_count: 3,
+            entries_count: 2,
+            ..Default::default()
+        };
+        let Ok(Update::BlockMeta(meta)) =
+            Update::try_from(update(yp::subscribe_update::UpdateOneof::BlockMeta(meta)))
+        else {
+            panic!("not a block meta update");
+        };
+        assert_eq!(meta.slot, 42);
+        assert_eq!(meta.parent_slot, 41);
+        assert_eq!(meta.block_time, Some(1_700_000_000));
+        assert_eq!(meta.block_height, Some(40));
+        assert_eq!(meta.executed_transaction_count, 3);
+        assert!(meta.rewards.is_empty());
+    }
+
+    #[test]
+    fn unreadable_token_balances_are_left_out() {
+        let missing = yp::TokenBalance {
+            ui_token_amount: None,
+            ..token_balance("1")
+        };
+        let meta = yp::TransactionStatusMeta {
+            pre_token_balances: vec![token_balance("5"), token_balance("1.5")],
+            post_token_balances: vec![missing, token_balance("7")],
+            ..Default::default()
+        };
+        let meta = TransactionMeta::try_from(meta).unwrap();
+        let amounts =
+            |balances: &[TokenBalance]| balances.iter().map(|b| b.amount).collect::<Vec<_>>();
+        assert_eq!(amounts(&meta.pre_token_balance), vec![5]);
+        assert_eq!(amounts(&meta.post_token_balance), vec![7]);
+    }
+
+    fn proto_message(versioned: bool) -> yp::Message {
+        yp::Message {
+            header: Some(yp::MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            }),
+            account_keys: vec![vec![1; 32], vec![2; 32], vec![3; 32]],
+            recent_blockhash: vec![9; 32],
+            instructions: vec![yp::CompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![0, 1, 3],
+                data: vec![7, 7],
+            }],
+            versioned,
+            address_table_lookups: vec![yp::MessageAddressTableLookup {
+                account_key: vec![4; 32],
+                writable_indexes: vec![5],
+                readonly_indexes: vec![6, 8],
+            }],
+        }
+    }
+
+    #[test]
+    fn message_keeps_instructions_and_lookups() {
+        let message = TransactionMessage::from(proto_message(true));
+        assert_eq!(message.version, MessageVersion::V0);
+        assert_eq!(message.header.unwrap().num_readonly_unsigned_accounts, 1);
+        assert_eq!(message.recent_blockhash, vec![9; 32]);
+
+        let [ix] = message.instructions.as_slice() else {
+            panic!("expected one instruction");
+        };
+        assert_eq!(ix.accounts, vec![0, 1, 3]);
+        assert_eq!(ix.data, vec![7, 7]);
+        assert_eq!(message.program_id(ix), Some(&[3; 32][..]));
+
+        let [lookup] = message.address_table_lookups.as_slice() else {
+            panic!("expected one lookup");
+        };
+        assert_eq!(lookup.account_key, vec![4; 32]);
+        assert_eq!(lookup.writable_indexes, vec![5]);
+        assert_eq!(lookup.readonly_indexes, vec![6, 8]);
+
+        let legacy = TransactionMessage::from(proto_message(false));
+        assert_eq!(legacy.version, MessageVersion::Legacy);
+    }
+
+    #[test]
+    fn program_id_out_of_the_static_keys_is_none() {
+        let message = TransactionMessage::from(proto_message(true));
+        let ix = CompiledInstruction {
+            program_id_index: 3,
+            accounts: vec![],
+            data: vec![],
+        };
+        assert_eq!(message.program_id(&ix), None);
+    }
+
+    // bincode of `TransactionError::InstructionError(1, InstructionError::Custom(6))`
+    const CUSTOM_ERROR: [u8; 13] = [8, 0, 0, 0, 1, 25, 0, 0, 0, 6, 0, 0, 0];
+
+    #[test]
+    fn transaction_errors_are_decoded() {
+        let err = yp::TransactionError {
+            err: CUSTOM_ERROR.to_vec(),
+        };
+        assert_eq!(
+            decode_transaction_error(&err),
+            "Error processing Instruction 1: custom program error: 0x6"
+        );
+
+        let garbage = yp::TransactionError { err: vec![255; 4] };
+        assert!(decode_transaction_error(&garbage).starts_with("Undecodable transaction error"));
+    }
+
+    fn status_meta() -> yp::TransactionStatusMeta {
+        yp::TransactionStatusMeta {
+            err: Some(yp::TransactionError {
+                err: CUSTOM_ERROR.to_vec(),
+            }),
+            inner_instructions: vec![yp::InnerInstructions {
+                index: 1,
+                instructions: vec![yp::InnerInstruction {
+                    program_id_index: 4,
+                    accounts: vec![0, 3],
+                    data: vec![5],
+                    stack_height: Some(2),
+                }],
+            }],
+            loaded_writable_addresses: vec![vec![4; 32]],
+            loaded_readonly_addresses: vec![vec![5; 32]],
+            return_data: Some(yp::ReturnData {
+                program_id: vec![4; 32],
+                data: vec![1, 2, 3],
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn meta_keeps_error_inner_instructions_loaded_addresses_and_return_data() {
+        let meta = TransactionMeta::try_from(status_meta()).unwrap();
+        assert_eq!(
+            meta.err.as_deref(),
+            Some("Error processing Instruction 1: custom program error: 0x6")
+        );
+
+        let [inner] = meta.inner_instructions.as_slice() else {
+            panic!("expected the CPIs of one instruction");
+        };
+        assert_eq!(inner.index, 1);
+        let [ix] = inner.instructions.as_slice() else {
+            panic!("expected one CPI");
+        };
+        assert_eq!(ix.program_id_index, 4);
+        assert_eq!(ix.accounts, vec![0, 3]);
+        assert_eq!(ix.stack_height, Some(2));
+
+        assert_eq!(meta.loaded_writable_addresses, vec![vec![4; 32]]);
+        assert_eq!(meta.loaded_readonly_addresses, vec![vec![5; 32]]);
+        let return_data = meta.return_data.unwrap();
+        assert_eq!(return_data.program_id, vec![4; 32]);
+        assert_eq!(return_data.data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn return_data_flagged_as_none_is_dropped() {
+        let meta = yp::TransactionStatusMeta {
+            return_data_none: true,
+            ..status_meta()
+        };
+        assert!(
+            TransactionMeta::try_from(meta)
+                .unwrap()
+                .return_data
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn transaction_status_error_is_decoded() {
+        let status = yp::SubscribeUpdateTransactionStatus {
+            slot: 42,
+            signature: vec![1; 64],
+            is_vote: false,
+            index: 3,
+            err: Some(yp::TransactionError {
+                err: CUSTOM_ERROR.to_vec(),
+            }),
+        };
+        let status = TransactionStatusUpdate::try_from(status).unwrap();
+        assert_eq!(
+            status.err.as_deref(),
+            Some("Error processing Instruction 1: custom program error: 0x6")
+        );
+    }
+
+    #[test]
+    fn account_keys_put_the_loaded_keys_after_the_static_ones() {
+        let info = TransactionUpdateInfo {
+            signature: vec![],
+            is_vote: false,
+            index: 0,
+            signatures: vec![],
+            message: Some(TransactionMessage::from(proto_message(true))),
+            meta: Some(TransactionMeta::try_from(status_meta()).unwrap()),
+        };
+        let keys = info.account_keys();
+        assert_eq!(
+            keys,
+            vec![&[1; 32][..], &[2; 32], &[3; 32], &[4; 32], &[5; 32]]
+        );
+    }
+
+    const SLOT_STATUSES: [(yp::SlotStatus, SlotStatus); 7] = [
+        (yp::SlotStatus::SlotProcessed, SlotStatus::Processed),
+        (yp::SlotStatus::SlotConfirmed, SlotStatus::Confirmed),
+        (yp::SlotStatus::SlotFinalized, SlotStatus::Finalized),
+        (
+            yp::SlotStatus::SlotFirstShredReceived,
+            SlotStatus::FirstShredReceived,
+        ),
+        (yp::SlotStatus::SlotCompleted, SlotStatus::Completed),
+        (yp::SlotStatus::SlotCreatedBank, SlotStatus::CreatedBank),
+        (yp::SlotStatus::SlotDead, SlotStatus::Dead),
+    ];
+
+    fn slot_update(status: i32) -> yp::SubscribeUpdateSlot {
+        yp::SubscribeUpdateSlot {
+            slot: 42,
+            parent: Some(41),
+            status,
+            dead_error: None,
+        }
+    }
+
+    #[test]
+    fn slot_status_round_trips() {
+        for (proto, status) in SLOT_STATUSES {
+            assert_eq!(SlotStatus::from(proto), status);
+            assert_eq!(yp::SlotStatus::from(status), proto);
+
+            let update = SlotUpdate::try_from(slot_update(proto as i32)).unwrap();
+            assert_eq!(update.status, status);
+        }
+    }
+
+    #[test]
+    fn unknown_slot_status_is_an_error() {
+        let unknown = SLOT_STATUSES.len() as i32;
+        assert!(yp::SlotStatus::try_from(unknown).is_err());
+        assert!(matches!(
+            SlotUpdate::try_from(slot_update(unknown)),
+            Err(ConversionError::UnknownSlotStatus(s)) if s == unknown
+        ));
+    }
 }
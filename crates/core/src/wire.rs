@@ -0,0 +1,610 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Serialize, de::DeserializeOwned};
+use yellowstone_grpc_proto::{prelude as yp, prost::Message};
+
+use crate::{
+    AccountInfo, AccountUpdate, BlockMetaUpdate, BlockUpdate, CH_ACCOUNTS, CH_BLOCKS,
+    CH_BLOCKS_META, CH_ENTRIES, CH_SLOTS, CH_TRANSACTION_STATUS, CH_TRANSACTIONS, EntryUpdate,
+    Filtered, SlotUpdate, TransactionStatusUpdate, TransactionUpdate, Update, WireError,
+};
+
+/// Version of the framed payload layout, bumped whenever the models change in
+/// a way older consumers can't read
+pub const SCHEMA_VERSION: u8 = 1;
+
+// first bytes of every framed payload, json payloads start with `{` instead
+const MAGIC: [u8; 2] = *b"SX";
+// magic, schema version, codec, kind and the little endian slot. the names of
+// the matched filters follow, a count byte and every name after its length
+const HEADER_LEN: usize = MAGIC.len() + 3 + 8;
+
+/// How the body of a payload is encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// the json of the update tagged with its filters, published without a
+    /// frame header as before the binary codecs existed
+    Json,
+    Bincode,
+    MessagePack,
+    /// the geyser update as received, in its protobuf encoding
+    Protobuf,
+}
+
+impl Codec {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::Bincode => "bincode",
+            Codec::MessagePack => "msgpack",
+            Codec::Protobuf => "protobuf",
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Codec::Json => 0,
+            Codec::Bincode => 1,
+            Codec::MessagePack => 2,
+            Codec::Protobuf => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, WireError> {
+        Ok(match id {
+            0 => Codec::Json,
+            1 => Codec::Bincode,
+            2 => Codec::MessagePack,
+            3 => Codec::Protobuf,
+            other => return Err(WireError::UnknownCodec(other)),
+        })
+    }
+}
+
+impl FromStr for Codec {
+    type Err = WireError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "json" => Codec::Json,
+            "bincode" => Codec::Bincode,
+            "msgpack" | "messagepack" => Codec::MessagePack,
+            "protobuf" | "proto" => Codec::Protobuf,
+            _ => return Err(WireError::UnknownCodecName(s.to_owned())),
+        })
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Kind of update a payload holds, each kind has a channel of its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateKind {
+    Account = 1,
+    Block = 2,
+    BlockMeta = 3,
+    Transaction = 4,
+    TransactionStatus = 5,
+    Entry = 6,
+    Slot = 7,
+}
+
+impl UpdateKind {
+    const ALL: [UpdateKind; 7] = [
+        UpdateKind::Account,
+        UpdateKind::Block,
+        UpdateKind::BlockMeta,
+        UpdateKind::Transaction,
+        UpdateKind::TransactionStatus,
+        UpdateKind::Entry,
+        UpdateKind::Slot,
+    ];
+
+    pub fn channel(self) -> &'static str {
+        match self {
+            UpdateKind::Account => CH_ACCOUNTS,
+            UpdateKind::Block => CH_BLOCKS,
+            UpdateKind::BlockMeta => CH_BLOCKS_META,
+            UpdateKind::Transaction => CH_TRANSACTIONS,
+            UpdateKind::TransactionStatus => CH_TRANSACTION_STATUS,
+            UpdateKind::Entry => CH_ENTRIES,
+            UpdateKind::Slot => CH_SLOTS,
+        }
+    }
+
+    pub fn from_channel(channel: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.channel() == channel)
+    }
+
+    fn from_id(id: u8) -> Result<Self, WireError> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| *kind as u8 == id)
+            .ok_or(WireError::UnknownKind(id))
+    }
+}
+
+impl fmt::Display for UpdateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.channel())
+    }
+}
+
+/// What a payload says about the update it carries
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// `0` for json payloads, which have no frame header
+    pub schema_version: u8,
+    pub codec: Codec,
+    pub kind: UpdateKind,
+    pub slot: u64,
+    /// names of the filter groups from filters.json that matched the update
+    pub filters: Vec<String>,
+}
+
+/// A decoded payload
+#[derive(Clone, Debug)]
+pub struct Envelope<T> {
+    pub header: Header,
+    pub update: T,
+}
+
+/// An update published on the channel of its kind
+pub trait WirePayload: Serialize + DeserializeOwned {
+    const KIND: UpdateKind;
+
+    fn slot(&self) -> u64;
+
+    // the update of this kind, out of a converted geyser update
+    fn from_update(update: Update) -> Option<Self>;
+
+    // reads a json payload
+    fn from_json(payload: &[u8]) -> Result<Filtered<Self>, WireError> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+// frames an update with the codec. `raw` is the geyser update it was
+// converted from, only the protobuf codec needs it
+pub fn encode(
+    codec: Codec,
+    update: &Update,
+    filters: Vec<String>,
+    raw: Option<&yp::SubscribeUpdate>,
+) -> Result<Vec<u8>, WireError> {
+    match update {
+        Update::Account(update) => encode_payload(codec, update, filters, raw),
+        Update::Block(update) => encode_payload(codec, update, filters, raw),
+        Update::BlockMeta(update) => encode_payload(codec, update, filters, raw),
+        Update::Transaction(update) => encode_payload(codec, update, filters, raw),
+        Update::TransactionStatus(update) => encode_payload(codec, update, filters, raw),
+        Update::Entry(update) => encode_payload(codec, update, filters, raw),
+        Update::Slot(update) => encode_payload(codec, update, filters, raw),
+        Update::Ping | Update::Pong(_) => Err(WireError::NotPublished),
+    }
+}
+
+pub fn encode_payload<T: WirePayload>(
+    codec: Codec,
+    update: &T,
+    filters: Vec<String>,
+    raw: Option<&yp::SubscribeUpdate>,
+) -> Result<Vec<u8>, WireError> {
+    // kept unframed so consumers reading plain json keep working
+    if codec == Codec::Json {
+        return Ok(serde_json::to_vec(&Filtered::new(filters, update))?);
+    }
+
+    let mut payload = Vec::with_capacity(HEADER_LEN + 256);
+    payload.extend_from_slice(&MAGIC);
+    payload.push(SCHEMA_VERSION);
+    payload.push(codec.id());
+    payload.push(T::KIND as u8);
+    payload.extend_from_slice(&update.slot().to_le_bytes());
+    write_filters(&mut payload, &filters)?;
+
+    match codec {
+        Codec::Json => serde_json::to_writer(&mut payload, update)?,
+        Codec::Bincode => bincode::serialize_into(&mut payload, update)?,
+        Codec::MessagePack => rmp_serde::encode::write(&mut payload, update)?,
+        Codec::Protobuf => raw.ok_or(WireError::MissingRaw)?.encode(&mut payload)?,
+    }
+    Ok(payload)
+}
+
+// the names are limited to 255 bytes, and the count to 255 names
+fn write_filters(payload: &mut Vec<u8>, filters: &[String]) -> Result<(), WireError> {
+    let count =
+        u8::try_from(filters.len()).map_err(|_| WireError::TooManyFilters(filters.len()))?;
+    payload.push(count);
+    for name in filters {
+        let len =
+            u8::try_from(name.len()).map_err(|_| WireError::FilterNameTooLong(name.clone()))?;
+        payload.push(len);
+        payload.extend_from_slice(name.as_bytes());
+    }
+    Ok(())
+}
+
+// the filter names of the header and the body after them
+fn read_filters(payload: &[u8]) -> Result<(Vec<String>, &[u8]), WireError> {
+    let truncated = || WireError::Truncated(payload.len());
+    let (&count, mut rest) = payload.split_first().ok_or_else(truncated)?;
+    let mut filters = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (&len, tail) = rest.split_first().ok_or_else(truncated)?;
+        let (name, tail) = tail.split_at_checked(len as usize).ok_or_else(truncated)?;
+        let name = std::str::from_utf8(name).map_err(|_| WireError::InvalidFilterName)?;
+        filters.push(name.to_owned());
+        rest = tail;
+    }
+    Ok((filters, rest))
+}
+
+// reads a payload of any codec, framed payloads are told apart by their
+// magic and anything else is taken for json
+pub fn decode<T: WirePayload>(payload: &[u8]) -> Result<Envelope<T>, WireError> {
+    if !payload.starts_with(&MAGIC) {
+        let Filtered { filters, update } = T::from_json(payload)?;
+        return Ok(Envelope {
+            header: Header {
+                schema_version: 0,
+                codec: Codec::Json,
+                kind: T::KIND,
+                slot: update.slot(),
+                filters,
+            },
+            update,
+        });
+    }
+
+    if payload.len() < HEADER_LEN {
+        return Err(WireError::Truncated(payload.len()));
+    }
+    let schema_version = payload[2];
+    if schema_version != SCHEMA_VERSION {
+        return Err(WireError::UnsupportedVersion(schema_version));
+    }
+    let codec = Codec::from_id(payload[3])?;
+    let kind = UpdateKind::from_id(payload[4])?;
+    if kind != T::KIND {
+        return Err(WireError::WrongKind(T::KIND));
+    }
+    let slot = u64::from_le_bytes(payload[5..HEADER_LEN].try_into().expect("8 bytes slot"));
+
+    let (filters, body) = read_filters(&payload[HEADER_LEN..])?;
+    let update = match codec {
+        Codec::Protobuf => {
+            let raw = yp::SubscribeUpdate::decode(body)?;
+            T::from_update(Update::try_from(raw)?).ok_or(WireError::WrongKind(T::KIND))?
+        }
+        _ => deserialize(codec, body)?,
+    };
+
+    Ok(Envelope {
+        header: Header {
+            schema_version,
+            codec,
+            kind,
+            slot,
+            filters,
+        },
+        update,
+    })
+}
+
+// reads the body of a serde codec, protobuf bodies are decoded by the caller
+fn deserialize<T: DeserializeOwned>(codec: Codec, body: &[u8]) -> Result<T, WireError> {
+    Ok(match codec {
+        Codec::Bincode => bincode::deserialize(body)?,
+        Codec::MessagePack => rmp_serde::from_slice(body)?,
+        Codec::Json | Codec::Protobuf => serde_json::from_slice(body)?,
+    })
+}
+
+impl WirePayload for AccountUpdate {
+    const KIND: UpdateKind = UpdateKind::Account;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::Account(update) => Some(update),
+            _ => None,
+        }
+    }
+
+    // older adapters only published the account info, without the slot and
+    // startup flag, those accounts are read at slot 0
+    fn from_json(payload: &[u8]) -> Result<Filtered<Self>, WireError> {
+        let update = match serde_json::from_slice(payload) {
+            Ok(update) => update,
+            Err(e) => match serde_json::from_slice::<Filtered<AccountInfo>>(payload) {
+                Ok(Filtered { filters, update }) => Filtered::new(
+                    filters,
+                    AccountUpdate {
+                        slot: 0,
+                        is_startup: false,
+                        info: update,
+                    },
+                ),
+                Err(_) => return Err(e.into()),
+            },
+        };
+        Ok(update)
+    }
+}
+
+impl WirePayload for BlockUpdate {
+    const KIND: UpdateKind = UpdateKind::Block;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::Block(update) => Some(update),
+            _ => None,
+        }
+    }
+}
+
+impl WirePayload for BlockMetaUpdate {
+    const KIND: UpdateKind = UpdateKind::BlockMeta;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::BlockMeta(update) => Some(update),
+            _ => None,
+        }
+    }
+}
+
+impl WirePayload for TransactionUpdate {
+    const KIND: UpdateKind = UpdateKind::Transaction;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::Transaction(update) => Some(update),
+            _ => None,
+        }
+    }
+}
+
+impl WirePayload for TransactionStatusUpdate {
+    const KIND: UpdateKind = UpdateKind::TransactionStatus;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::TransactionStatus(update) => Some(update),
+            _ => None,
+        }
+    }
+}
+
+impl WirePayload for EntryUpdate {
+    const KIND: UpdateKind = UpdateKind::Entry;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::Entry(update) => Some(update),
+            _ => None,
+        }
+    }
+}
+
+impl WirePayload for SlotUpdate {
+    const KIND: UpdateKind = UpdateKind::Slot;
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn from_update(update: Update) -> Option<Self> {
+        match update {
+            Update::Slot(update) => Some(update),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AddressTableLookup, CompiledInstruction, MessageVersion, SlotStatus, TransactionMessage,
+        TransactionUpdateInfo,
+    };
+
+    fn slot_update() -> SlotUpdate {
+        SlotUpdate {
+            slot: 42,
+            parent: Some(41),
+            status: SlotStatus::Confirmed,
+            dead_error: None,
+        }
+    }
+
+    fn transaction_update() -> TransactionUpdate {
+        TransactionUpdate {
+            slot: 42,
+            transaction: Some(TransactionUpdateInfo {
+                signature: vec![1; 64],
+                is_vote: false,
+                index: 3,
+                signatures: vec![vec![1; 64]],
+                message: Some(TransactionMessage {
+                    version: MessageVersion::V0,
+                    header: None,
+                    account_keys: vec![vec![2; 32], vec![3; 32]],
+                    recent_blockhash: vec![4; 32],
+                    instructions: vec![CompiledInstruction {
+                        program_id_index: 1,
+                        accounts: vec![0, 1],
+                        data: vec![5; 40],
+                    }],
+                    address_table_lookups: vec![AddressTableLookup {
+                        account_key: vec![6; 32],
+                        writable_indexes: vec![0],
+                        readonly_indexes: vec![1, 2],
+                    }],
+                }),
+                meta: None,
+            }),
+        }
+    }
+
+    fn filters() -> Vec<String> {
+        vec!["memo".to_owned(), "accounts_0".to_owned()]
+    }
+
+    #[test]
+    fn filters_are_in_the_frame_header() {
+        for codec in [Codec::Bincode, Codec::MessagePack] {
+            let payload = encode_payload(codec, &slot_update(), filters(), None).unwrap();
+            assert_eq!(payload[2], SCHEMA_VERSION);
+            assert_eq!(
+                read_filters(&payload[HEADER_LEN..]).unwrap().0,
+                filters(),
+                "{}",
+                codec
+            );
+
+            let envelope = decode::<SlotUpdate>(&payload).unwrap();
+            assert_eq!(envelope.header.filters, filters());
+            assert_eq!(envelope.header.slot, 42);
+            assert_eq!(envelope.update.status, SlotStatus::Confirmed);
+        }
+    }
+
+    #[test]
+    fn truncated_filters_are_an_error() {
+        let payload = encode_payload(Codec::Bincode, &slot_update(), filters(), None).unwrap();
+        let cut = &payload[..HEADER_LEN + 3];
+        assert!(matches!(
+            decode::<SlotUpdate>(cut),
+            Err(WireError::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn byte_fields_are_sent_as_bytes() {
+        let payload =
+            encode_payload(Codec::MessagePack, &transaction_update(), vec![], None).unwrap();
+        // msgpack bin8 markers of the 32 byte keys, numbers would take more
+        let keys = payload.windows(2).filter(|w| w == &[0xc4, 32]).count();
+        assert_eq!(keys, 4);
+
+        for codec in [Codec::Json, Codec::Bincode, Codec::MessagePack] {
+            let payload = encode_payload(codec, &transaction_update(), vec![], None).unwrap();
+            let info = decode::<TransactionUpdate>(&payload)
+                .unwrap()
+                .update
+                .transaction
+                .unwrap();
+            let message = info.message.unwrap();
+            assert_eq!(info.signatures, vec![vec![1; 64]]);
+            assert_eq!(message.account_keys, vec![vec![2; 32], vec![3; 32]]);
+            assert_eq!(message.recent_blockhash, vec![4; 32]);
+            assert_eq!(message.instructions[0].data, vec![5; 40]);
+            assert_eq!(
+                message.address_table_lookups[0].readonly_indexes,
+                vec![1, 2]
+            );
+        }
+    }
+
+    fn account_update() -> AccountUpdate {
+        AccountUpdate {
+            slot: 42,
+            is_startup: true,
+            info: AccountInfo {
+                pubkey: vec![1; 32],
+                lamports: 5,
+                owner: vec![2; 32],
+                executable: false,
+                rent_epoch: 0,
+                data: vec![3; 8],
+                write_version: 7,
+                txn_signature: None,
+            },
+        }
+    }
+
+    // the geyser update the account update converts from, for the protobuf codec
+    fn raw_account_update() -> yp::SubscribeUpdate {
+        let update = account_update();
+        yp::SubscribeUpdate {
+            filters: vec![],
+            update_oneof: Some(yp::subscribe_update::UpdateOneof::Account(
+                yp::SubscribeUpdateAccount {
+                    account: Some(yp::SubscribeUpdateAccountInfo {
+                        pubkey: update.info.pubkey,
+                        lamports: update.info.lamports,
+                        owner: update.info.owner,
+                        executable: update.info.executable,
+                        rent_epoch: update.info.rent_epoch,
+                        data: update.info.data,
+                        write_version: update.info.write_version,
+                        txn_signature: None,
+                    }),
+                    slot: update.slot,
+                    is_startup: update.is_startup,
+                },
+            )),
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn account_updates_keep_their_slot_and_startup_flag() {
+        let raw = raw_account_update();
+        for codec in [
+            Codec::Json,
+            Codec::Bincode,
+            Codec::MessagePack,
+            Codec::Protobuf,
+        ] {
+            let payload = encode_payload(codec, &account_update(), filters(), Some(&raw)).unwrap();
+            let envelope = decode::<AccountUpdate>(&payload).unwrap();
+            assert_eq!(envelope.header.slot, 42, "{}", codec);
+            assert_eq!(envelope.header.filters, filters(), "{}", codec);
+            assert_eq!(envelope.update.slot, 42, "{}", codec);
+            assert!(envelope.update.is_startup, "{}", codec);
+            assert_eq!(envelope.update.info.write_version, 7, "{}", codec);
+        }
+    }
+
+    #[test]
+    fn account_infos_of_older_adapters_are_read_at_slot_0() {
+        let payload = serde_json::to_vec(&Filtered::new(filters(), account_update().info)).unwrap();
+        let envelope = decode::<AccountUpdate>(&payload).unwrap();
+        assert_eq!(envelope.update.slot, 0);
+        assert!(!envelope.update.is_startup);
+        assert_eq!(envelope.update.info.pubkey, vec![1; 32]);
+        assert_eq!(envelope.header.filters, filters());
+    }
+}
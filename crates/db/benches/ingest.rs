@@ -0,0 +1,233 @@
+// compares the diesel multi-row insert path of the store with the COPY BINARY
+// path on synthetic transactions and accounts.
+//
+//     cargo bench -p db --bench ingest
+//
+// it writes into the database of DATABASE_URL, using slots far above anything
+// on chain and deleting them again after every run. BENCH_ROWS (default 5000)
+// and BENCH_ROUNDS (default 3) size the runs
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use core::{
+    CompiledInstruction, MessageHeader, MessageVersion, TokenBalance, TransactionMessage,
+    TransactionMeta, TransactionUpdateInfo,
+};
+use db::{
+    models::{NewAccount, NewTransaction, TokenAmount},
+    store::Store,
+};
+use diesel::{
+    RunQueryDsl,
+    pg::PgConnection,
+    r2d2::{ConnectionManager, Pool},
+    sql_query,
+};
+
+// first slot used by the benchmark rows
+const BENCH_SLOT: u64 = 900_000_000_000;
+
+fn main() -> Result<()> {
+    let rows = env_number("BENCH_ROWS", 5000);
+    let rounds = env_number("BENCH_ROUNDS", 3);
+
+    let db_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let manager = ConnectionManager::<PgConnection>::new(db_url);
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(manager)
+        .context("Failed to create database connection pool")?;
+    let store = Store::new(pool);
+
+    let infos: Vec<TransactionUpdateInfo> = (0..rows).map(transaction_info).collect();
+    let txs: Vec<(NewTransaction, &TransactionUpdateInfo)> = infos
+        .iter()
+        .map(|info| (new_transaction(info), info))
+        .collect();
+    let accounts: Vec<NewAccount> = (0..rows).map(new_account).collect();
+
+    println!(
+        "🏁 Benchmarking {} row(s) per run, {} round(s)",
+        rows, rounds
+    );
+
+    let insert = bench(&store, rounds, |store| store.insert_full_transactions(&txs))?;
+    let copy = bench(&store, rounds, |store| store.copy_full_transactions(&txs))?;
+    report("transactions", rows, insert, copy);
+
+    let insert = bench(&store, rounds, |store| store.insert_accounts(&accounts))?;
+    let copy = bench(&store, rounds, |store| store.copy_accounts(&accounts))?;
+    report("accounts", rows, insert, copy);
+
+    Ok(())
+}
+
+// runs the ingestion on an empty range of slots and returns the best time
+fn bench(
+    store: &Store,
+    rounds: usize,
+    ingest: impl Fn(&Store) -> Result<usize>,
+) -> Result<Duration> {
+    let mut best = Duration::MAX;
+    for _ in 0..rounds {
+        cleanup(store)?;
+        let start = Instant::now();
+        ingest(store)?;
+        best = best.min(start.elapsed());
+    }
+    cleanup(store)?;
+    Ok(best)
+}
+
+fn report(table: &str, rows: usize, insert: Duration, copy: Duration) {
+    let per_sec = |d: Duration| rows as f64 / d.as_secs_f64();
+    println!(
+        "📊 {:<12} insert: {:>9.2?} ({:>9.0} rows/s)   copy: {:>9.2?} ({:>9.0} rows/s)   {:.2}x",
+        table,
+        insert,
+        per_sec(insert),
+        copy,
+        per_sec(copy),
+        insert.as_secs_f64() / copy.as_secs_f64()
+    );
+}
+
+// child rows go with their transactions through the cascading foreign keys
+fn cleanup(store: &Store) -> Result<()> {
+    let mut conn = store
+        .pool
+        .get()
+        .context("Failed to get DB connection from pool")?;
+    sql_query(format!(
+        "DELETE FROM transactions WHERE slot >= {}",
+        BENCH_SLOT
+    ))
+    .execute(&mut conn)
+    .context("Failed to delete benchmark transactions")?;
+    sql_query(format!("DELETE FROM accounts WHERE slot >= {}", BENCH_SLOT))
+        .execute(&mut conn)
+        .context("Failed to delete benchmark accounts")?;
+    Ok(())
+}
+
+fn env_number(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+// a token transfer sized transaction: two instructions, a few log lines and
+// a pre and post token balance
+fn transaction_info(i: usize) -> TransactionUpdateInfo {
+    let signature = [(i as u64).to_le_bytes().as_slice(), &[7u8; 56]].concat();
+    let account_keys: Vec<Vec<u8>> = (0..4u8).map(|k| vec![k; 32]).collect();
+    let token_balance = |amount| TokenBalance {
+        account_index: 1,
+        mint: "So11111111111111111111111111111111111111112".to_owned(),
+        owner: "11111111111111111111111111111111".to_owned(),
+        program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".to_owned(),
+        amount,
+        decimals: 9,
+        ui_amount: amount as f64 / 1e9,
+    };
+
+    TransactionUpdateInfo {
+        signature: signature.clone(),
+        is_vote: false,
+        index: i as u64,
+        signatures: vec![signature],
+        message: Some(TransactionMessage {
+            version: MessageVersion::V0,
+            header: Some(MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            }),
+            account_keys,
+            recent_blockhash: vec![9; 32],
+            instructions: vec![
+                CompiledInstruction {
+                    program_id_index: 3,
+                    accounts: vec![],
+                    data: vec![2, 64, 13, 3, 0],
+                },
+                CompiledInstruction {
+                    program_id_index: 3,
+                    accounts: vec![0, 1, 2],
+                    data: vec![3, 0, 202, 154, 59, 0, 0, 0, 0],
+                },
+            ],
+            address_table_lookups: vec![],
+        }),
+        meta: Some(TransactionMeta {
+            err: None,
+            fee: 5000,
+            compute_units_consumed: Some(4_500),
+            pre_balances: vec![1_000_000, 2_039_280, 2_039_280, 1],
+            post_balances: vec![995_000, 2_039_280, 2_039_280, 1],
+            inner_instructions: vec![],
+            log_messages: vec![
+                "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [1]".to_owned(),
+                "Program log: Instruction: Transfer".to_owned(),
+                "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success".to_owned(),
+            ],
+            pre_token_balance: vec![token_balance(2_000_000_000)],
+            post_token_balance: vec![token_balance(1_000_000_000)],
+            rewards: vec![],
+            loaded_writable_addresses: vec![],
+            loaded_readonly_addresses: vec![],
+            return_data: None,
+        }),
+    }
+}
+
+fn new_transaction(info: &TransactionUpdateInfo) -> NewTransaction {
+    let meta = info
+        .meta
+        .as_ref()
+        .expect("benchmark transactions have a meta");
+    let amounts = |balances: &[TokenBalance]| {
+        Some(
+            balances
+                .iter()
+                .map(|tb| Some(TokenAmount(tb.amount)))
+                .collect(),
+        )
+    };
+
+    NewTransaction::new(
+        (BENCH_SLOT + info.index / 1000) as i64,
+        info.signature.clone(),
+        info.is_vote,
+        (info.index % 1000) as i32,
+        Some(meta.fee as i64),
+        meta.compute_units_consumed.map(|u| u as i64),
+        Some(meta.pre_balances.iter().map(|b| Some(*b as i64)).collect()),
+        Some(meta.post_balances.iter().map(|b| Some(*b as i64)).collect()),
+        Some(meta.log_messages.iter().cloned().map(Some).collect()),
+        amounts(&meta.pre_token_balance),
+        amounts(&meta.post_token_balance),
+    )
+    .with_message(info.signatures.clone(), info.message.as_ref())
+    .with_status(meta)
+}
+
+fn new_account(i: usize) -> NewAccount {
+    NewAccount::new(
+        (BENCH_SLOT + i as u64 / 1000) as i64,
+        false,
+        [(i as u64).to_le_bytes().as_slice(), &[3u8; 24]].concat(),
+        2_039_280,
+        vec![6; 32],
+        false,
+        0,
+        vec![1; 165],
+        i as i64,
+        None,
+    )
+}
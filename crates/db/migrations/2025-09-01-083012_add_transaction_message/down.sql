@@ -0,0 +1,12 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS transaction_address_table_lookups;
+DROP TABLE IF EXISTS transaction_instructions;
+
+ALTER TABLE transactions
+    DROP COLUMN IF EXISTS signatures,
+    DROP COLUMN IF EXISTS versioned,
+    DROP COLUMN IF EXISTS num_required_signatures,
+    DROP COLUMN IF EXISTS num_readonly_signed_accounts,
+    DROP COLUMN IF EXISTS num_readonly_unsigned_accounts,
+    DROP COLUMN IF EXISTS account_keys,
+    DROP COLUMN IF EXISTS recent_blockhash;
@@ -0,0 +1,33 @@
+-- Your SQL goes here
+ALTER TABLE transactions
+    ADD COLUMN signatures BYTEA[] NOT NULL DEFAULT '{}',
+    ADD COLUMN versioned BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN num_required_signatures INT,
+    ADD COLUMN num_readonly_signed_accounts INT,
+    ADD COLUMN num_readonly_unsigned_accounts INT,
+    ADD COLUMN account_keys BYTEA[] NOT NULL DEFAULT '{}',
+    ADD COLUMN recent_blockhash BYTEA;
+
+CREATE TABLE transaction_instructions (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    -- position of the instruction within the message
+    idx INT NOT NULL,
+    program_id_index INT NOT NULL,
+    -- resolved from account_keys at insert time
+    program_id BYTEA,
+    accounts BYTEA NOT NULL,
+    data BYTEA NOT NULL
+);
+
+CREATE TABLE transaction_address_table_lookups (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    account_key BYTEA NOT NULL,
+    writable_indexes BYTEA NOT NULL,
+    readonly_indexes BYTEA NOT NULL
+);
+
+CREATE INDEX idx_ti_transaction_id ON transaction_instructions(transaction_id);
+CREATE INDEX idx_ti_program_id ON transaction_instructions(program_id);
+CREATE INDEX idx_tatl_transaction_id ON transaction_address_table_lookups(transaction_id);
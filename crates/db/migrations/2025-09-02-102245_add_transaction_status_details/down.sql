@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS transaction_rewards;
+DROP TABLE IF EXISTS transaction_inner_instructions;
+DROP INDEX IF EXISTS idx_transactions_failed;
+
+ALTER TABLE transactions
+    DROP COLUMN IF EXISTS err,
+    DROP COLUMN IF EXISTS loaded_writable_addresses,
+    DROP COLUMN IF EXISTS loaded_readonly_addresses,
+    DROP COLUMN IF EXISTS return_data_program_id,
+    DROP COLUMN IF EXISTS return_data;
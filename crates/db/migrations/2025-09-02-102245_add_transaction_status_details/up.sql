@@ -0,0 +1,38 @@
+-- Your SQL goes here
+ALTER TABLE transactions
+    -- decoded error, NULL for successful transactions
+    ADD COLUMN err TEXT,
+    ADD COLUMN loaded_writable_addresses BYTEA[] NOT NULL DEFAULT '{}',
+    ADD COLUMN loaded_readonly_addresses BYTEA[] NOT NULL DEFAULT '{}',
+    ADD COLUMN return_data_program_id BYTEA,
+    ADD COLUMN return_data BYTEA;
+
+CREATE TABLE transaction_inner_instructions (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    -- index of the top level instruction which made the CPI
+    instruction_idx INT NOT NULL,
+    -- position among the inner instructions of that instruction
+    idx INT NOT NULL,
+    program_id_index INT NOT NULL,
+    -- resolved against the static and loaded account keys at insert time
+    program_id BYTEA,
+    accounts BYTEA NOT NULL,
+    data BYTEA NOT NULL,
+    stack_height INT
+);
+
+CREATE TABLE transaction_rewards (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    pubkey TEXT NOT NULL,
+    lamports BIGINT NOT NULL,
+    post_balance BIGINT NOT NULL,
+    reward_type TEXT NOT NULL CHECK (reward_type IN ('unspecified', 'fee', 'rent', 'staking', 'voting')),
+    commission SMALLINT
+);
+
+CREATE INDEX idx_transactions_failed ON transactions(slot) WHERE err IS NOT NULL;
+CREATE INDEX idx_tii_transaction_id ON transaction_inner_instructions(transaction_id);
+CREATE INDEX idx_tii_program_id ON transaction_inner_instructions(program_id);
+CREATE INDEX idx_tr_transaction_id ON transaction_rewards(transaction_id);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+-- fails while an amount above the BIGINT range is stored
+ALTER TABLE transactions
+    ALTER COLUMN pre_token_balances TYPE BIGINT[],
+    ALTER COLUMN post_token_balances TYPE BIGINT[];
+
+ALTER TABLE transaction_token_balances ALTER COLUMN amount TYPE BIGINT;
+
+ALTER TABLE transaction_token_balances DROP COLUMN IF EXISTS decimals;
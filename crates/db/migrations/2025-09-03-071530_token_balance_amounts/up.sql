@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE transaction_token_balances ADD COLUMN decimals INT;
+
+-- raw token amounts are u64, which doesn't fit a BIGINT
+ALTER TABLE transaction_token_balances ALTER COLUMN amount TYPE NUMERIC(20, 0);
+
+ALTER TABLE transactions
+    ALTER COLUMN pre_token_balances TYPE NUMERIC(20, 0)[],
+    ALTER COLUMN post_token_balances TYPE NUMERIC(20, 0)[];
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_slots_slot;
+DROP INDEX IF EXISTS idx_accounts_pubkey;
+DROP TABLE IF EXISTS transaction_statuses;
+DROP TABLE IF EXISTS entries;
+DROP TABLE IF EXISTS blocks;
@@ -0,0 +1,36 @@
+-- Your SQL goes here
+CREATE TABLE blocks (
+    slot BIGINT PRIMARY KEY,
+    blockhash TEXT NOT NULL,
+    parent_slot BIGINT NOT NULL,
+    parent_blockhash TEXT NOT NULL,
+    executed_transaction_count BIGINT NOT NULL,
+    updated_account_count BIGINT NOT NULL,
+    entries_count BIGINT NOT NULL
+);
+
+CREATE TABLE entries (
+    id BIGSERIAL PRIMARY KEY,
+    slot BIGINT NOT NULL,
+    idx BIGINT NOT NULL,
+    num_hashes BIGINT NOT NULL,
+    hash BYTEA NOT NULL,
+    executed_transaction_count BIGINT NOT NULL,
+    starting_transaction_index BIGINT NOT NULL,
+    UNIQUE (slot, idx)
+);
+
+CREATE TABLE transaction_statuses (
+    id BIGSERIAL PRIMARY KEY,
+    slot BIGINT NOT NULL,
+    signature BYTEA NOT NULL,
+    is_vote BOOLEAN NOT NULL,
+    idx INT NOT NULL,
+    err TEXT,
+    -- statuses redelivered by the stream are skipped on this key
+    UNIQUE (slot, signature)
+);
+
+CREATE INDEX idx_transaction_statuses_signature ON transaction_statuses(signature);
+CREATE INDEX idx_accounts_pubkey ON accounts(pubkey);
+CREATE INDEX idx_slots_slot ON slots(slot);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transaction_token_balances DROP CONSTRAINT IF EXISTS ttb_transaction_account_balance_type_key;
+ALTER TABLE accounts DROP CONSTRAINT IF EXISTS accounts_pubkey_slot_write_version_key;
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_slot_signature_key;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- rows redelivered by the stream are merged with ON CONFLICT on these keys,
+-- so drop duplicates which may already be there first
+DELETE FROM transactions a
+    USING transactions b
+    WHERE a.id > b.id AND a.slot = b.slot AND a.signature = b.signature;
+
+DELETE FROM accounts a
+    USING accounts b
+    WHERE a.id > b.id
+        AND a.pubkey = b.pubkey
+        AND a.slot = b.slot
+        AND a.write_version = b.write_version;
+
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_slot_signature_key UNIQUE (slot, signature);
+
+ALTER TABLE accounts
+    ADD CONSTRAINT accounts_pubkey_slot_write_version_key UNIQUE (pubkey, slot, write_version);
+
+ALTER TABLE transaction_token_balances
+    ADD CONSTRAINT ttb_transaction_account_balance_type_key
+    UNIQUE (transaction_id, account_index, balance_type);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- latest known state of every account, the append-only accounts table
+-- keeps the history of every write
+CREATE TABLE account_state (
+    pubkey BYTEA PRIMARY KEY,
+    slot BIGINT NOT NULL,
+    is_startup BOOLEAN NOT NULL,
+    lamports BIGINT NOT NULL,
+    owner BYTEA NOT NULL,
+    executable BOOLEAN NOT NULL,
+    rent_epoch BIGINT NOT NULL,
+    data BYTEA NOT NULL,
+    write_version BIGINT NOT NULL,
+    txn_signature BYTEA
+);
+
+CREATE INDEX idx_account_state_owner ON account_state(owner);
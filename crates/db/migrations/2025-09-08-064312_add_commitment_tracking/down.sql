@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_account_state_slot;
+DROP INDEX IF EXISTS idx_accounts_slot;
+
+ALTER TABLE account_state DROP COLUMN IF EXISTS commitment;
+
+ALTER TABLE accounts
+    DROP COLUMN IF EXISTS orphaned,
+    DROP COLUMN IF EXISTS commitment;
+
+ALTER TABLE transactions
+    DROP COLUMN IF EXISTS orphaned,
+    DROP COLUMN IF EXISTS commitment;
+
+DROP TABLE IF EXISTS slot_commitments;
+
+ALTER TABLE slots DROP CONSTRAINT IF EXISTS slots_slot_status_key;
@@ -0,0 +1,40 @@
+-- Your SQL goes here
+-- the slots table logs every status a slot went through, once per status
+DELETE FROM slots a
+    USING slots b
+    WHERE a.id > b.id AND a.slot = b.slot AND a.status = b.status;
+
+ALTER TABLE slots ADD CONSTRAINT slots_slot_status_key UNIQUE (slot, status);
+
+-- current view of the fork tracker on every slot it heard of. a slot is
+-- orphaned when it died or a later slot was finalized on another fork
+CREATE TABLE slot_commitments (
+    slot BIGINT PRIMARY KEY,
+    parent BIGINT,
+    commitment TEXT NOT NULL DEFAULT 'processed'
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized')),
+    orphaned BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_slot_commitments_pending ON slot_commitments(slot)
+    WHERE commitment <> 'finalized' AND NOT orphaned;
+
+-- commitment of the slot a row was written in, so queries can ask for
+-- finalized data only
+ALTER TABLE transactions
+    ADD COLUMN commitment TEXT NOT NULL DEFAULT 'processed'
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized')),
+    ADD COLUMN orphaned BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE accounts
+    ADD COLUMN commitment TEXT NOT NULL DEFAULT 'processed'
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized')),
+    ADD COLUMN orphaned BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- orphaned writes are rolled back in account_state, so it needs no flag
+ALTER TABLE account_state
+    ADD COLUMN commitment TEXT NOT NULL DEFAULT 'processed'
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized'));
+
+CREATE INDEX idx_accounts_slot ON accounts(slot);
+CREATE INDEX idx_account_state_slot ON account_state(slot);
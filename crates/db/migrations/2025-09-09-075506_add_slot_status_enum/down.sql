@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE slots
+    ALTER COLUMN status TYPE INT USING (
+        CASE status
+            WHEN 'processed' THEN 0
+            WHEN 'confirmed' THEN 1
+            WHEN 'finalized' THEN 2
+            WHEN 'first_shred_received' THEN 3
+            WHEN 'completed' THEN 4
+            WHEN 'created_bank' THEN 5
+            WHEN 'dead' THEN 6
+        END
+    );
+
+DROP TYPE IF EXISTS slot_status;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TYPE slot_status AS ENUM (
+    'processed',
+    'confirmed',
+    'finalized',
+    'first_shred_received',
+    'completed',
+    'created_bank',
+    'dead'
+);
+
+-- the raw values follow the numbering of the geyser SlotStatus enum
+ALTER TABLE slots
+    ALTER COLUMN status TYPE slot_status USING (
+        CASE status
+            WHEN 0 THEN 'processed'
+            WHEN 1 THEN 'confirmed'
+            WHEN 2 THEN 'finalized'
+            WHEN 3 THEN 'first_shred_received'
+            WHEN 4 THEN 'completed'
+            WHEN 5 THEN 'created_bank'
+            WHEN 6 THEN 'dead'
+        END
+    )::slot_status;
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_blocks_block_time;
+
+UPDATE blocks SET updated_account_count = 0 WHERE updated_account_count IS NULL;
+
+ALTER TABLE blocks
+    ALTER COLUMN updated_account_count SET NOT NULL,
+    DROP COLUMN IF EXISTS block_height,
+    DROP COLUMN IF EXISTS block_time;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- blocks are filled from both full block updates and block meta updates,
+-- the account count only comes with full blocks
+ALTER TABLE blocks
+    ADD COLUMN block_time BIGINT,
+    ADD COLUMN block_height BIGINT,
+    ALTER COLUMN updated_account_count DROP NOT NULL;
+
+CREATE INDEX idx_blocks_block_time ON blocks(block_time);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS rewards;
+DROP TYPE IF EXISTS reward_type;
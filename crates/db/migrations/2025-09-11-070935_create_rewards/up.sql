@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TYPE reward_type AS ENUM (
+    'unspecified',
+    'fee',
+    'rent',
+    'staking',
+    'voting'
+);
+
+-- block level rewards, one row per credited account and slot. full blocks
+-- and block metas carry the same rewards, whichever comes first writes them
+CREATE TABLE rewards (
+    slot BIGINT NOT NULL,
+    pubkey TEXT NOT NULL,
+    lamports BIGINT NOT NULL,
+    post_balance BIGINT NOT NULL,
+    reward_type reward_type NOT NULL,
+    commission SMALLINT,
+    PRIMARY KEY (slot, pubkey)
+);
+
+CREATE INDEX idx_rewards_pubkey ON rewards(pubkey, slot);
+CREATE INDEX idx_rewards_reward_type ON rewards(reward_type);
@@ -0,0 +1,49 @@
+-- This file should undo anything in `up.sql`
+-- only one reward per account and slot fits the old key
+DELETE FROM rewards a
+    USING rewards b
+    WHERE a.slot = b.slot AND a.pubkey = b.pubkey AND a.reward_type > b.reward_type;
+
+ALTER TABLE rewards
+    DROP CONSTRAINT rewards_pkey,
+    ADD PRIMARY KEY (slot, pubkey);
+
+ALTER TABLE transaction_rewards
+    ALTER COLUMN reward_type TYPE TEXT USING reward_type::text,
+    ADD CONSTRAINT transaction_rewards_reward_type_check
+        CHECK (reward_type IN ('unspecified', 'fee', 'rent', 'staking', 'voting'));
+
+DROP INDEX IF EXISTS idx_slot_commitments_pending;
+
+ALTER TABLE account_state
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE TEXT USING commitment::text,
+    ALTER COLUMN commitment SET DEFAULT 'processed',
+    ADD CONSTRAINT account_state_commitment_check
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized'));
+
+ALTER TABLE accounts
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE TEXT USING commitment::text,
+    ALTER COLUMN commitment SET DEFAULT 'processed',
+    ADD CONSTRAINT accounts_commitment_check
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized'));
+
+ALTER TABLE transactions
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE TEXT USING commitment::text,
+    ALTER COLUMN commitment SET DEFAULT 'processed',
+    ADD CONSTRAINT transactions_commitment_check
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized'));
+
+ALTER TABLE slot_commitments
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE TEXT USING commitment::text,
+    ALTER COLUMN commitment SET DEFAULT 'processed',
+    ADD CONSTRAINT slot_commitments_commitment_check
+        CHECK (commitment IN ('processed', 'confirmed', 'finalized'));
+
+CREATE INDEX idx_slot_commitments_pending ON slot_commitments(slot)
+    WHERE commitment <> 'finalized' AND NOT orphaned;
+
+DROP TYPE IF EXISTS commitment;
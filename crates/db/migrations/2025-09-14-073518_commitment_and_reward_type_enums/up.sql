@@ -0,0 +1,47 @@
+-- Your SQL goes here
+-- commitments and the reward types of transactions are postgres enums like
+-- the slot statuses and block rewards, instead of text checked against a list
+CREATE TYPE commitment AS ENUM (
+    'processed',
+    'confirmed',
+    'finalized'
+);
+
+-- the predicate compares with text, it is created again below
+DROP INDEX IF EXISTS idx_slot_commitments_pending;
+
+ALTER TABLE slot_commitments
+    DROP CONSTRAINT slot_commitments_commitment_check,
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE commitment USING commitment::commitment,
+    ALTER COLUMN commitment SET DEFAULT 'processed';
+
+ALTER TABLE transactions
+    DROP CONSTRAINT transactions_commitment_check,
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE commitment USING commitment::commitment,
+    ALTER COLUMN commitment SET DEFAULT 'processed';
+
+ALTER TABLE accounts
+    DROP CONSTRAINT accounts_commitment_check,
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE commitment USING commitment::commitment,
+    ALTER COLUMN commitment SET DEFAULT 'processed';
+
+ALTER TABLE account_state
+    DROP CONSTRAINT account_state_commitment_check,
+    ALTER COLUMN commitment DROP DEFAULT,
+    ALTER COLUMN commitment TYPE commitment USING commitment::commitment,
+    ALTER COLUMN commitment SET DEFAULT 'processed';
+
+CREATE INDEX idx_slot_commitments_pending ON slot_commitments(slot)
+    WHERE commitment <> 'finalized' AND NOT orphaned;
+
+ALTER TABLE transaction_rewards
+    DROP CONSTRAINT transaction_rewards_reward_type_check,
+    ALTER COLUMN reward_type TYPE reward_type USING reward_type::reward_type;
+
+-- an account can be credited rewards of several types in the same block
+ALTER TABLE rewards
+    DROP CONSTRAINT rewards_pkey,
+    ADD PRIMARY KEY (slot, pubkey, reward_type);
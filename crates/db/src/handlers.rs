@@ -0,0 +1,278 @@
+use anyhow::Result;
+use core::{
+    AccountUpdate, BlockMetaUpdate, BlockUpdate, EntryUpdate, SlotUpdate, TransactionStatusUpdate,
+    TransactionUpdate, TransactionUpdateInfo,
+};
+
+use crate::{
+    models::{
+        NewAccount, NewAccountState, NewBlock, NewEntry, NewReward, NewSlot, NewTransaction,
+        NewTransactionStatus, TokenAmount,
+    },
+    store::Store,
+};
+
+pub fn handle_transactions(store: &Store, updates: Vec<TransactionUpdate>) -> Result<()> {
+    // extract transaction info if present
+    let txs: Vec<(u64, TransactionUpdateInfo)> = updates
+        .into_iter()
+        .filter_map(|update| match update.transaction {
+            Some(info) => Some((update.slot, info)),
+            None => {
+                println!("⚠️ No transaction info found");
+                None
+            }
+        })
+        .collect();
+
+    store_transactions(store, txs.iter().map(|(slot, info)| (*slot, info)))
+}
+
+pub fn handle_accounts(store: &Store, updates: Vec<AccountUpdate>) -> Result<()> {
+    store_accounts(store, &updates)
+}
+
+pub fn handle_slots(store: &Store, updates: Vec<SlotUpdate>) -> Result<()> {
+    let new_slots: Vec<NewSlot> = updates
+        .into_iter()
+        .map(|slot| {
+            NewSlot::new(
+                slot.slot as i64,
+                slot.parent.map(|p| p as i64),
+                slot.status,
+                slot.dead_error,
+            )
+        })
+        .collect();
+    store.insert_slots(&new_slots)?;
+
+    // move the commitment of everything written in these slots along
+    let changed = store.track_slots(&new_slots)?;
+    if changed > 0 {
+        println!("🔀 Commitment of {} slot(s) changed", changed);
+    }
+    Ok(())
+}
+
+// stores the blocks along with whatever transactions, accounts and entries
+// the block filter asked to be included
+pub fn handle_blocks(store: &Store, updates: Vec<BlockUpdate>) -> Result<()> {
+    let mut new_blocks = Vec::with_capacity(updates.len());
+    let mut accounts = vec![];
+    let mut entries = vec![];
+    let mut rewards = vec![];
+
+    for block in &updates {
+        println!(
+            "📦 Block update: slot={}, transactions={}, accounts={}, entries={}",
+            block.slot,
+            block.transactions.len(),
+            block.accounts.len(),
+            block.entries.len()
+        );
+
+        new_blocks.push(NewBlock::new(
+            block.slot as i64,
+            block.blockhash.clone(),
+            block.parent_slot as i64,
+            block.parent_blockhash.clone(),
+            block.executed_transaction_count as i64,
+            Some(block.updated_account_count as i64),
+            block.entries_count as i64,
+            block.block_time,
+            block.block_height.map(|h| h as i64),
+        ));
+        accounts.extend(block.accounts.iter().map(|info| AccountUpdate {
+            slot: block.slot,
+            is_startup: false,
+            info: info.clone(),
+        }));
+        entries.extend(block.entries.iter().cloned().map(new_entry));
+        rewards.extend(NewReward::from_block(block.slot, &block.rewards));
+    }
+
+    store.insert_blocks(&new_blocks)?;
+    store.insert_rewards(&rewards)?;
+    store_transactions(
+        store,
+        updates
+            .iter()
+            .flat_map(|block| block.transactions.iter().map(|info| (block.slot, info))),
+    )?;
+    store_accounts(store, &accounts)?;
+    store.insert_entries(&entries)?;
+
+    println!("✅ Successfully inserted {} block(s)", new_blocks.len());
+    Ok(())
+}
+
+// block metas carry the header of a block without its contents, they fill
+// the same rows as full block updates
+pub fn handle_blocks_meta(store: &Store, updates: Vec<BlockMetaUpdate>) -> Result<()> {
+    let rewards: Vec<NewReward> = updates
+        .iter()
+        .flat_map(|meta| NewReward::from_block(meta.slot, &meta.rewards))
+        .collect();
+    let new_blocks: Vec<NewBlock> = updates
+        .into_iter()
+        .map(|meta| {
+            NewBlock::new(
+                meta.slot as i64,
+                meta.blockhash,
+                meta.parent_slot as i64,
+                meta.parent_blockhash,
+                meta.executed_transaction_count as i64,
+                None,
+                meta.entries_count as i64,
+                meta.block_time,
+                meta.block_height.map(|h| h as i64),
+            )
+        })
+        .collect();
+    let count = store.insert_blocks(&new_blocks)?;
+    store.insert_rewards(&rewards)?;
+    println!("✅ Successfully upserted {} block meta(s)", count);
+    Ok(())
+}
+
+pub fn handle_entries(store: &Store, updates: Vec<EntryUpdate>) -> Result<()> {
+    let entries: Vec<NewEntry> = updates.into_iter().map(new_entry).collect();
+    store.insert_entries(&entries)?;
+    Ok(())
+}
+
+pub fn handle_transaction_statuses(
+    store: &Store,
+    updates: Vec<TransactionStatusUpdate>,
+) -> Result<()> {
+    let statuses: Vec<NewTransactionStatus> = updates
+        .into_iter()
+        .map(|status| {
+            NewTransactionStatus::new(
+                status.slot as i64,
+                status.signature,
+                status.is_vote,
+                status.index as i32,
+                status.err,
+            )
+        })
+        .collect();
+    store.insert_transaction_statuses(&statuses)?;
+    Ok(())
+}
+
+// inserts transactions with everything attached to them, used for both
+// transaction updates and the transactions of blocks
+fn store_transactions<'a>(
+    store: &Store,
+    txs: impl Iterator<Item = (u64, &'a TransactionUpdateInfo)>,
+) -> Result<()> {
+    let new_txs: Vec<(NewTransaction, &TransactionUpdateInfo)> = txs
+        .filter_map(|(slot, tx_info)| new_transaction(slot, tx_info).map(|tx| (tx, tx_info)))
+        .collect();
+
+    // copy the new transactions along with their messages into the database,
+    // transactions which were delivered before are skipped
+    println!(
+        "💾 Inserting {} transaction(s) into database...",
+        new_txs.len()
+    );
+    match store.copy_full_transactions(&new_txs) {
+        Ok(count) => println!("✅ Successfully inserted {} transaction(s)", count),
+        Err(e) => {
+            eprintln!("❌ Failed to insert transactions: {}", e);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+fn new_transaction(slot: u64, tx_info: &TransactionUpdateInfo) -> Option<NewTransaction> {
+    println!(
+        "📝 Transaction info found: slot={}, index={}",
+        slot, tx_info.index
+    );
+
+    // extract meta if present
+    let Some(meta) = &tx_info.meta else {
+        println!("⚠️ No meta found for transaction");
+        return None;
+    };
+
+    if let Some(err) = &meta.err {
+        println!("⚠️ Transaction failed: {}", err);
+    }
+
+    // convert to NewTransaction format by mapping the fields
+    let new_tx = NewTransaction::new(
+        slot as i64,
+        tx_info.signature.clone(),
+        tx_info.is_vote,
+        tx_info.index as i32,
+        Some(meta.fee as i64),
+        meta.compute_units_consumed.map(|u| u as i64),
+        Some(meta.pre_balances.iter().map(|b| Some(*b as i64)).collect()),
+        Some(meta.post_balances.iter().map(|b| Some(*b as i64)).collect()),
+        Some(meta.log_messages.iter().cloned().map(Some).collect()),
+        Some(
+            meta.pre_token_balance
+                .iter()
+                .map(|tb| Some(TokenAmount(tb.amount)))
+                .collect(),
+        ),
+        Some(
+            meta.post_token_balance
+                .iter()
+                .map(|tb| Some(TokenAmount(tb.amount)))
+                .collect(),
+        ),
+    )
+    .with_message(tx_info.signatures.clone(), tx_info.message.as_ref())
+    .with_status(meta);
+
+    Some(new_tx)
+}
+
+// moves the latest state of the accounts forward and, unless switched off,
+// appends every write to the account history
+fn store_accounts(store: &Store, updates: &[AccountUpdate]) -> Result<()> {
+    let states: Vec<NewAccountState> = updates.iter().map(NewAccountState::from).collect();
+    let count = store.upsert_account_states(&states)?;
+    println!("✅ Successfully updated {} account state(s)", count);
+
+    if store.account_history {
+        let accounts: Vec<NewAccount> = updates.iter().map(new_account).collect();
+        let count = store.copy_accounts(&accounts)?;
+        println!("✅ Successfully inserted {} account(s) into history", count);
+    }
+
+    Ok(())
+}
+
+fn new_account(update: &AccountUpdate) -> NewAccount {
+    let info = &update.info;
+    NewAccount::new(
+        update.slot as i64,
+        update.is_startup,
+        info.pubkey.clone(),
+        info.lamports as i64,
+        info.owner.clone(),
+        info.executable,
+        info.rent_epoch as i64,
+        info.data.clone(),
+        info.write_version as i64,
+        info.txn_signature.clone(),
+    )
+}
+
+fn new_entry(entry: EntryUpdate) -> NewEntry {
+    NewEntry::new(
+        entry.slot as i64,
+        entry.index as i64,
+        entry.num_hashes as i64,
+        entry.hash,
+        entry.executed_transaction_count as i64,
+        entry.starting_transaction_index as i64,
+    )
+}
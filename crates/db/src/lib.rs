@@ -1,110 +1,172 @@
+use std::time::Duration;
+
 use anyhow::Result;
-use core::TransactionUpdate;
-use models::NewTransaction;
-use redis_adapter::{Consumer, Redis};
+use core::{
+    CH_ACCOUNTS, CH_BLOCKS, CH_BLOCKS_META, CH_ENTRIES, CH_SLOTS, CH_TRANSACTION_STATUS,
+    CH_TRANSACTIONS, WirePayload, wire,
+};
+use diesel::{
+    ConnectionError,
+    r2d2::PoolError,
+    result::{DatabaseErrorKind, Error as DieselError},
+};
+use redis_adapter::{Consumer, Redis, Unavailable};
 use store::Store;
+mod handlers;
 pub mod models;
 mod schema;
 pub mod store;
 
-pub async fn run_consumer(store: Store, redis_url: &str, channel: &str) -> Result<()> {
-    let redis = Redis::new(redis_url)?;
+/// How the consumers group messages into multi-row inserts
+#[derive(Clone, Copy, Debug)]
+pub struct BatchConfig {
+    /// rows flushed per insert at most
+    pub max_items: usize,
+    /// how long a batch waits to fill up after its first message
+    pub flush_interval: Duration,
+}
 
-    // Consume from the "transactions" channel
-    let store_for_handler = store.clone();
-    redis
-        .consumer(channel, move |msg| {
-            println!(
-                "📨 Received message from Redis: {}",
-                &msg[..msg.len().min(100)]
-            );
+// runs the consumer matching one of the channels published by the geyser adapter
+pub async fn run_consumer(
+    store: Store,
+    redis: Redis,
+    channel: &str,
+    batch: BatchConfig,
+) -> Result<()> {
+    match channel {
+        CH_TRANSACTIONS => {
+            consume(store, &redis, channel, batch, handlers::handle_transactions).await?
+        }
+        CH_ACCOUNTS => consume(store, &redis, channel, batch, handlers::handle_accounts).await?,
+        CH_SLOTS => consume(store, &redis, channel, batch, handlers::handle_slots).await?,
+        CH_BLOCKS => consume(store, &redis, channel, batch, handlers::handle_blocks).await?,
+        CH_BLOCKS_META => {
+            consume(store, &redis, channel, batch, handlers::handle_blocks_meta).await?
+        }
+        CH_ENTRIES => consume(store, &redis, channel, batch, handlers::handle_entries).await?,
+        CH_TRANSACTION_STATUS => {
+            consume(
+                store,
+                &redis,
+                channel,
+                batch,
+                handlers::handle_transaction_statuses,
+            )
+            .await?
+        }
+        _ => anyhow::bail!("No consumer for channel '{}'", channel),
+    }
 
-            // deserialize the incoming message into TransactionUpdate from geyser
-            let tx_update: TransactionUpdate = match serde_json::from_str(&msg) {
-                Ok(tx) => {
-                    println!("✅ Successfully deserialized TransactionUpdate");
-                    tx
-                }
-                Err(e) => {
-                    eprintln!("❌ Failed to deserialize message: {}", e);
-                    eprintln!("Message content: {}", msg);
-                    return Err(anyhow::anyhow!("Deserialization failed: {}", e));
-                }
-            };
+    println!("🔄 Consumer for '{}' stopped", channel);
+    Ok(())
+}
 
-            // extract transaction info if present
-            if let Some(tx_info) = tx_update.transaction {
-                println!(
-                    "📝 Transaction info found: slot={}, index={}",
-                    tx_update.slot, tx_info.index
-                );
+// decodes every batch of messages of the channel and passes it on to the
+// handler, the messages are only acknowledged once the handler committed them
+async fn consume<T, F>(
+    store: Store,
+    redis: &Redis,
+    channel: &str,
+    batch: BatchConfig,
+    handler: F,
+) -> Result<()>
+where
+    T: WirePayload + 'static,
+    F: Fn(&Store, Vec<T>) -> Result<()> + Copy + Send + 'static,
+{
+    let channel_name = channel.to_owned();
 
-                // extract meta if present
-                if let Some(meta) = tx_info.meta {
+    redis
+        .consumer_batch(
+            channel,
+            batch.max_items,
+            batch.flush_interval,
+            move |msgs| {
+                let store = store.clone();
+                let channel_name = channel_name.clone();
+                // diesel blocks, so the batch is written off the async workers
+                let handled = tokio::task::spawn_blocking(move || {
                     println!(
-                        "🔍 Meta found: fee={}, pre_balances={}",
-                        meta.fee,
-                        meta.pre_balances.len()
-                    );
-
-                    // convert to NewTransaction format by mapping the fields
-                    let new_tx = NewTransaction::new(
-                        tx_update.slot as i64,
-                        tx_info.signature.clone(),
-                        tx_info.is_vote,
-                        tx_info.index as i32,
-                        Some(meta.fee as i64),
-                        meta.compute_units_consumed.map(|u| u as i64),
-                        Some(
-                            meta.pre_balances
-                                .into_iter()
-                                .map(|b| Some(b as i64))
-                                .collect(),
-                        ),
-                        Some(
-                            meta.post_balances
-                                .into_iter()
-                                .map(|b| Some(b as i64))
-                                .collect(),
-                        ),
-                        Some(meta.log_messages.into_iter().map(|m| Some(m)).collect()),
-                        Some(
-                            meta.pre_token_balance
-                                .into_iter()
-                                .map(|_| Some(0i64))
-                                .collect(),
-                        ), // Placeholder for now
-                        Some(
-                            meta.post_token_balance
-                                .into_iter()
-                                .map(|_| Some(0i64))
-                                .collect(),
-                        ),
+                        "📨 Received {} message(s) from Redis on '{}'",
+                        msgs.len(),
+                        channel_name
                     );
 
-                    // insert the new transaction into the database
-                    println!("💾 Inserting transaction into database...");
-                    match store_for_handler.insert_transaction(&[new_tx]) {
-                        Ok(count) => println!("✅ Successfully inserted {} transaction(s)", count),
-                        Err(e) => {
-                            eprintln!("❌ Failed to insert transaction: {}", e);
-                            return Err(e);
+                    // decode the incoming messages into the update type of the channel,
+                    // whatever codec the adapter published them with
+                    let mut updates: Vec<T> = Vec::with_capacity(msgs.len());
+                    for msg in msgs {
+                        match wire::decode::<T>(&msg) {
+                            Ok(envelope) => updates.push(envelope.update),
+                            Err(e) => {
+                                eprintln!("❌ Failed to decode message: {}", e);
+                                eprintln!("Message content: {}", String::from_utf8_lossy(&msg));
+                                return Err(anyhow::anyhow!("Decoding failed: {}", e));
+                            }
                         }
                     }
-                } else {
-                    println!("⚠️ No meta found for transaction");
-                }
-            } else {
-                println!("⚠️ No transaction info found");
-            }
 
-            Ok(())
-        })
-        .await?;
+                    handler(&store, updates).map_err(unavailable_database)
+                });
+                async move { handled.await? }
+            },
+        )
+        .await
+}
 
-    // need to implement consumers for the other channels
-    // as for my current rpc not getting their data i'll impl them later
+// failures of the database itself say nothing about the messages, they are
+// marked so the messages stay pending until the database is back
+fn unavailable_database(e: anyhow::Error) -> anyhow::Error {
+    let unavailable = e.chain().any(|cause| {
+        cause.is::<PoolError>()
+            || cause.is::<ConnectionError>()
+            || matches!(
+                cause.downcast_ref::<DieselError>(),
+                Some(DieselError::DatabaseError(
+                    DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::UnableToSendCommand,
+                    _
+                ))
+            )
+    });
+    if unavailable {
+        Unavailable(e).into()
+    } else {
+        e
+    }
+}
 
-    println!("🔄 Consumer stopped");
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn database_error(kind: DatabaseErrorKind) -> anyhow::Error {
+        Err::<(), _>(DieselError::DatabaseError(
+            kind,
+            Box::new("boom".to_owned()),
+        ))
+        .context("Failed to insert transactions")
+        .unwrap_err()
+    }
+
+    #[test]
+    fn lost_connections_are_unavailable() {
+        for kind in [
+            DatabaseErrorKind::ClosedConnection,
+            DatabaseErrorKind::UnableToSendCommand,
+        ] {
+            assert!(unavailable_database(database_error(kind)).is::<Unavailable>());
+        }
+        let refused = anyhow::Error::new(ConnectionError::BadConnection("refused".to_owned()))
+            .context("Failed to get DB connection from pool");
+        assert!(unavailable_database(refused).is::<Unavailable>());
+    }
+
+    #[test]
+    fn errors_of_the_data_are_kept() {
+        let violation = unavailable_database(database_error(DatabaseErrorKind::CheckViolation));
+        assert!(!violation.is::<Unavailable>());
+        let decoding = unavailable_database(anyhow::anyhow!("Decoding failed"));
+        assert!(!decoding.is::<Unavailable>());
+    }
 }
@@ -1,17 +1,24 @@
 use anyhow::Result;
 use config::CONFIG;
-use db::{run_consumer, store::Store};
+use core::{
+    CH_ACCOUNTS, CH_BLOCKS, CH_BLOCKS_META, CH_ENTRIES, CH_SLOTS, CH_TRANSACTION_STATUS,
+    CH_TRANSACTIONS,
+};
+use db::{BatchConfig, run_consumer, store::Store};
 use diesel::{
     pg::PgConnection,
     r2d2::{ConnectionManager, Pool},
 };
+use redis_adapter::{Redis, StreamConfig};
+use std::time::Duration;
+use tokio::task::JoinSet;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-
     // get the required configurations
     let db_url = &CONFIG.db_url;
     let redis_url = &CONFIG.redis_url;
+    let switches = &CONFIG.consumers;
 
     println!("Starting DB consumer...");
     println!("Database URL: {}", db_url);
@@ -24,11 +31,50 @@ async fn main() -> Result<()> {
         .expect("Failed to create database connection pool");
 
     // create store with the pool
-    let store = Store::new(pool);
+    let store = Store::new(pool)
+        .with_account_history(CONFIG.db_account_history)
+        .with_orphan_deletion(CONFIG.db_delete_orphans);
+
+    // every db consumer joins the same group, so running several of them shares the load
+    let defaults = StreamConfig::default();
+    let redis = Redis::new(redis_url)?.with_stream_config(StreamConfig {
+        group: CONFIG.redis_group.clone().unwrap_or(defaults.group),
+        consumer: CONFIG.redis_consumer.clone().unwrap_or(defaults.consumer),
+        max_len: CONFIG.redis_stream_max_len.or(defaults.max_len),
+        ..defaults
+    });
+
+    // rows are flushed once a batch is full or its flush interval passed
+    let batch = BatchConfig {
+        max_items: CONFIG.db_batch_size,
+        flush_interval: Duration::from_millis(CONFIG.db_flush_interval_ms),
+    };
+
+    let channels = [
+        (CH_TRANSACTIONS, switches.transactions),
+        (CH_ACCOUNTS, switches.accounts),
+        (CH_SLOTS, switches.slots),
+        (CH_BLOCKS, switches.blocks),
+        (CH_BLOCKS_META, switches.blocks_meta),
+        (CH_ENTRIES, switches.entries),
+        (CH_TRANSACTION_STATUS, switches.transaction_status),
+    ];
+
+    // run a consumer per enabled channel concurrently
+    let mut consumers = JoinSet::new();
+    for (channel, enabled) in channels {
+        if !enabled {
+            println!("Consumer for '{}' channel is disabled", channel);
+            continue;
+        }
+        println!("Starting consumer for '{}' channel...", channel);
+        consumers.spawn(run_consumer(store.clone(), redis.clone(), channel, batch));
+    }
 
-    // run consumer for transactions channel
-    println!("Starting consumer for 'transactions' channel...");
-    run_consumer(store, &redis_url, "transactions").await?;
+    // a failing consumer stops the whole process
+    while let Some(result) = consumers.join_next().await {
+        result??;
+    }
 
     Ok(())
 }
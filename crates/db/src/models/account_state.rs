@@ -0,0 +1,61 @@
+use crate::{models::Commitment, schema::account_state};
+use core::AccountUpdate;
+use diesel::prelude::*;
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = account_state)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AccountState {
+    pub pubkey: Vec<u8>,
+    pub slot: i64,
+    pub is_startup: bool,
+    pub lamports: i64,
+    pub owner: Vec<u8>,
+    pub executable: bool,
+    pub rent_epoch: i64,
+    pub data: Vec<u8>,
+    pub write_version: i64,
+    pub txn_signature: Option<Vec<u8>>,
+    pub commitment: Commitment,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = account_state)]
+pub struct NewAccountState {
+    pub pubkey: Vec<u8>,
+    pub slot: i64,
+    pub is_startup: bool,
+    pub lamports: i64,
+    pub owner: Vec<u8>,
+    pub executable: bool,
+    pub rent_epoch: i64,
+    pub data: Vec<u8>,
+    pub write_version: i64,
+    pub txn_signature: Option<Vec<u8>>,
+}
+
+impl NewAccountState {
+    // writes are ordered by slot first, the write version only orders the
+    // writes within a slot
+    pub fn is_newer_than(&self, other: &NewAccountState) -> bool {
+        (self.slot, self.write_version) > (other.slot, other.write_version)
+    }
+}
+
+impl From<&AccountUpdate> for NewAccountState {
+    fn from(update: &AccountUpdate) -> Self {
+        let info = &update.info;
+        NewAccountState {
+            pubkey: info.pubkey.clone(),
+            slot: update.slot as i64,
+            is_startup: update.is_startup,
+            lamports: info.lamports as i64,
+            owner: info.owner.clone(),
+            executable: info.executable,
+            rent_epoch: info.rent_epoch as i64,
+            data: info.data.clone(),
+            write_version: info.write_version as i64,
+            txn_signature: info.txn_signature.clone(),
+        }
+    }
+}
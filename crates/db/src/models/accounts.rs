@@ -1,4 +1,4 @@
-use crate::schema::accounts;
+use crate::{models::Commitment, schema::accounts};
 use diesel::prelude::*;
 
 #[derive(Queryable, Selectable, Debug)]
@@ -16,10 +16,13 @@ pub struct Account {
     pub data: Vec<u8>,
     pub write_version: i64,
     pub txn_signature: Option<Vec<u8>>,
+    pub commitment: Commitment,
+    pub orphaned: bool,
 }
 
 #[derive(Insertable, Debug)]
 #[diesel(table_name = accounts)]
+#[diesel(treat_none_as_default_value = false)]
 pub struct NewAccount {
     pub slot: i64,
     pub is_startup: bool,
@@ -34,6 +37,7 @@ pub struct NewAccount {
 }
 
 impl NewAccount {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         slot: i64,
         is_startup: bool,
@@ -0,0 +1,63 @@
+use crate::schema::blocks;
+use diesel::prelude::*;
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = blocks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Block {
+    pub slot: i64,
+    pub blockhash: String,
+    pub parent_slot: i64,
+    pub parent_blockhash: String,
+    pub executed_transaction_count: i64,
+    /// only known from full block updates
+    pub updated_account_count: Option<i64>,
+    pub entries_count: i64,
+    /// unix timestamp of the block
+    pub block_time: Option<i64>,
+    pub block_height: Option<i64>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = blocks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewBlock {
+    pub slot: i64,
+    pub blockhash: String,
+    pub parent_slot: i64,
+    pub parent_blockhash: String,
+    pub executed_transaction_count: i64,
+    /// only known from full block updates
+    pub updated_account_count: Option<i64>,
+    pub entries_count: i64,
+    /// unix timestamp of the block
+    pub block_time: Option<i64>,
+    pub block_height: Option<i64>,
+}
+
+impl NewBlock {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        slot: i64,
+        blockhash: String,
+        parent_slot: i64,
+        parent_blockhash: String,
+        executed_transaction_count: i64,
+        updated_account_count: Option<i64>,
+        entries_count: i64,
+        block_time: Option<i64>,
+        block_height: Option<i64>,
+    ) -> Self {
+        NewBlock {
+            slot,
+            blockhash,
+            parent_slot,
+            parent_blockhash,
+            executed_transaction_count,
+            updated_account_count,
+            entries_count,
+            block_time,
+            block_height,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::schema::entries;
+use diesel::prelude::*;
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = entries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Entry {
+    pub id: i64,
+    pub slot: i64,
+    pub idx: i64,
+    pub num_hashes: i64,
+    pub hash: Vec<u8>,
+    pub executed_transaction_count: i64,
+    pub starting_transaction_index: i64,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = entries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewEntry {
+    pub slot: i64,
+    pub idx: i64,
+    pub num_hashes: i64,
+    pub hash: Vec<u8>,
+    pub executed_transaction_count: i64,
+    pub starting_transaction_index: i64,
+}
+
+impl NewEntry {
+    pub fn new(
+        slot: i64,
+        idx: i64,
+        num_hashes: i64,
+        hash: Vec<u8>,
+        executed_transaction_count: i64,
+        starting_transaction_index: i64,
+    ) -> Self {
+        NewEntry {
+            slot,
+            idx,
+            num_hashes,
+            hash,
+            executed_transaction_count,
+            starting_transaction_index,
+        }
+    }
+}
@@ -1,7 +1,17 @@
+pub mod account_state;
 pub mod accounts;
+pub mod blocks;
+pub mod entries;
+pub mod rewards;
 pub mod slots;
+pub mod transaction_statuses;
 pub mod transactions;
 
-// pub use accounts::*;
-// pub use slots::*;
+pub use account_state::*;
+pub use accounts::*;
+pub use blocks::*;
+pub use entries::*;
+pub use rewards::*;
+pub use slots::*;
+pub use transaction_statuses::*;
 pub use transactions::*;
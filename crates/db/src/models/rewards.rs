@@ -0,0 +1,95 @@
+use std::io::Write;
+
+use crate::schema::{rewards, sql_types};
+use core::{Reward as CoreReward, RewardType};
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, IsNull, Output, ToSql},
+};
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = rewards)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Reward {
+    pub slot: i64,
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: i64,
+    #[diesel(deserialize_as = PgRewardType)]
+    pub reward_type: RewardType,
+    pub commission: Option<i16>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = rewards)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewReward {
+    pub slot: i64,
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: i64,
+    #[diesel(serialize_as = PgRewardType)]
+    pub reward_type: RewardType,
+    pub commission: Option<i16>,
+}
+
+impl NewReward {
+    pub fn from_block(slot: u64, rewards: &[CoreReward]) -> Vec<Self> {
+        rewards
+            .iter()
+            .map(|reward| NewReward {
+                slot: slot as i64,
+                pubkey: reward.pubkey.clone(),
+                lamports: reward.lamports,
+                post_balance: reward.post_balance as i64,
+                reward_type: reward.reward_type,
+                commission: reward.commission.map(i16::from),
+            })
+            .collect()
+    }
+}
+
+/// Diesel mapping of the core `RewardType` to the `reward_type` postgres enum
+#[derive(AsExpression, FromSqlRow, Debug, Clone, Copy)]
+#[diesel(sql_type = sql_types::RewardType)]
+pub struct PgRewardType(pub RewardType);
+
+impl From<RewardType> for PgRewardType {
+    fn from(reward_type: RewardType) -> Self {
+        PgRewardType(reward_type)
+    }
+}
+
+impl From<PgRewardType> for RewardType {
+    fn from(reward_type: PgRewardType) -> Self {
+        reward_type.0
+    }
+}
+
+impl ToSql<sql_types::RewardType, Pg> for PgRewardType {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.0.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::RewardType, Pg> for PgRewardType {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let reward_type = match bytes.as_bytes() {
+            b"unspecified" => RewardType::Unspecified,
+            b"fee" => RewardType::Fee,
+            b"rent" => RewardType::Rent,
+            b"staking" => RewardType::Staking,
+            b"voting" => RewardType::Voting,
+            other => {
+                return Err(
+                    format!("Unknown reward type '{}'", String::from_utf8_lossy(other)).into(),
+                );
+            }
+        };
+        Ok(PgRewardType(reward_type))
+    }
+}
@@ -1,5 +1,14 @@
-use crate::schema::slots;
-use diesel::prelude::*;
+use std::io::Write;
+
+use crate::schema::{slot_commitments, slots, sql_types};
+use core::SlotStatus;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, IsNull, Output, ToSql},
+};
 
 #[derive(Queryable, Selectable, Debug)]
 #[diesel(table_name = slots)]
@@ -8,22 +17,29 @@ pub struct Slot {
     pub id: i32,
     pub slot: i64,
     pub parent: Option<i64>,
-    pub status: i32,
+    #[diesel(deserialize_as = PgSlotStatus)]
+    pub status: SlotStatus,
     pub dead_error: Option<String>,
 }
 
-#[derive(Insertable, Debug)]
+#[derive(Insertable, Debug, Clone)]
 #[diesel(table_name = slots)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewSlot {
     pub slot: i64,
     pub parent: Option<i64>,
-    pub status: i32,
+    #[diesel(serialize_as = PgSlotStatus)]
+    pub status: SlotStatus,
     pub dead_error: Option<String>,
 }
 
-impl Slot {
-    pub fn new(slot: i64, parent: Option<i64>, status: i32, dead_error: Option<String>) -> NewSlot {
+impl NewSlot {
+    pub fn new(
+        slot: i64,
+        parent: Option<i64>,
+        status: SlotStatus,
+        dead_error: Option<String>,
+    ) -> Self {
         NewSlot {
             slot,
             parent,
@@ -32,3 +48,104 @@ impl Slot {
         }
     }
 }
+
+/// Diesel mapping of the core `SlotStatus` to the `slot_status` postgres enum
+#[derive(AsExpression, FromSqlRow, Debug, Clone, Copy)]
+#[diesel(sql_type = sql_types::SlotStatus)]
+pub struct PgSlotStatus(pub SlotStatus);
+
+impl From<SlotStatus> for PgSlotStatus {
+    fn from(status: SlotStatus) -> Self {
+        PgSlotStatus(status)
+    }
+}
+
+impl From<PgSlotStatus> for SlotStatus {
+    fn from(status: PgSlotStatus) -> Self {
+        status.0
+    }
+}
+
+// enum values go over the wire as their label
+impl ToSql<sql_types::SlotStatus, Pg> for PgSlotStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.0.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::SlotStatus, Pg> for PgSlotStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let status = match bytes.as_bytes() {
+            b"processed" => SlotStatus::Processed,
+            b"confirmed" => SlotStatus::Confirmed,
+            b"finalized" => SlotStatus::Finalized,
+            b"first_shred_received" => SlotStatus::FirstShredReceived,
+            b"completed" => SlotStatus::Completed,
+            b"created_bank" => SlotStatus::CreatedBank,
+            b"dead" => SlotStatus::Dead,
+            other => {
+                return Err(
+                    format!("Unknown slot status '{}'", String::from_utf8_lossy(other)).into(),
+                );
+            }
+        };
+        Ok(PgSlotStatus(status))
+    }
+}
+
+/// How final the data written in a slot is, the `commitment` postgres enum
+#[derive(AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[diesel(sql_type = sql_types::Commitment)]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl Commitment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Commitment::Processed => "processed",
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        }
+    }
+}
+
+impl ToSql<sql_types::Commitment, Pg> for Commitment {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::Commitment, Pg> for Commitment {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"processed" => Ok(Commitment::Processed),
+            b"confirmed" => Ok(Commitment::Confirmed),
+            b"finalized" => Ok(Commitment::Finalized),
+            other => Err(format!("Unknown commitment '{}'", String::from_utf8_lossy(other)).into()),
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = slot_commitments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SlotCommitment {
+    pub slot: i64,
+    pub parent: Option<i64>,
+    pub commitment: Commitment,
+    /// the slot died or a later slot was finalized on another fork
+    pub orphaned: bool,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = slot_commitments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewSlotCommitment {
+    pub slot: i64,
+    pub parent: Option<i64>,
+}
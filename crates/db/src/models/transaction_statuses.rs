@@ -0,0 +1,43 @@
+use crate::schema::transaction_statuses;
+use diesel::prelude::*;
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = transaction_statuses)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TransactionStatus {
+    pub id: i64,
+    pub slot: i64,
+    pub signature: Vec<u8>,
+    pub is_vote: bool,
+    pub idx: i32,
+    pub err: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = transaction_statuses)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTransactionStatus {
+    pub slot: i64,
+    pub signature: Vec<u8>,
+    pub is_vote: bool,
+    pub idx: i32,
+    pub err: Option<String>,
+}
+
+impl NewTransactionStatus {
+    pub fn new(
+        slot: i64,
+        signature: Vec<u8>,
+        is_vote: bool,
+        idx: i32,
+        err: Option<String>,
+    ) -> Self {
+        NewTransactionStatus {
+            slot,
+            signature,
+            is_vote,
+            idx,
+            err,
+        }
+    }
+}
@@ -1,4 +1,15 @@
-use diesel::prelude::*;
+use crate::models::{Commitment, PgRewardType};
+use core::{
+    MessageVersion, RewardType, TransactionMessage, TransactionMeta, TransactionUpdateInfo,
+};
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue, data_types::PgNumeric},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Numeric,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Queryable, Selectable, Debug)]
@@ -15,12 +26,27 @@ pub struct Transaction {
     pub pre_balances: Vec<Option<i64>>,
     pub post_balances: Vec<Option<i64>>,
     pub log_messages: Option<Vec<Option<String>>>,
-    pub pre_token_balances: Vec<Option<i64>>,
-    pub post_token_balances: Vec<Option<i64>>,
+    pub pre_token_balances: Vec<Option<TokenAmount>>,
+    pub post_token_balances: Vec<Option<TokenAmount>>,
+    pub signatures: Vec<Option<Vec<u8>>>,
+    pub versioned: bool,
+    pub num_required_signatures: Option<i32>,
+    pub num_readonly_signed_accounts: Option<i32>,
+    pub num_readonly_unsigned_accounts: Option<i32>,
+    pub account_keys: Vec<Option<Vec<u8>>>,
+    pub recent_blockhash: Option<Vec<u8>>,
+    pub err: Option<String>,
+    pub loaded_writable_addresses: Vec<Option<Vec<u8>>>,
+    pub loaded_readonly_addresses: Vec<Option<Vec<u8>>>,
+    pub return_data_program_id: Option<Vec<u8>>,
+    pub return_data: Option<Vec<u8>>,
+    pub commitment: Commitment,
+    pub orphaned: bool,
 }
 
-#[derive(Insertable, Debug,Deserialize,Serialize)]
+#[derive(Insertable, Debug, Deserialize, Serialize)]
 #[diesel(table_name = crate::schema::transactions)]
+#[diesel(treat_none_as_default_value = false)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewTransaction {
     pub slot: i64,
@@ -32,8 +58,20 @@ pub struct NewTransaction {
     pub pre_balances: Vec<Option<i64>>,
     pub post_balances: Vec<Option<i64>>,
     pub log_messages: Option<Vec<Option<String>>>,
-    pub pre_token_balances: Vec<Option<i64>>,
-    pub post_token_balances: Vec<Option<i64>>,
+    pub pre_token_balances: Vec<Option<TokenAmount>>,
+    pub post_token_balances: Vec<Option<TokenAmount>>,
+    pub signatures: Vec<Option<Vec<u8>>>,
+    pub versioned: bool,
+    pub num_required_signatures: Option<i32>,
+    pub num_readonly_signed_accounts: Option<i32>,
+    pub num_readonly_unsigned_accounts: Option<i32>,
+    pub account_keys: Vec<Option<Vec<u8>>>,
+    pub recent_blockhash: Option<Vec<u8>>,
+    pub err: Option<String>,
+    pub loaded_writable_addresses: Vec<Option<Vec<u8>>>,
+    pub loaded_readonly_addresses: Vec<Option<Vec<u8>>>,
+    pub return_data_program_id: Option<Vec<u8>>,
+    pub return_data: Option<Vec<u8>>,
 }
 
 #[derive(Queryable, Selectable, Debug)]
@@ -46,12 +84,14 @@ pub struct TransactionTokenBAlance {
     pub mint: String,
     pub owner: Option<String>,
     pub ui_amount: Option<f64>,
-    pub amount: Option<i64>,
+    pub amount: Option<TokenAmount>,
     pub balance_type: Option<String>,
+    pub decimals: Option<i32>,
 }
 
 #[derive(Insertable, Debug)]
 #[diesel(table_name = crate::schema::transaction_token_balances)]
+#[diesel(treat_none_as_default_value = false)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewTransactionTokenBalance {
     pub transaction_id: i64,
@@ -59,11 +99,33 @@ pub struct NewTransactionTokenBalance {
     pub mint: String,
     pub owner: Option<String>,
     pub ui_amount: Option<f64>,
-    pub amount: Option<i64>,
+    pub amount: Option<TokenAmount>,
     pub balance_type: Option<String>,
+    pub decimals: Option<i32>,
+}
+
+impl NewTransactionTokenBalance {
+    // one row per pre and post token balance of the transaction
+    pub fn from_meta(transaction_id: i64, meta: &TransactionMeta) -> Vec<Self> {
+        let pre = meta.pre_token_balance.iter().map(|tb| (tb, "pre"));
+        let post = meta.post_token_balance.iter().map(|tb| (tb, "post"));
+        pre.chain(post)
+            .map(|(tb, balance_type)| NewTransactionTokenBalance {
+                transaction_id,
+                account_index: tb.account_index as i32,
+                mint: tb.mint.clone(),
+                owner: Some(tb.owner.clone()).filter(|owner| !owner.is_empty()),
+                ui_amount: Some(tb.ui_amount),
+                amount: Some(TokenAmount(tb.amount)),
+                balance_type: Some(balance_type.to_owned()),
+                decimals: Some(tb.decimals as i32),
+            })
+            .collect()
+    }
 }
 
 impl NewTransaction {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         slot: i64,
         signature: Vec<u8>,
@@ -74,8 +136,8 @@ impl NewTransaction {
         pre_balances: Option<Vec<Option<i64>>>,
         post_balances: Option<Vec<Option<i64>>>,
         log_messages: Option<Vec<Option<String>>>,
-        pre_token_balances: Option<Vec<Option<i64>>>,
-        post_token_balances: Option<Vec<Option<i64>>>,
+        pre_token_balances: Option<Vec<Option<TokenAmount>>>,
+        post_token_balances: Option<Vec<Option<TokenAmount>>>,
     ) -> Self {
         NewTransaction {
             slot,
@@ -89,6 +151,307 @@ impl NewTransaction {
             log_messages,
             pre_token_balances: pre_token_balances.unwrap_or_default(),
             post_token_balances: post_token_balances.unwrap_or_default(),
+            signatures: vec![],
+            versioned: false,
+            num_required_signatures: None,
+            num_readonly_signed_accounts: None,
+            num_readonly_unsigned_accounts: None,
+            account_keys: vec![],
+            recent_blockhash: None,
+            err: None,
+            loaded_writable_addresses: vec![],
+            loaded_readonly_addresses: vec![],
+            return_data_program_id: None,
+            return_data: None,
+        }
+    }
+
+    // fills the columns describing the transaction message
+    pub fn with_message(
+        mut self,
+        signatures: Vec<Vec<u8>>,
+        message: Option<&TransactionMessage>,
+    ) -> Self {
+        self.signatures = signatures.into_iter().map(Some).collect();
+        if let Some(message) = message {
+            self.versioned = message.version == MessageVersion::V0;
+            if let Some(header) = message.header {
+                self.num_required_signatures = Some(header.num_required_signatures as i32);
+                self.num_readonly_signed_accounts =
+                    Some(header.num_readonly_signed_accounts as i32);
+                self.num_readonly_unsigned_accounts =
+                    Some(header.num_readonly_unsigned_accounts as i32);
+            }
+            self.account_keys = message.account_keys.iter().cloned().map(Some).collect();
+            self.recent_blockhash = Some(message.recent_blockhash.clone());
+        }
+        self
+    }
+
+    // fills the status columns which don't fit the constructor
+    pub fn with_status(mut self, meta: &TransactionMeta) -> Self {
+        self.err = meta.err.clone();
+        self.loaded_writable_addresses = meta
+            .loaded_writable_addresses
+            .iter()
+            .cloned()
+            .map(Some)
+            .collect();
+        self.loaded_readonly_addresses = meta
+            .loaded_readonly_addresses
+            .iter()
+            .cloned()
+            .map(Some)
+            .collect();
+        if let Some(return_data) = &meta.return_data {
+            self.return_data_program_id = Some(return_data.program_id.clone());
+            self.return_data = Some(return_data.data.clone());
+        }
+        self
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::transaction_instructions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TransactionInstruction {
+    pub id: i64,
+    pub transaction_id: i64,
+    pub idx: i32,
+    pub program_id_index: i32,
+    pub program_id: Option<Vec<u8>>,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::transaction_instructions)]
+#[diesel(treat_none_as_default_value = false)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTransactionInstruction {
+    pub transaction_id: i64,
+    pub idx: i32,
+    pub program_id_index: i32,
+    pub program_id: Option<Vec<u8>>,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl NewTransactionInstruction {
+    // one row per top level instruction of the message
+    pub fn from_message(transaction_id: i64, message: &TransactionMessage) -> Vec<Self> {
+        message
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(idx, ix)| NewTransactionInstruction {
+                transaction_id,
+                idx: idx as i32,
+                program_id_index: ix.program_id_index as i32,
+                program_id: message.program_id(ix).map(<[u8]>::to_vec),
+                accounts: ix.accounts.clone(),
+                data: ix.data.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::transaction_address_table_lookups)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TransactionAddressTableLookup {
+    pub id: i64,
+    pub transaction_id: i64,
+    pub account_key: Vec<u8>,
+    pub writable_indexes: Vec<u8>,
+    pub readonly_indexes: Vec<u8>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::transaction_address_table_lookups)]
+#[diesel(treat_none_as_default_value = false)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTransactionAddressTableLookup {
+    pub transaction_id: i64,
+    pub account_key: Vec<u8>,
+    pub writable_indexes: Vec<u8>,
+    pub readonly_indexes: Vec<u8>,
+}
+
+impl NewTransactionAddressTableLookup {
+    pub fn from_message(transaction_id: i64, message: &TransactionMessage) -> Vec<Self> {
+        message
+            .address_table_lookups
+            .iter()
+            .map(|lookup| NewTransactionAddressTableLookup {
+                transaction_id,
+                account_key: lookup.account_key.clone(),
+                writable_indexes: lookup.writable_indexes.clone(),
+                readonly_indexes: lookup.readonly_indexes.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::transaction_inner_instructions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TransactionInnerInstruction {
+    pub id: i64,
+    pub transaction_id: i64,
+    pub instruction_idx: i32,
+    pub idx: i32,
+    pub program_id_index: i32,
+    pub program_id: Option<Vec<u8>>,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+    pub stack_height: Option<i32>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::transaction_inner_instructions)]
+#[diesel(treat_none_as_default_value = false)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTransactionInnerInstruction {
+    pub transaction_id: i64,
+    pub instruction_idx: i32,
+    pub idx: i32,
+    pub program_id_index: i32,
+    pub program_id: Option<Vec<u8>>,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+    pub stack_height: Option<i32>,
+}
+
+impl NewTransactionInnerInstruction {
+    // one row per CPI, with the program resolved against the loaded keys as well
+    pub fn from_info(transaction_id: i64, info: &TransactionUpdateInfo) -> Vec<Self> {
+        let Some(meta) = &info.meta else {
+            return vec![];
+        };
+        let account_keys = info.account_keys();
+        meta.inner_instructions
+            .iter()
+            .flat_map(|inner| {
+                inner
+                    .instructions
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, ix)| (inner.index, idx, ix))
+            })
+            .map(
+                |(instruction_idx, idx, ix)| NewTransactionInnerInstruction {
+                    transaction_id,
+                    instruction_idx: instruction_idx as i32,
+                    idx: idx as i32,
+                    program_id_index: ix.program_id_index as i32,
+                    program_id: account_keys
+                        .get(ix.program_id_index as usize)
+                        .map(|key| key.to_vec()),
+                    accounts: ix.accounts.clone(),
+                    data: ix.data.clone(),
+                    stack_height: ix.stack_height.map(|h| h as i32),
+                },
+            )
+            .collect()
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::transaction_rewards)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TransactionReward {
+    pub id: i64,
+    pub transaction_id: i64,
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: i64,
+    #[diesel(deserialize_as = PgRewardType)]
+    pub reward_type: RewardType,
+    pub commission: Option<i16>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::transaction_rewards)]
+#[diesel(treat_none_as_default_value = false)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTransactionReward {
+    pub transaction_id: i64,
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: i64,
+    #[diesel(serialize_as = PgRewardType)]
+    pub reward_type: RewardType,
+    pub commission: Option<i16>,
+}
+
+impl NewTransactionReward {
+    pub fn from_meta(transaction_id: i64, meta: &TransactionMeta) -> Vec<Self> {
+        meta.rewards
+            .iter()
+            .map(|reward| NewTransactionReward {
+                transaction_id,
+                pubkey: reward.pubkey.clone(),
+                lamports: reward.lamports,
+                post_balance: reward.post_balance as i64,
+                reward_type: reward.reward_type,
+                commission: reward.commission.map(i16::from),
+            })
+            .collect()
+    }
+}
+
+/// A raw token amount, a u64 kept in a NUMERIC(20, 0) column as it doesn't
+/// fit a BIGINT
+#[derive(AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[diesel(sql_type = Numeric)]
+#[serde(transparent)]
+pub struct TokenAmount(pub u64);
+
+/// Base of the digits of a postgres NUMERIC
+const NUMERIC_BASE: u64 = 10_000;
+
+impl ToSql<Numeric, Pg> for TokenAmount {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let mut digits = vec![];
+        let mut rest = self.0;
+        while rest > 0 {
+            digits.push((rest % NUMERIC_BASE) as i16);
+            rest /= NUMERIC_BASE;
+        }
+        digits.reverse();
+        let weight = (digits.len() as i16 - 1).max(0);
+        // trailing zero digits are implied by the weight
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        let numeric = PgNumeric::Positive {
+            weight,
+            scale: 0,
+            digits,
+        };
+        ToSql::<Numeric, Pg>::to_sql(&numeric, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Numeric, Pg> for TokenAmount {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let PgNumeric::Positive { weight, digits, .. } = PgNumeric::from_sql(bytes)? else {
+            return Err("Token amount isn't a positive number".into());
+        };
+        // digits after the weight are the fraction
+        let whole = usize::try_from(weight + 1).unwrap_or(0);
+        if digits.iter().skip(whole).any(|&d| d != 0) {
+            return Err("Token amount isn't a whole number".into());
+        }
+        let mut amount: u64 = 0;
+        for i in 0..whole {
+            let digit = digits.get(i).copied().unwrap_or(0) as u64;
+            amount = amount
+                .checked_mul(NUMERIC_BASE)
+                .and_then(|a| a.checked_add(digit))
+                .ok_or("Token amount doesn't fit a u64")?;
         }
+        Ok(TokenAmount(amount))
     }
 }
@@ -1,6 +1,42 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "commitment"))]
+    pub struct Commitment;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "reward_type"))]
+    pub struct RewardType;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "slot_status"))]
+    pub struct SlotStatus;
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Commitment;
+
+    account_state (pubkey) {
+        pubkey -> Bytea,
+        slot -> Int8,
+        is_startup -> Bool,
+        lamports -> Int8,
+        owner -> Bytea,
+        executable -> Bool,
+        rent_epoch -> Int8,
+        data -> Bytea,
+        write_version -> Int8,
+        txn_signature -> Nullable<Bytea>,
+        commitment -> Commitment,
+    }
+}
+
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Commitment;
+
     accounts (id) {
         id -> Int8,
         slot -> Int8,
@@ -13,19 +49,138 @@ diesel::table! {
         data -> Bytea,
         write_version -> Int8,
         txn_signature -> Nullable<Bytea>,
+        commitment -> Commitment,
+        orphaned -> Bool,
+    }
+}
+
+diesel::table! {
+    blocks (slot) {
+        slot -> Int8,
+        blockhash -> Text,
+        parent_slot -> Int8,
+        parent_blockhash -> Text,
+        executed_transaction_count -> Int8,
+        updated_account_count -> Nullable<Int8>,
+        entries_count -> Int8,
+        block_time -> Nullable<Int8>,
+        block_height -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    entries (id) {
+        id -> Int8,
+        slot -> Int8,
+        idx -> Int8,
+        num_hashes -> Int8,
+        hash -> Bytea,
+        executed_transaction_count -> Int8,
+        starting_transaction_index -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::RewardType;
+
+    rewards (slot, pubkey, reward_type) {
+        slot -> Int8,
+        pubkey -> Text,
+        lamports -> Int8,
+        post_balance -> Int8,
+        reward_type -> RewardType,
+        commission -> Nullable<Int2>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Commitment;
+
+    slot_commitments (slot) {
+        slot -> Int8,
+        parent -> Nullable<Int8>,
+        commitment -> Commitment,
+        orphaned -> Bool,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::SlotStatus;
+
     slots (id) {
         id -> Int4,
         slot -> Int8,
         parent -> Nullable<Int8>,
-        status -> Int4,
+        status -> SlotStatus,
         dead_error -> Nullable<Text>,
     }
 }
 
+diesel::table! {
+    transaction_address_table_lookups (id) {
+        id -> Int8,
+        transaction_id -> Int8,
+        account_key -> Bytea,
+        writable_indexes -> Bytea,
+        readonly_indexes -> Bytea,
+    }
+}
+
+diesel::table! {
+    transaction_inner_instructions (id) {
+        id -> Int8,
+        transaction_id -> Int8,
+        instruction_idx -> Int4,
+        idx -> Int4,
+        program_id_index -> Int4,
+        program_id -> Nullable<Bytea>,
+        accounts -> Bytea,
+        data -> Bytea,
+        stack_height -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    transaction_instructions (id) {
+        id -> Int8,
+        transaction_id -> Int8,
+        idx -> Int4,
+        program_id_index -> Int4,
+        program_id -> Nullable<Bytea>,
+        accounts -> Bytea,
+        data -> Bytea,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::RewardType;
+
+    transaction_rewards (id) {
+        id -> Int8,
+        transaction_id -> Int8,
+        pubkey -> Text,
+        lamports -> Int8,
+        post_balance -> Int8,
+        reward_type -> RewardType,
+        commission -> Nullable<Int2>,
+    }
+}
+
+diesel::table! {
+    transaction_statuses (id) {
+        id -> Int8,
+        slot -> Int8,
+        signature -> Bytea,
+        is_vote -> Bool,
+        idx -> Int4,
+        err -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     transaction_token_balances (id) {
         id -> Int8,
@@ -34,12 +189,16 @@ diesel::table! {
         mint -> Text,
         owner -> Nullable<Text>,
         ui_amount -> Nullable<Float8>,
-        amount -> Nullable<Int8>,
+        amount -> Nullable<Numeric>,
         balance_type -> Nullable<Text>,
+        decimals -> Nullable<Int4>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Commitment;
+
     transactions (id) {
         id -> Int8,
         slot -> Int8,
@@ -51,16 +210,44 @@ diesel::table! {
         pre_balances -> Array<Nullable<Int8>>,
         post_balances -> Array<Nullable<Int8>>,
         log_messages -> Nullable<Array<Nullable<Text>>>,
-        pre_token_balances -> Array<Nullable<Int8>>,
-        post_token_balances -> Array<Nullable<Int8>>,
+        pre_token_balances -> Array<Nullable<Numeric>>,
+        post_token_balances -> Array<Nullable<Numeric>>,
+        signatures -> Array<Nullable<Bytea>>,
+        versioned -> Bool,
+        num_required_signatures -> Nullable<Int4>,
+        num_readonly_signed_accounts -> Nullable<Int4>,
+        num_readonly_unsigned_accounts -> Nullable<Int4>,
+        account_keys -> Array<Nullable<Bytea>>,
+        recent_blockhash -> Nullable<Bytea>,
+        err -> Nullable<Text>,
+        loaded_writable_addresses -> Array<Nullable<Bytea>>,
+        loaded_readonly_addresses -> Array<Nullable<Bytea>>,
+        return_data_program_id -> Nullable<Bytea>,
+        return_data -> Nullable<Bytea>,
+        commitment -> Commitment,
+        orphaned -> Bool,
     }
 }
 
+diesel::joinable!(transaction_address_table_lookups -> transactions (transaction_id));
+diesel::joinable!(transaction_inner_instructions -> transactions (transaction_id));
+diesel::joinable!(transaction_instructions -> transactions (transaction_id));
+diesel::joinable!(transaction_rewards -> transactions (transaction_id));
 diesel::joinable!(transaction_token_balances -> transactions (transaction_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    account_state,
     accounts,
+    blocks,
+    entries,
+    rewards,
+    slot_commitments,
     slots,
+    transaction_address_table_lookups,
+    transaction_inner_instructions,
+    transaction_instructions,
+    transaction_rewards,
+    transaction_statuses,
     transaction_token_balances,
     transactions,
 );
@@ -1,16 +1,70 @@
+use std::collections::HashMap;
+
 use anyhow::{Context, Error, Result};
-use diesel::{pg::PgConnection, r2d2::{ConnectionManager, Pool}, RunQueryDsl, dsl::insert_into};
+use core::{SlotStatus, TransactionUpdateInfo};
+use diesel::{
+    BoolExpressionMethods, Connection, ExecuteCopyFromDsl, ExpressionMethods, QueryableByName,
+    RunQueryDsl, define_sql_function,
+    dsl::insert_into,
+    pg::PgConnection,
+    query_dsl::methods::FilterDsl,
+    r2d2::{ConnectionManager, Pool},
+    sql_query,
+    sql_types::{Array, BigInt, Bytea, Nullable, SingleValue, SqlType},
+    upsert::excluded,
+};
+
+use crate::{
+    models::{
+        Commitment, NewAccount, NewAccountState, NewBlock, NewEntry, NewReward, NewSlot,
+        NewSlotCommitment, NewTransaction, NewTransactionAddressTableLookup,
+        NewTransactionInnerInstruction, NewTransactionInstruction, NewTransactionReward,
+        NewTransactionStatus, NewTransactionTokenBalance,
+    },
+    schema::{
+        account_state, accounts, blocks, entries, rewards, slot_commitments, slots,
+        transaction_address_table_lookups, transaction_inner_instructions,
+        transaction_instructions, transaction_rewards, transaction_statuses,
+        transaction_token_balances, transactions,
+    },
+};
+
+define_sql_function! {
+    // first of the arguments which isn't null
+    fn coalesce<T: SqlType + SingleValue>(x: Nullable<T>, y: Nullable<T>) -> Nullable<T>;
+}
 
-use crate::{models::NewTransaction, schema::transactions};
+// rows per multi-row insert, keeps the widest table (transactions) well
+// below the 65535 bind parameters postgres accepts per statement
+const INSERT_CHUNK_ROWS: usize = 1000;
 
 #[derive(Clone)]
 pub struct Store {
     pub pool: Pool<ConnectionManager<PgConnection>>,
+    /// whether every account write is kept in `accounts` next to the latest
+    /// state in `account_state`
+    pub account_history: bool,
+    /// whether rows of orphaned slots are deleted instead of only flagged
+    pub delete_orphans: bool,
 }
 
 impl Store {
     pub fn new(pool: Pool<ConnectionManager<PgConnection>>) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            account_history: true,
+            delete_orphans: false,
+        }
+    }
+
+    pub fn with_account_history(mut self, account_history: bool) -> Self {
+        self.account_history = account_history;
+        self
+    }
+
+    pub fn with_orphan_deletion(mut self, delete_orphans: bool) -> Self {
+        self.delete_orphans = delete_orphans;
+        self
     }
 
     pub fn insert_transaction(&self, txs: &[NewTransaction]) -> Result<usize, Error> {
@@ -18,14 +72,807 @@ impl Store {
             return Ok(0);
         }
 
-        let mut conn = self.pool.get().context("Failed to get DB connection from pool")?;
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        let mut results = 0;
+        for chunk in txs.chunks(INSERT_CHUNK_ROWS) {
+            results += insert_into(transactions::table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .context("Failed to insert transactions")?;
+        }
+
+        Ok(results)
+    }
+
+    // inserts a batch of transactions with the instructions and lookups of their
+    // messages, their inner instructions, token balances and rewards inside a
+    // single db transaction, using one multi-row insert per table. transactions
+    // which are already stored are skipped together with their child rows
+    pub fn insert_full_transactions(
+        &self,
+        txs: &[(NewTransaction, &TransactionUpdateInfo)],
+    ) -> Result<usize, Error> {
+        if txs.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let mut ids = HashMap::with_capacity(txs.len());
+            for chunk in txs.chunks(INSERT_CHUNK_ROWS) {
+                let rows: Vec<&NewTransaction> = chunk.iter().map(|(tx, _)| tx).collect();
+                let inserted = insert_into(transactions::table)
+                    .values(rows)
+                    .on_conflict((transactions::slot, transactions::signature))
+                    .do_nothing()
+                    .returning((
+                        transactions::id,
+                        transactions::slot,
+                        transactions::signature,
+                    ))
+                    .get_results::<(i64, i64, Vec<u8>)>(conn)
+                    .context("Failed to insert transactions")?;
+
+                ids.extend(
+                    inserted
+                        .into_iter()
+                        .map(|(id, slot, signature)| ((slot, signature), id)),
+                );
+            }
+
+            let inserted = ids.len();
+            let children = TransactionChildren::link(ids, txs);
+
+            for chunk in children.instructions.chunks(INSERT_CHUNK_ROWS) {
+                insert_into(transaction_instructions::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .context("Failed to insert transaction instructions")?;
+            }
+
+            for chunk in children.lookups.chunks(INSERT_CHUNK_ROWS) {
+                insert_into(transaction_address_table_lookups::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .context("Failed to insert address table lookups")?;
+            }
+
+            for chunk in children.inner_instructions.chunks(INSERT_CHUNK_ROWS) {
+                insert_into(transaction_inner_instructions::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .context("Failed to insert inner instructions")?;
+            }
+
+            for chunk in children.token_balances.chunks(INSERT_CHUNK_ROWS) {
+                insert_into(transaction_token_balances::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .context("Failed to insert token balances")?;
+            }
+
+            for chunk in children.rewards.chunks(INSERT_CHUNK_ROWS) {
+                insert_into(transaction_rewards::table)
+                    .values(chunk.to_vec())
+                    .execute(conn)
+                    .context("Failed to insert transaction rewards")?;
+            }
+
+            let slots = distinct_slots(txs.iter().map(|(tx, _)| tx.slot));
+            sync_transactions(conn, &slots, self.delete_orphans)?;
+
+            Ok(inserted)
+        })
+    }
+
+    // same as insert_full_transactions, but streams the rows through
+    // COPY ... FROM STDIN (FORMAT BINARY) which is much cheaper for big batches.
+    // the transactions are staged first and merged with ON CONFLICT, the child
+    // rows only ever belong to freshly merged transactions so they are copied
+    // straight into their tables
+    pub fn copy_full_transactions(
+        &self,
+        txs: &[(NewTransaction, &TransactionUpdateInfo)],
+    ) -> Result<usize, Error> {
+        if txs.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let rows: Vec<&NewTransaction> = txs.iter().map(|(tx, _)| tx).collect();
+            let merged = stage(
+                conn,
+                "transactions",
+                |conn| {
+                    diesel::copy_from(transactions::table)
+                        .from_insertable(rows)
+                        .execute(conn)
+                        .context("Failed to copy transactions")
+                },
+                |conn| {
+                    sql_query(
+                        "INSERT INTO public.transactions SELECT * FROM pg_temp.transactions_stage \
+                         ON CONFLICT (slot, signature) DO NOTHING \
+                         RETURNING id, slot, signature",
+                    )
+                    .load::<MergedTransaction>(conn)
+                    .context("Failed to merge staged transactions")
+                },
+            )?;
+
+            let ids: HashMap<_, _> = merged
+                .into_iter()
+                .map(|tx| ((tx.slot, tx.signature), tx.id))
+                .collect();
+            let inserted = ids.len();
+            let children = TransactionChildren::link(ids, txs);
+
+            if !children.instructions.is_empty() {
+                diesel::copy_from(transaction_instructions::table)
+                    .from_insertable(&children.instructions)
+                    .execute(conn)
+                    .context("Failed to copy transaction instructions")?;
+            }
+
+            if !children.lookups.is_empty() {
+                diesel::copy_from(transaction_address_table_lookups::table)
+                    .from_insertable(&children.lookups)
+                    .execute(conn)
+                    .context("Failed to copy address table lookups")?;
+            }
+
+            if !children.inner_instructions.is_empty() {
+                diesel::copy_from(transaction_inner_instructions::table)
+                    .from_insertable(&children.inner_instructions)
+                    .execute(conn)
+                    .context("Failed to copy inner instructions")?;
+            }
+
+            if !children.token_balances.is_empty() {
+                diesel::copy_from(transaction_token_balances::table)
+                    .from_insertable(&children.token_balances)
+                    .execute(conn)
+                    .context("Failed to copy token balances")?;
+            }
+
+            if !children.rewards.is_empty() {
+                diesel::copy_from(transaction_rewards::table)
+                    .from_insertable(children.rewards)
+                    .execute(conn)
+                    .context("Failed to copy transaction rewards")?;
+            }
+
+            let slots = distinct_slots(txs.iter().map(|(tx, _)| tx.slot));
+            sync_transactions(conn, &slots, self.delete_orphans)?;
+
+            Ok(inserted)
+        })
+    }
+
+    pub fn insert_accounts(&self, accs: &[NewAccount]) -> Result<usize, Error> {
+        if accs.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let mut results = 0;
+            for chunk in accs.chunks(INSERT_CHUNK_ROWS) {
+                results += insert_into(accounts::table)
+                    .values(chunk)
+                    .on_conflict((accounts::pubkey, accounts::slot, accounts::write_version))
+                    .do_nothing()
+                    .execute(conn)
+                    .context("Failed to insert accounts")?;
+            }
+
+            let slots = distinct_slots(accs.iter().map(|acc| acc.slot));
+            sync_accounts(conn, &slots, self.delete_orphans)?;
+
+            Ok(results)
+        })
+    }
+
+    // COPY BINARY counterpart of insert_accounts, account writes which are
+    // already stored are skipped when the staged rows are merged
+    pub fn copy_accounts(&self, accs: &[NewAccount]) -> Result<usize, Error> {
+        if accs.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
 
-        let results = insert_into(transactions::table)
-            .values(txs)
-            .on_conflict_do_nothing()
-            .execute(&mut conn)
-            .context("Failed to insert transactions")?;
+        conn.transaction::<_, Error, _>(|conn| {
+            let merged = stage(
+                conn,
+                "accounts",
+                |conn| {
+                    diesel::copy_from(accounts::table)
+                        .from_insertable(accs)
+                        .execute(conn)
+                        .context("Failed to copy accounts")
+                },
+                |conn| {
+                    sql_query(
+                        "INSERT INTO public.accounts SELECT * FROM pg_temp.accounts_stage \
+                         ON CONFLICT (pubkey, slot, write_version) DO NOTHING",
+                    )
+                    .execute(conn)
+                    .context("Failed to merge staged accounts")
+                },
+            )?;
+
+            let slots = distinct_slots(accs.iter().map(|acc| acc.slot));
+            sync_accounts(conn, &slots, self.delete_orphans)?;
+
+            Ok(merged)
+        })
+    }
+
+    // moves the state of every account forward to the newest write of the
+    // batch. a write only replaces the stored state when its (slot, write_version)
+    // is newer, so writes arriving late or twice never roll an account back
+    pub fn upsert_account_states(&self, states: &[NewAccountState]) -> Result<usize, Error> {
+        if states.is_empty() {
+            return Ok(0);
+        }
+
+        // postgres refuses to update the same row twice in one statement, so
+        // only the newest write of every account in the batch is kept
+        let mut newest: HashMap<&[u8], &NewAccountState> = HashMap::with_capacity(states.len());
+        for state in states {
+            match newest.get(state.pubkey.as_slice()) {
+                Some(current) if !state.is_newer_than(current) => {}
+                _ => {
+                    newest.insert(&state.pubkey, state);
+                }
+            }
+        }
+        let rows: Vec<&NewAccountState> = newest.into_values().collect();
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let mut results = 0;
+            for chunk in rows.chunks(INSERT_CHUNK_ROWS) {
+                results += insert_into(account_state::table)
+                    .values(chunk.to_vec())
+                    .on_conflict(account_state::pubkey)
+                    .do_update()
+                    .set((
+                        account_state::slot.eq(excluded(account_state::slot)),
+                        account_state::is_startup.eq(excluded(account_state::is_startup)),
+                        account_state::lamports.eq(excluded(account_state::lamports)),
+                        account_state::owner.eq(excluded(account_state::owner)),
+                        account_state::executable.eq(excluded(account_state::executable)),
+                        account_state::rent_epoch.eq(excluded(account_state::rent_epoch)),
+                        account_state::data.eq(excluded(account_state::data)),
+                        account_state::write_version.eq(excluded(account_state::write_version)),
+                        account_state::txn_signature.eq(excluded(account_state::txn_signature)),
+                        account_state::commitment.eq(excluded(account_state::commitment)),
+                    ))
+                    .filter(account_state::slot.lt(excluded(account_state::slot)).or(
+                        account_state::slot.eq(excluded(account_state::slot)).and(
+                            account_state::write_version.lt(excluded(account_state::write_version)),
+                        ),
+                    ))
+                    .execute(conn)
+                    .context("Failed to upsert account states")?;
+            }
+
+            // the write may come from a slot which is already confirmed or orphaned
+            let slots = distinct_slots(rows.iter().map(|state| state.slot));
+            sync_accounts(conn, &slots, self.delete_orphans)?;
+
+            Ok(results)
+        })
+    }
+
+    pub fn insert_slots(&self, new_slots: &[NewSlot]) -> Result<usize, Error> {
+        if new_slots.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        let mut results = 0;
+        for chunk in new_slots.chunks(INSERT_CHUNK_ROWS) {
+            results += insert_into(slots::table)
+                .values(chunk.to_vec())
+                .on_conflict((slots::slot, slots::status))
+                .do_nothing()
+                .execute(&mut conn)
+                .context("Failed to insert slots")?;
+        }
 
         Ok(results)
     }
+
+    // fork tracker: follows the status updates of the slots in order and moves
+    // the commitment of the transactions and accounts written in them along.
+    // finalizing a slot finalizes its ancestors and orphans the slots below it
+    // which are still pending and known not to be among them, as those were on
+    // a fork that got skipped
+    pub fn track_slots(&self, updates: &[NewSlot]) -> Result<usize, Error> {
+        if updates.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let mut changed = vec![];
+            for update in updates {
+                insert_into(slot_commitments::table)
+                    .values(NewSlotCommitment {
+                        slot: update.slot,
+                        parent: update.parent,
+                    })
+                    .on_conflict(slot_commitments::slot)
+                    .do_update()
+                    .set(slot_commitments::parent.eq(excluded(slot_commitments::parent)))
+                    .filter(excluded(slot_commitments::parent).is_not_null())
+                    .execute(conn)
+                    .context("Failed to record slot")?;
+
+                match update.status {
+                    SlotStatus::Confirmed => changed.extend(confirm_slot(conn, update.slot)?),
+                    SlotStatus::Finalized => changed.extend(finalize_slot(conn, update.slot)?),
+                    SlotStatus::Dead => {
+                        println!("💀 Slot {} is dead, orphaning its rows", update.slot);
+                        changed.extend(orphan_slots(conn, update.slot, update.slot)?)
+                    }
+                    _ => {}
+                }
+            }
+
+            let changed = distinct_slots(changed.into_iter());
+            sync_transactions(conn, &changed, self.delete_orphans)?;
+            sync_accounts(conn, &changed, self.delete_orphans)?;
+
+            Ok(changed.len())
+        })
+    }
+
+    // a block is stored once per slot. full blocks and block metas fill in the
+    // fields the other one doesn't carry, neither wipes what is already known
+    pub fn insert_blocks(&self, new_blocks: &[NewBlock]) -> Result<usize, Error> {
+        if new_blocks.is_empty() {
+            return Ok(0);
+        }
+
+        // postgres refuses to update the same row twice in one statement
+        let mut by_slot: HashMap<i64, &NewBlock> = HashMap::with_capacity(new_blocks.len());
+        for block in new_blocks {
+            by_slot.insert(block.slot, block);
+        }
+        let rows: Vec<&NewBlock> = by_slot.into_values().collect();
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        let mut results = 0;
+        for chunk in rows.chunks(INSERT_CHUNK_ROWS) {
+            results += insert_into(blocks::table)
+                .values(chunk.to_vec())
+                .on_conflict(blocks::slot)
+                .do_update()
+                .set((
+                    blocks::blockhash.eq(excluded(blocks::blockhash)),
+                    blocks::parent_slot.eq(excluded(blocks::parent_slot)),
+                    blocks::parent_blockhash.eq(excluded(blocks::parent_blockhash)),
+                    blocks::executed_transaction_count
+                        .eq(excluded(blocks::executed_transaction_count)),
+                    blocks::entries_count.eq(excluded(blocks::entries_count)),
+                    blocks::updated_account_count.eq(coalesce(
+                        excluded(blocks::updated_account_count),
+                        blocks::updated_account_count,
+                    )),
+                    blocks::block_time
+                        .eq(coalesce(excluded(blocks::block_time), blocks::block_time)),
+                    blocks::block_height.eq(coalesce(
+                        excluded(blocks::block_height),
+                        blocks::block_height,
+                    )),
+                ))
+                .execute(&mut conn)
+                .context("Failed to upsert blocks")?;
+        }
+
+        Ok(results)
+    }
+
+    // full blocks and block metas of a slot carry the same rewards, the first
+    // one to arrive writes them
+    pub fn insert_rewards(&self, new_rewards: &[NewReward]) -> Result<usize, Error> {
+        if new_rewards.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        let mut results = 0;
+        for chunk in new_rewards.chunks(INSERT_CHUNK_ROWS) {
+            results += insert_into(rewards::table)
+                .values(chunk.to_vec())
+                .on_conflict((rewards::slot, rewards::pubkey, rewards::reward_type))
+                .do_nothing()
+                .execute(&mut conn)
+                .context("Failed to insert rewards")?;
+        }
+
+        Ok(results)
+    }
+
+    pub fn insert_entries(&self, new_entries: &[NewEntry]) -> Result<usize, Error> {
+        if new_entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        let mut results = 0;
+        for chunk in new_entries.chunks(INSERT_CHUNK_ROWS) {
+            results += insert_into(entries::table)
+                .values(chunk)
+                .on_conflict((entries::slot, entries::idx))
+                .do_nothing()
+                .execute(&mut conn)
+                .context("Failed to insert entries")?;
+        }
+
+        Ok(results)
+    }
+
+    pub fn insert_transaction_statuses(
+        &self,
+        statuses: &[NewTransactionStatus],
+    ) -> Result<usize, Error> {
+        if statuses.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection from pool")?;
+
+        let mut results = 0;
+        for chunk in statuses.chunks(INSERT_CHUNK_ROWS) {
+            results += insert_into(transaction_statuses::table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .context("Failed to insert transaction statuses")?;
+        }
+
+        Ok(results)
+    }
+}
+
+// sorted slots without duplicates, to be bound as an array
+fn distinct_slots(slots: impl Iterator<Item = i64>) -> Vec<i64> {
+    let mut slots: Vec<i64> = slots.collect();
+    slots.sort_unstable();
+    slots.dedup();
+    slots
+}
+
+fn confirm_slot(conn: &mut PgConnection, slot: i64) -> Result<Vec<i64>> {
+    diesel::update(
+        slot_commitments::table
+            .filter(slot_commitments::slot.eq(slot))
+            .filter(slot_commitments::commitment.eq(Commitment::Processed)),
+    )
+    .set(slot_commitments::commitment.eq(Commitment::Confirmed))
+    .returning(slot_commitments::slot)
+    .get_results(conn)
+    .context("Failed to confirm slot")
+}
+
+// finalizes the slot with every ancestor which isn't finalized yet, and orphans
+// the pending slots that are provably on another fork: those between the
+// finalized slot and the lowest ancestor its recorded parents lead to, as the
+// chain down to there is known. slots below a gap in the chain are left alone
+fn finalize_slot(conn: &mut PgConnection, slot: i64) -> Result<Vec<i64>> {
+    let ancestors: Vec<SlotLink> = sql_query(
+        "WITH RECURSIVE ancestors AS ( \
+             SELECT slot, parent FROM slot_commitments WHERE slot = $1 \
+             UNION \
+             SELECT c.slot, c.parent FROM slot_commitments c \
+             JOIN ancestors a ON c.slot = a.parent \
+             WHERE c.commitment <> 'finalized' \
+         ) \
+         SELECT slot, parent FROM ancestors",
+    )
+    .bind::<BigInt, _>(slot)
+    .load(conn)
+    .context("Failed to load the ancestors of the slot")?;
+
+    let slots: Vec<i64> = ancestors.iter().map(|a| a.slot).collect();
+    // a slot orphaned by mistake is taken back once it turns out to be final
+    let mut changed = diesel::update(
+        slot_commitments::table
+            .filter(slot_commitments::slot.eq_any(&slots))
+            .filter(
+                slot_commitments::commitment
+                    .ne(Commitment::Finalized)
+                    .or(slot_commitments::orphaned),
+            ),
+    )
+    .set((
+        slot_commitments::commitment.eq(Commitment::Finalized),
+        slot_commitments::orphaned.eq(false),
+    ))
+    .returning(slot_commitments::slot)
+    .get_results::<i64>(conn)
+    .context("Failed to finalize slot")?;
+
+    // the lowest ancestor reached, or its parent when that one is recorded
+    let known_from = ancestors
+        .iter()
+        .min_by_key(|a| a.slot)
+        .map_or(slot, |a| a.parent.unwrap_or(a.slot));
+    changed.extend(orphan_slots(conn, known_from + 1, slot - 1)?);
+    Ok(changed)
+}
+
+// orphans the slots of the range which aren't finalized
+fn orphan_slots(conn: &mut PgConnection, from: i64, to: i64) -> Result<Vec<i64>> {
+    let orphaned = diesel::update(
+        slot_commitments::table
+            .filter(slot_commitments::slot.between(from, to))
+            .filter(slot_commitments::commitment.ne(Commitment::Finalized))
+            .filter(slot_commitments::orphaned.eq(false)),
+    )
+    .set(slot_commitments::orphaned.eq(true))
+    .returning(slot_commitments::slot)
+    .get_results::<i64>(conn)
+    .context("Failed to orphan slots")?;
+
+    if !orphaned.is_empty() {
+        println!("🍂 Orphaned {} slot(s): {:?}", orphaned.len(), orphaned);
+    }
+    Ok(orphaned)
+}
+
+// copies the commitment the tracker knows of the slots onto their transactions,
+// dropping the transactions of orphaned slots when asked to
+fn sync_transactions(conn: &mut PgConnection, slots: &[i64], delete_orphans: bool) -> Result<()> {
+    if slots.is_empty() {
+        return Ok(());
+    }
+
+    sql_query(
+        "UPDATE transactions t SET commitment = c.commitment, orphaned = c.orphaned \
+         FROM slot_commitments c \
+         WHERE t.slot = c.slot AND c.slot = ANY($1) \
+         AND (t.commitment, t.orphaned) IS DISTINCT FROM (c.commitment, c.orphaned)",
+    )
+    .bind::<Array<BigInt>, _>(slots)
+    .execute(conn)
+    .context("Failed to sync transaction commitment")?;
+
+    if delete_orphans {
+        sql_query("DELETE FROM transactions WHERE orphaned AND slot = ANY($1)")
+            .bind::<Array<BigInt>, _>(slots)
+            .execute(conn)
+            .context("Failed to delete orphaned transactions")?;
+    }
+
+    Ok(())
+}
+
+// same as sync_transactions for the account history, account states written
+// in an orphaned slot are rolled back to the newest write left in the history
+// or dropped when there is none
+fn sync_accounts(conn: &mut PgConnection, slots: &[i64], delete_orphans: bool) -> Result<()> {
+    if slots.is_empty() {
+        return Ok(());
+    }
+
+    sql_query(
+        "UPDATE accounts a SET commitment = c.commitment, orphaned = c.orphaned \
+         FROM slot_commitments c \
+         WHERE a.slot = c.slot AND c.slot = ANY($1) \
+         AND (a.commitment, a.orphaned) IS DISTINCT FROM (c.commitment, c.orphaned)",
+    )
+    .bind::<Array<BigInt>, _>(slots)
+    .execute(conn)
+    .context("Failed to sync account commitment")?;
+
+    sql_query(
+        "UPDATE account_state s SET commitment = c.commitment \
+         FROM slot_commitments c \
+         WHERE s.slot = c.slot AND c.slot = ANY($1) AND NOT c.orphaned \
+         AND s.commitment <> c.commitment",
+    )
+    .bind::<Array<BigInt>, _>(slots)
+    .execute(conn)
+    .context("Failed to sync account state commitment")?;
+
+    sql_query(
+        "UPDATE account_state s SET slot = h.slot, is_startup = h.is_startup, \
+             lamports = h.lamports, owner = h.owner, executable = h.executable, \
+             rent_epoch = h.rent_epoch, data = h.data, write_version = h.write_version, \
+             txn_signature = h.txn_signature, commitment = h.commitment \
+         FROM ( \
+             SELECT DISTINCT ON (h.pubkey) h.* FROM accounts h \
+             JOIN account_state s ON s.pubkey = h.pubkey \
+             JOIN slot_commitments c ON c.slot = s.slot \
+             WHERE c.orphaned AND s.slot = ANY($1) AND NOT h.orphaned \
+             ORDER BY h.pubkey, h.slot DESC, h.write_version DESC \
+         ) h \
+         WHERE s.pubkey = h.pubkey",
+    )
+    .bind::<Array<BigInt>, _>(slots)
+    .execute(conn)
+    .context("Failed to roll back orphaned account states")?;
+
+    sql_query(
+        "DELETE FROM account_state s USING slot_commitments c \
+         WHERE s.slot = c.slot AND c.orphaned AND s.slot = ANY($1)",
+    )
+    .bind::<Array<BigInt>, _>(slots)
+    .execute(conn)
+    .context("Failed to drop orphaned account states")?;
+
+    if delete_orphans {
+        sql_query("DELETE FROM accounts WHERE orphaned AND slot = ANY($1)")
+            .bind::<Array<BigInt>, _>(slots)
+            .execute(conn)
+            .context("Failed to delete orphaned accounts")?;
+    }
+
+    Ok(())
+}
+
+// a slot with its parent, as recorded by the fork tracker
+#[derive(QueryableByName)]
+struct SlotLink {
+    #[diesel(sql_type = BigInt)]
+    slot: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    parent: Option<i64>,
+}
+
+// id, slot and signature of a transaction merged from the staging table
+#[derive(QueryableByName)]
+struct MergedTransaction {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+    #[diesel(sql_type = BigInt)]
+    slot: i64,
+    #[diesel(sql_type = Bytea)]
+    signature: Vec<u8>,
+}
+
+// COPY can't resolve conflicts, so the rows are copied into a temp table
+// first and merged from pg_temp.<table>_stage into public.<table>. the COPY
+// diesel emits names the real table without a schema, so the temp table is
+// created under that name, where it comes first in the search path, and
+// renamed right after the copy. nothing but the COPY ever sees it in place of
+// the real table
+fn stage<T>(
+    conn: &mut PgConnection,
+    table: &str,
+    copy: impl FnOnce(&mut PgConnection) -> Result<usize>,
+    merge: impl FnOnce(&mut PgConnection) -> Result<T>,
+) -> Result<T> {
+    sql_query(format!(
+        "CREATE TEMP TABLE {table} (LIKE public.{table} INCLUDING DEFAULTS) ON COMMIT DROP"
+    ))
+    .execute(conn)
+    .with_context(|| format!("Failed to create staging table for {}", table))?;
+
+    copy(conn)?;
+    sql_query(format!(
+        "ALTER TABLE pg_temp.{table} RENAME TO {table}_stage"
+    ))
+    .execute(conn)
+    .with_context(|| format!("Failed to rename staging table for {}", table))?;
+
+    let merged = merge(conn)?;
+
+    sql_query(format!("DROP TABLE pg_temp.{table}_stage"))
+        .execute(conn)
+        .with_context(|| format!("Failed to drop staging table for {}", table))?;
+
+    Ok(merged)
+}
+
+// child rows of a batch of transactions, linked to the ids the transactions got
+#[derive(Default)]
+struct TransactionChildren {
+    instructions: Vec<NewTransactionInstruction>,
+    lookups: Vec<NewTransactionAddressTableLookup>,
+    inner_instructions: Vec<NewTransactionInnerInstruction>,
+    token_balances: Vec<NewTransactionTokenBalance>,
+    rewards: Vec<NewTransactionReward>,
+}
+
+impl TransactionChildren {
+    // `ids` only holds the transactions which were actually inserted, keyed by
+    // slot and signature, duplicates of a batch are linked once
+    fn link(
+        mut ids: HashMap<(i64, Vec<u8>), i64>,
+        txs: &[(NewTransaction, &TransactionUpdateInfo)],
+    ) -> Self {
+        let mut children = Self::default();
+
+        for (tx, info) in txs {
+            let Some(transaction_id) = ids.remove(&(tx.slot, tx.signature.clone())) else {
+                continue;
+            };
+
+            if let Some(message) = &info.message {
+                children
+                    .instructions
+                    .extend(NewTransactionInstruction::from_message(
+                        transaction_id,
+                        message,
+                    ));
+                children
+                    .lookups
+                    .extend(NewTransactionAddressTableLookup::from_message(
+                        transaction_id,
+                        message,
+                    ));
+            }
+            children
+                .inner_instructions
+                .extend(NewTransactionInnerInstruction::from_info(
+                    transaction_id,
+                    info,
+                ));
+            if let Some(meta) = &info.meta {
+                children
+                    .token_balances
+                    .extend(NewTransactionTokenBalance::from_meta(transaction_id, meta));
+                children
+                    .rewards
+                    .extend(NewTransactionReward::from_meta(transaction_id, meta));
+            }
+        }
+
+        children
+    }
 }
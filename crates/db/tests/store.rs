@@ -0,0 +1,800 @@
+// runs the store against the database of DATABASE_URL, migrated with
+// `diesel migration run`. every test writes its own range of slots far above
+// anything on chain and deletes it again. without DATABASE_URL the tests
+// are skipped
+//
+//     DATABASE_URL=postgres://... cargo test -p db --test store
+
+use std::env;
+
+use core::{
+    AccountInfo, AccountUpdate, AddressTableLookup, CompiledInstruction, InnerInstruction,
+    InnerInstructions, MessageVersion, ReturnData, Reward, RewardType, SlotStatus, TokenBalance,
+    TransactionMessage, TransactionMeta, TransactionUpdateInfo,
+};
+use db::{
+    models::{
+        NewAccount, NewAccountState, NewBlock, NewEntry, NewReward, NewSlot, NewTransaction,
+        NewTransactionStatus, TokenAmount,
+    },
+    store::Store,
+};
+use diesel::{
+    QueryableByName, RunQueryDsl,
+    pg::PgConnection,
+    r2d2::{ConnectionManager, Pool},
+    sql_query,
+    sql_types::{Array, BigInt, Binary, Bool, Integer, Nullable, Numeric, Text},
+};
+
+// first slot used by the test rows
+const TEST_SLOT: u64 = 910_000_000_000;
+
+// a store on DATABASE_URL, none when it isn't set
+fn store() -> Option<Store> {
+    let Ok(db_url) = env::var("DATABASE_URL") else {
+        eprintln!("⚠️ DATABASE_URL not set, skipping");
+        return None;
+    };
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(ConnectionManager::<PgConnection>::new(db_url))
+        .expect("Failed to create database connection pool");
+    Some(Store::new(pool))
+}
+
+// deletes the rows of the slots `from..from + 1000`, child rows go with
+// their transactions through the cascading foreign keys
+fn cleanup(store: &Store, from: u64) {
+    let mut conn = store.pool.get().unwrap();
+    for table in [
+        "transactions",
+        "transaction_statuses",
+        "blocks",
+        "entries",
+        "accounts",
+        "account_state",
+        "slot_commitments",
+        "rewards",
+    ] {
+        sql_query(format!(
+            "DELETE FROM {} WHERE slot >= {} AND slot < {}",
+            table,
+            from,
+            from + 1000
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    }
+}
+
+fn transaction(slot: u64, amount: u64) -> (NewTransaction, TransactionUpdateInfo) {
+    let token_balance = TokenBalance {
+        account_index: 1,
+        mint: "So11111111111111111111111111111111111111112".to_owned(),
+        owner: "11111111111111111111111111111111".to_owned(),
+        program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".to_owned(),
+        amount,
+        decimals: 0,
+        ui_amount: amount as f64,
+    };
+    let signature = [slot.to_le_bytes().as_slice(), &[7u8; 56]].concat();
+    let info = TransactionUpdateInfo {
+        signature: signature.clone(),
+        is_vote: false,
+        index: 0,
+        signatures: vec![signature],
+        message: None,
+        meta: Some(TransactionMeta {
+            err: None,
+            fee: 5000,
+            compute_units_consumed: None,
+            pre_balances: vec![],
+            post_balances: vec![],
+            inner_instructions: vec![],
+            log_messages: vec![],
+            pre_token_balance: vec![token_balance.clone()],
+            post_token_balance: vec![token_balance],
+            rewards: vec![reward("11111111111111111111111111111111", RewardType::Fee)],
+            loaded_writable_addresses: vec![],
+            loaded_readonly_addresses: vec![],
+            return_data: None,
+        }),
+    };
+    let amounts = Some(vec![Some(TokenAmount(amount))]);
+    let tx = NewTransaction::new(
+        slot as i64,
+        info.signature.clone(),
+        false,
+        0,
+        Some(5000),
+        None,
+        None,
+        None,
+        None,
+        amounts.clone(),
+        amounts,
+    );
+    (tx, info)
+}
+
+#[derive(QueryableByName)]
+struct StoredAmounts {
+    #[diesel(sql_type = Nullable<Numeric>)]
+    amount: Option<TokenAmount>,
+    #[diesel(sql_type = Text)]
+    amount_text: String,
+    #[diesel(sql_type = Array<Nullable<Numeric>>)]
+    pre_token_balances: Vec<Option<TokenAmount>>,
+}
+
+fn stored_amounts(store: &Store, slot: u64) -> Vec<StoredAmounts> {
+    let mut conn = store.pool.get().unwrap();
+    sql_query(format!(
+        "SELECT b.amount, b.amount::text AS amount_text, t.pre_token_balances
+         FROM transactions t JOIN transaction_token_balances b ON b.transaction_id = t.id
+         WHERE t.slot = {}",
+        slot
+    ))
+    .load(&mut conn)
+    .unwrap()
+}
+
+#[test]
+fn token_amounts_above_i64_are_kept() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT;
+    cleanup(&store, from);
+
+    for (slot, amount) in [(from, u64::MAX), (from + 1, 0), (from + 2, 10_000_000_000)] {
+        let (tx, info) = transaction(slot, amount);
+        store.insert_full_transactions(&[(tx, &info)]).unwrap();
+        let stored = stored_amounts(&store, slot);
+        assert_eq!(stored.len(), 2);
+        for stored in stored {
+            assert_eq!(stored.amount, Some(TokenAmount(amount)));
+            assert_eq!(stored.amount_text, amount.to_string());
+            assert_eq!(stored.pre_token_balances, vec![Some(TokenAmount(amount))]);
+        }
+    }
+
+    // the COPY path writes the same values
+    let (tx, info) = transaction(from + 3, u64::MAX);
+    store.copy_full_transactions(&[(tx, &info)]).unwrap();
+    let stored = stored_amounts(&store, from + 3);
+    assert_eq!(stored.len(), 2);
+    assert!(stored.iter().all(|s| s.amount_text == u64::MAX.to_string()));
+
+    cleanup(&store, from);
+}
+
+#[test]
+fn redelivered_transaction_statuses_are_skipped() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 1000;
+    cleanup(&store, from);
+
+    let status = |slot: u64, sig: u8| {
+        NewTransactionStatus::new(slot as i64, vec![sig; 64], false, sig as i32, None)
+    };
+    let batch = [status(from, 1), status(from, 2), status(from + 1, 1)];
+    assert_eq!(store.insert_transaction_statuses(&batch).unwrap(), 3);
+    // a redelivered batch, with a duplicate inside it
+    let redelivered = [status(from, 1), status(from, 1), status(from + 1, 2)];
+    assert_eq!(store.insert_transaction_statuses(&redelivered).unwrap(), 1);
+
+    cleanup(&store, from);
+}
+
+#[test]
+fn copied_accounts_are_merged_once() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 6000;
+    cleanup(&store, from);
+
+    let account = |write_version: i64| NewAccount {
+        slot: from as i64,
+        is_startup: false,
+        pubkey: vec![6; 32],
+        lamports: 1,
+        owner: vec![0; 32],
+        executable: false,
+        rent_epoch: 0,
+        data: vec![],
+        write_version,
+        txn_signature: None,
+    };
+    assert_eq!(store.copy_accounts(&[account(1), account(2)]).unwrap(), 2);
+    // the staging table is gone with the transaction, a second copy gets a
+    // fresh one
+    assert_eq!(store.copy_accounts(&[account(2), account(3)]).unwrap(), 1);
+
+    let mut conn = store.pool.get().unwrap();
+    let rows: Vec<StoredLamports> = sql_query(format!(
+        "SELECT lamports FROM accounts WHERE slot = {}",
+        from
+    ))
+    .load(&mut conn)
+    .unwrap();
+    assert_eq!(rows.len(), 3);
+    assert!(rows.iter().all(|row| row.lamports == 1));
+
+    cleanup(&store, from);
+}
+
+// the state of an account of its own for every test, `key` fills the pubkey
+fn account_state(key: u8, slot: u64, write_version: i64, lamports: i64) -> NewAccountState {
+    NewAccountState {
+        pubkey: vec![key; 32],
+        slot: slot as i64,
+        is_startup: false,
+        lamports,
+        owner: vec![0; 32],
+        executable: false,
+        rent_epoch: 0,
+        data: vec![],
+        write_version,
+        txn_signature: None,
+    }
+}
+
+#[derive(QueryableByName)]
+struct StoredLamports {
+    #[diesel(sql_type = BigInt)]
+    lamports: i64,
+}
+
+fn stored_lamports(store: &Store, key: u8) -> i64 {
+    let mut conn = store.pool.get().unwrap();
+    let rows: Vec<StoredLamports> = sql_query(format!(
+        "SELECT lamports FROM account_state WHERE pubkey = decode(repeat('{:02x}', 32), 'hex')",
+        key
+    ))
+    .load(&mut conn)
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    rows[0].lamports
+}
+
+#[test]
+fn account_states_keep_the_newest_write() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 2000;
+    cleanup(&store, from);
+
+    store
+        .upsert_account_states(&[account_state(1, from + 5, 10, 1)])
+        .unwrap();
+
+    // an older slot arriving late doesn't overwrite, whatever its write version
+    store
+        .upsert_account_states(&[account_state(1, from + 4, 20, 2)])
+        .unwrap();
+    assert_eq!(stored_lamports(&store, 1), 1);
+
+    // neither does an older write of the same slot
+    store
+        .upsert_account_states(&[account_state(1, from + 5, 9, 3)])
+        .unwrap();
+    assert_eq!(stored_lamports(&store, 1), 1);
+
+    // a later write of the same slot does
+    store
+        .upsert_account_states(&[account_state(1, from + 5, 11, 4)])
+        .unwrap();
+    assert_eq!(stored_lamports(&store, 1), 4);
+
+    // as does a later slot with a lower write version
+    store
+        .upsert_account_states(&[account_state(1, from + 6, 1, 5)])
+        .unwrap();
+    assert_eq!(stored_lamports(&store, 1), 5);
+
+    cleanup(&store, from);
+}
+
+#[test]
+fn account_states_of_one_batch_keep_the_newest_write() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 3000;
+    cleanup(&store, from);
+
+    let batch = [
+        account_state(2, from + 1, 7, 1),
+        account_state(2, from + 1, 8, 2),
+        account_state(2, from, 9, 3),
+    ];
+    assert_eq!(store.upsert_account_states(&batch).unwrap(), 1);
+    assert_eq!(stored_lamports(&store, 2), 2);
+
+    cleanup(&store, from);
+}
+
+#[derive(QueryableByName)]
+struct TrackedSlot {
+    #[diesel(sql_type = BigInt)]
+    slot: i64,
+    #[diesel(sql_type = Text)]
+    commitment: String,
+    #[diesel(sql_type = Bool)]
+    orphaned: bool,
+}
+
+// slot, commitment and whether it was orphaned, relative to `from`
+fn tracked_slots(store: &Store, from: u64) -> Vec<(u64, String, bool)> {
+    let mut conn = store.pool.get().unwrap();
+    let rows: Vec<TrackedSlot> = sql_query(format!(
+        "SELECT slot, commitment::text AS commitment, orphaned FROM slot_commitments \
+         WHERE slot >= {} AND slot < {} ORDER BY slot",
+        from,
+        from + 1000
+    ))
+    .load(&mut conn)
+    .unwrap();
+    rows.into_iter()
+        .map(|r| (r.slot as u64 - from, r.commitment, r.orphaned))
+        .collect()
+}
+
+#[test]
+fn finalizing_orphans_only_slots_known_to_be_on_another_fork() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 4000;
+    cleanup(&store, from);
+
+    let slot = |n: u64, parent: Option<u64>, status: SlotStatus| {
+        NewSlot::new(
+            (from + n) as i64,
+            parent.map(|p| (from + p) as i64),
+            status,
+            None,
+        )
+    };
+    let processed = |n: u64, parent: Option<u64>| slot(n, parent, SlotStatus::Processed);
+    store
+        .track_slots(&[
+            // 5 -> 3 -> 1 -> 0, with 2 and 4 on other forks
+            processed(0, None),
+            processed(1, Some(0)),
+            processed(2, Some(1)),
+            processed(3, Some(1)),
+            processed(4, None),
+            processed(5, Some(3)),
+            // 10 -> 8, which was never seen, so the chain below 8 is unknown
+            processed(7, Some(6)),
+            processed(9, Some(7)),
+            processed(10, Some(8)),
+        ])
+        .unwrap();
+
+    // a slot orphaned before it turned out to be an ancestor is taken back
+    let mut conn = store.pool.get().unwrap();
+    sql_query(format!(
+        "UPDATE slot_commitments SET orphaned = true WHERE slot = {}",
+        from + 3
+    ))
+    .execute(&mut conn)
+    .unwrap();
+
+    store
+        .track_slots(&[slot(5, Some(3), SlotStatus::Finalized)])
+        .unwrap();
+    store
+        .track_slots(&[slot(10, Some(8), SlotStatus::Finalized)])
+        .unwrap();
+
+    let state = |n: u64, commitment: &str, orphaned: bool| (n, commitment.to_owned(), orphaned);
+    assert_eq!(
+        tracked_slots(&store, from),
+        vec![
+            state(0, "finalized", false),
+            state(1, "finalized", false),
+            state(2, "processed", true),
+            state(3, "finalized", false),
+            state(4, "processed", true),
+            state(5, "finalized", false),
+            state(7, "processed", false),
+            state(9, "processed", true),
+            state(10, "finalized", false),
+        ]
+    );
+
+    cleanup(&store, from);
+}
+
+fn reward(pubkey: &str, reward_type: RewardType) -> Reward {
+    Reward {
+        pubkey: pubkey.to_owned(),
+        lamports: 1000,
+        post_balance: 2000,
+        reward_type,
+        commission: None,
+    }
+}
+
+#[test]
+fn rewards_of_every_type_are_kept() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 5000;
+    cleanup(&store, from);
+
+    let validator = "Vote111111111111111111111111111111111111111";
+    let rewards = NewReward::from_block(
+        from,
+        &[
+            reward(validator, RewardType::Fee),
+            reward(validator, RewardType::Rent),
+            reward(validator, RewardType::Voting),
+        ],
+    );
+    assert_eq!(store.insert_rewards(&rewards).unwrap(), 3);
+    // the block meta of the slot carries the same rewards
+    assert_eq!(store.insert_rewards(&rewards).unwrap(), 0);
+
+    cleanup(&store, from);
+}
+
+// a v0 message calling the program at key 3 twice, with one lookup table
+fn message() -> TransactionMessage {
+    let instruction = |data: u8| CompiledInstruction {
+        program_id_index: 3,
+        accounts: vec![0, 1, 4],
+        data: vec![data],
+    };
+    TransactionMessage {
+        version: MessageVersion::V0,
+        header: None,
+        account_keys: (0..4).map(|key| vec![key; 32]).collect(),
+        recent_blockhash: vec![9; 32],
+        instructions: vec![instruction(1), instruction(2)],
+        address_table_lookups: vec![AddressTableLookup {
+            account_key: vec![8; 32],
+            writable_indexes: vec![0],
+            readonly_indexes: vec![1, 2],
+        }],
+    }
+}
+
+#[derive(QueryableByName)]
+struct StoredInstruction {
+    #[diesel(sql_type = Integer)]
+    idx: i32,
+    #[diesel(sql_type = Nullable<Binary>)]
+    program_id: Option<Vec<u8>>,
+    #[diesel(sql_type = Binary)]
+    data: Vec<u8>,
+}
+
+#[derive(QueryableByName)]
+struct StoredLookup {
+    #[diesel(sql_type = Binary)]
+    account_key: Vec<u8>,
+    #[diesel(sql_type = Binary)]
+    writable_indexes: Vec<u8>,
+    #[diesel(sql_type = Binary)]
+    readonly_indexes: Vec<u8>,
+}
+
+#[test]
+fn instructions_and_lookups_are_stored() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 7000;
+    cleanup(&store, from);
+
+    for slot in [from, from + 1] {
+        let (tx, mut info) = transaction(slot, 1);
+        info.message = Some(message());
+        let tx = tx.with_message(info.signatures.clone(), info.message.as_ref());
+        // the second slot goes through the COPY path
+        if slot == from {
+            store.insert_full_transactions(&[(tx, &info)]).unwrap();
+        } else {
+            store.copy_full_transactions(&[(tx, &info)]).unwrap();
+        }
+
+        let mut conn = store.pool.get().unwrap();
+        let instructions: Vec<StoredInstruction> = sql_query(format!(
+            "SELECT i.idx, i.program_id, i.data
+             FROM transactions t JOIN transaction_instructions i ON i.transaction_id = t.id
+             WHERE t.slot = {} AND t.versioned ORDER BY i.idx",
+            slot
+        ))
+        .load(&mut conn)
+        .unwrap();
+        assert_eq!(instructions.len(), 2);
+        for (idx, instruction) in instructions.iter().enumerate() {
+            assert_eq!(instruction.idx, idx as i32);
+            assert_eq!(instruction.program_id, Some(vec![3; 32]));
+            assert_eq!(instruction.data, vec![idx as u8 + 1]);
+        }
+
+        let lookups: Vec<StoredLookup> = sql_query(format!(
+            "SELECT l.account_key, l.writable_indexes, l.readonly_indexes
+             FROM transactions t JOIN transaction_address_table_lookups l ON l.transaction_id = t.id
+             WHERE t.slot = {}",
+            slot
+        ))
+        .load(&mut conn)
+        .unwrap();
+        assert_eq!(lookups.len(), 1);
+        assert_eq!(lookups[0].account_key, vec![8; 32]);
+        assert_eq!(lookups[0].writable_indexes, vec![0]);
+        assert_eq!(lookups[0].readonly_indexes, vec![1, 2]);
+    }
+
+    cleanup(&store, from);
+}
+
+#[derive(QueryableByName)]
+struct StoredStatus {
+    #[diesel(sql_type = Nullable<Text>)]
+    err: Option<String>,
+    #[diesel(sql_type = Array<Nullable<Binary>>)]
+    loaded_writable_addresses: Vec<Option<Vec<u8>>>,
+    #[diesel(sql_type = Nullable<Binary>)]
+    return_data_program_id: Option<Vec<u8>>,
+    #[diesel(sql_type = Nullable<Binary>)]
+    return_data: Option<Vec<u8>>,
+    #[diesel(sql_type = BigInt)]
+    rewards: i64,
+}
+
+#[derive(QueryableByName)]
+struct StoredInnerInstruction {
+    #[diesel(sql_type = Integer)]
+    instruction_idx: i32,
+    #[diesel(sql_type = Nullable<Binary>)]
+    program_id: Option<Vec<u8>>,
+    #[diesel(sql_type = Nullable<Integer>)]
+    stack_height: Option<i32>,
+}
+
+#[test]
+fn failed_transactions_keep_their_inner_instructions_and_return_data() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 8000;
+    cleanup(&store, from);
+
+    for slot in [from, from + 1] {
+        let (tx, mut info) = transaction(slot, 1);
+        info.message = Some(message());
+        let meta = info.meta.as_mut().unwrap();
+        meta.err = Some("Error processing Instruction 1: custom program error: 0x6".to_owned());
+        // the CPI invokes the program at the first loaded key, right after
+        // the four static ones
+        meta.inner_instructions = vec![InnerInstructions {
+            index: 1,
+            instructions: vec![InnerInstruction {
+                program_id_index: 4,
+                accounts: vec![0],
+                data: vec![],
+                stack_height: Some(2),
+            }],
+        }];
+        meta.loaded_writable_addresses = vec![vec![4; 32]];
+        meta.return_data = Some(ReturnData {
+            program_id: vec![4; 32],
+            data: vec![1, 2, 3],
+        });
+        let tx = tx
+            .with_message(info.signatures.clone(), info.message.as_ref())
+            .with_status(info.meta.as_ref().unwrap());
+        // the second slot goes through the COPY path
+        if slot == from {
+            store.insert_full_transactions(&[(tx, &info)]).unwrap();
+        } else {
+            store.copy_full_transactions(&[(tx, &info)]).unwrap();
+        }
+
+        let mut conn = store.pool.get().unwrap();
+        let statuses: Vec<StoredStatus> = sql_query(format!(
+            "SELECT t.err, t.loaded_writable_addresses, t.return_data_program_id, t.return_data,
+                    (SELECT count(*) FROM transaction_rewards r WHERE r.transaction_id = t.id) AS rewards
+             FROM transactions t WHERE t.slot = {}",
+            slot
+        ))
+        .load(&mut conn)
+        .unwrap();
+        let [status] = statuses.as_slice() else {
+            panic!("expected one transaction");
+        };
+        assert_eq!(
+            status.err.as_deref(),
+            Some("Error processing Instruction 1: custom program error: 0x6")
+        );
+        assert_eq!(status.loaded_writable_addresses, vec![Some(vec![4; 32])]);
+        assert_eq!(status.return_data_program_id, Some(vec![4; 32]));
+        assert_eq!(status.return_data, Some(vec![1, 2, 3]));
+        assert_eq!(status.rewards, 1);
+
+        let inner: Vec<StoredInnerInstruction> = sql_query(format!(
+            "SELECT i.instruction_idx, i.program_id, i.stack_height
+             FROM transactions t JOIN transaction_inner_instructions i ON i.transaction_id = t.id
+             WHERE t.slot = {}",
+            slot
+        ))
+        .load(&mut conn)
+        .unwrap();
+        let [inner] = inner.as_slice() else {
+            panic!("expected one inner instruction");
+        };
+        assert_eq!(inner.instruction_idx, 1);
+        assert_eq!(inner.program_id, Some(vec![4; 32]));
+        assert_eq!(inner.stack_height, Some(2));
+    }
+
+    cleanup(&store, from);
+}
+
+#[derive(QueryableByName)]
+struct StoredBlock {
+    #[diesel(sql_type = Nullable<BigInt>)]
+    updated_account_count: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    block_time: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    block_height: Option<i64>,
+}
+
+fn stored_block(store: &Store, slot: u64) -> StoredBlock {
+    let mut conn = store.pool.get().unwrap();
+    let mut rows: Vec<StoredBlock> = sql_query(format!(
+        "SELECT updated_account_count, block_time, block_height FROM blocks WHERE slot = {}",
+        slot
+    ))
+    .load(&mut conn)
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    rows.remove(0)
+}
+
+#[test]
+fn block_metas_and_full_blocks_fill_in_the_same_row() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 9000;
+    cleanup(&store, from);
+
+    let block = |updated_account_count: Option<i64>, block_time: Option<i64>| {
+        NewBlock::new(
+            from as i64,
+            "hash".to_owned(),
+            from as i64 - 1,
+            "parent".to_owned(),
+            3,
+            updated_account_count,
+            2,
+            block_time,
+            Some(40),
+        )
+    };
+    // the meta doesn't know the account count
+    store
+        .insert_blocks(&[block(None, Some(1_700_000_000))])
+        .unwrap();
+    let stored = stored_block(&store, from);
+    assert_eq!(stored.updated_account_count, None);
+    assert_eq!(stored.block_time, Some(1_700_000_000));
+    assert_eq!(stored.block_height, Some(40));
+
+    // a full block without a time keeps the one of the meta
+    store.insert_blocks(&[block(Some(5), None)]).unwrap();
+    let stored = stored_block(&store, from);
+    assert_eq!(stored.updated_account_count, Some(5));
+    assert_eq!(stored.block_time, Some(1_700_000_000));
+
+    // a late meta doesn't wipe the account count of the full block
+    store
+        .insert_blocks(&[block(None, Some(1_700_000_000))])
+        .unwrap();
+    assert_eq!(stored_block(&store, from).updated_account_count, Some(5));
+
+    cleanup(&store, from);
+}
+
+#[derive(QueryableByName)]
+struct StoredError {
+    #[diesel(sql_type = Nullable<Text>)]
+    err: Option<String>,
+}
+
+#[test]
+fn transaction_statuses_keep_their_error() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 10_000;
+    cleanup(&store, from);
+
+    let err = "Error processing Instruction 1: custom program error: 0x6";
+    let statuses = [
+        NewTransactionStatus::new(from as i64, vec![1; 64], false, 0, None),
+        NewTransactionStatus::new(from as i64, vec![2; 64], false, 1, Some(err.to_owned())),
+    ];
+    assert_eq!(store.insert_transaction_statuses(&statuses).unwrap(), 2);
+
+    let mut conn = store.pool.get().unwrap();
+    let stored: Vec<StoredError> = sql_query(format!(
+        "SELECT err FROM transaction_statuses WHERE slot = {} ORDER BY idx",
+        from
+    ))
+    .load(&mut conn)
+    .unwrap();
+    let errs: Vec<_> = stored.iter().map(|s| s.err.as_deref()).collect();
+    assert_eq!(errs, [None, Some(err)]);
+
+    cleanup(&store, from);
+}
+
+#[test]
+fn redelivered_entries_are_skipped() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 11_000;
+    cleanup(&store, from);
+
+    let entry = |idx: i64| NewEntry::new(from as i64, idx, 1, vec![idx as u8; 32], 0, 0);
+    assert_eq!(store.insert_entries(&[entry(0), entry(1)]).unwrap(), 2);
+    assert_eq!(store.insert_entries(&[entry(1), entry(2)]).unwrap(), 1);
+
+    cleanup(&store, from);
+}
+
+#[derive(QueryableByName)]
+struct StoredWrite {
+    #[diesel(sql_type = BigInt)]
+    slot: i64,
+    #[diesel(sql_type = Bool)]
+    is_startup: bool,
+}
+
+#[test]
+fn account_writes_keep_their_slot_and_startup_flag() {
+    let Some(store) = store() else { return };
+    let from = TEST_SLOT + 12_000;
+    cleanup(&store, from);
+
+    let update = AccountUpdate {
+        slot: from + 1,
+        is_startup: true,
+        info: AccountInfo {
+            pubkey: vec![12; 32],
+            lamports: 1,
+            owner: vec![0; 32],
+            executable: false,
+            rent_epoch: 0,
+            data: vec![],
+            write_version: 1,
+            txn_signature: None,
+        },
+    };
+    store
+        .upsert_account_states(&[NewAccountState::from(&update)])
+        .unwrap();
+    let account = NewAccount::new(
+        update.slot as i64,
+        update.is_startup,
+        update.info.pubkey.clone(),
+        1,
+        vec![0; 32],
+        false,
+        0,
+        vec![],
+        1,
+        None,
+    );
+    store.copy_accounts(&[account]).unwrap();
+
+    let mut conn = store.pool.get().unwrap();
+    for table in ["accounts", "account_state"] {
+        let rows: Vec<StoredWrite> = sql_query(format!(
+            "SELECT slot, is_startup FROM {} WHERE pubkey = decode(repeat('0c', 32), 'hex')",
+            table
+        ))
+        .load(&mut conn)
+        .unwrap();
+        let [row] = rows.as_slice() else {
+            panic!("expected one row in {}", table);
+        };
+        assert_eq!(row.slot, (from + 1) as i64, "{}", table);
+        assert!(row.is_startup, "{}", table);
+    }
+
+    cleanup(&store, from);
+}
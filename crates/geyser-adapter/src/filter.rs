@@ -1,17 +1,22 @@
 use std::{collections::HashMap, fs};
 
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use yellowstone_grpc_proto::geyser::{
-    SubscribeRequest, SubscribeRequestAccountsDataSlice, SubscribeRequestFilterAccounts,
-    SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp,
-    SubscribeRequestFilterBlocks, SubscribeRequestFilterSlots, SubscribeRequestFilterTransactions,
+    CommitmentLevel, SubscribeRequest, SubscribeRequestAccountsDataSlice,
+    SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter,
+    SubscribeRequestFilterAccountsFilterMemcmp, SubscribeRequestFilterBlocks,
+    SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterEntry, SubscribeRequestFilterSlots,
+    SubscribeRequestFilterTransactions,
     subscribe_request_filter_accounts_filter::Filter as AccountsFilterOneof,
     subscribe_request_filter_accounts_filter_memcmp::Data as MemcmpData,
 };
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct TxFilter {
+    /// name of the filter group, carried on every matching message
+    #[serde(default)]
+    pub name: Option<String>,
     pub vote: Option<bool>,
     pub failed: Option<bool>,
     pub account_include: Vec<String>,
@@ -27,6 +32,9 @@ pub struct AccountMemcmp {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Accounts {
+    /// name of the filter group, carried on every matching message
+    #[serde(default)]
+    pub name: Option<String>,
     pub accounts: Vec<String>,
     pub owners: Vec<String>,
     pub filters: Vec<AccountFilter>,
@@ -38,6 +46,76 @@ pub struct AccountFilter {
     pub datasize: Option<u64>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockFilter {
+    /// name of the filter group, carried on every matching message
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub account_include: Vec<String>,
+    #[serde(default)]
+    pub include_transactions: Option<bool>,
+    #[serde(default)]
+    pub include_accounts: Option<bool>,
+    #[serde(default)]
+    pub include_entries: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntryFilter {
+    /// name of the filter group, carried on every matching message
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlotFilter {
+    /// name of the filter group, carried on every matching message
+    #[serde(default)]
+    pub name: Option<String>,
+    /// only send the status updates up to the subscription commitment
+    #[serde(default)]
+    pub filter_by_commitment: Option<bool>,
+    /// also send the statuses reached within a slot (first shred received,
+    /// bank created, completed)
+    #[serde(default)]
+    pub interslot_updates: Option<bool>,
+}
+
+/// Commitment level the geyser streams the updates at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<Commitment> for CommitmentLevel {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Processed => CommitmentLevel::Processed,
+            Commitment::Confirmed => CommitmentLevel::Confirmed,
+            Commitment::Finalized => CommitmentLevel::Finalized,
+        }
+    }
+}
+
+/// Part of the account data to send, the rest is cut off by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct DataSlice {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Limits of the geyser provider, checked by the filter validation
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FilterLimits {
+    /// most pubkeys accepted in a single accounts, owners or account_* list
+    #[serde(default)]
+    pub max_pubkeys: Option<usize>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Filters {
     /// accounts to watch
@@ -46,6 +124,10 @@ pub struct Filters {
     #[serde(default)]
     pub include_blocks: bool,
 
+    /// whether to subscribe to block metadata (hashes, time, height, counts)
+    #[serde(default)]
+    pub include_blocks_meta: bool,
+
     /// slots to watch
     #[serde(default)]
     pub include_slots: bool,
@@ -61,12 +143,157 @@ pub struct Filters {
     #[serde(default)]
     pub block_accounts: Option<Vec<String>>,
 
-    /// Whether to subscribe to transactions and how to filter them
+    /// Whether to subscribe to transactions and how to filter them, a single
+    /// filter or a list of named groups
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub transactions: Vec<TxFilter>,
+
+    /// transaction statuses (signature, slot and error only), filtered like
+    /// the transactions
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub transactions_status: Vec<TxFilter>,
+
+    /// named entry groups, entries can't be filtered any further
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub entry: Vec<EntryFilter>,
+
+    /// named block groups, next to the `include_blocks` flags
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub blocks: Vec<BlockFilter>,
+
+    /// named slot groups, next to the `include_slots` flag
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub slots: Vec<SlotFilter>,
+
+    /// limits of the provider, only used to validate the filters
+    #[serde(default)]
+    pub limits: Option<FilterLimits>,
+
+    /// commitment of the streamed updates, processed when unset
     #[serde(default)]
-    pub transactions: Option<TxFilter>,
+    pub commitment: Option<Commitment>,
+
+    /// parts of the account data to send, the whole data when unset
+    #[serde(default)]
+    pub accounts_data_slice: Option<Vec<DataSlice>>,
+}
+
+// lists are tried first, serde would also read `[]` as a struct with all
+// fields defaulted
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    Many(Vec<T>),
+    One(T),
+}
+
+// accepts a single filter object as well as a list of them
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(match Option::<OneOrMany<T>>::deserialize(deserializer)? {
+        None => vec![],
+        Some(OneOrMany::One(filter)) => vec![filter],
+        Some(OneOrMany::Many(filters)) => filters,
+    })
+}
+
+/// A filter group under the name it is subscribed with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupName {
+    pub name: String,
+    /// where the name comes from in filters.json
+    pub path: String,
+}
+
+// the names of the groups of a kind, groups without a name are keyed by
+// their kind and position in the file
+fn group_names<'a>(kind: &str, names: impl Iterator<Item = &'a Option<String>>) -> Vec<GroupName> {
+    names
+        .enumerate()
+        .map(|(i, name)| match name {
+            Some(name) => GroupName {
+                name: name.clone(),
+                path: format!("{}[{}].name", kind, i),
+            },
+            None => GroupName {
+                name: format!("{}_{}", kind, i),
+                path: format!("{}[{}]", kind, i),
+            },
+        })
+        .collect()
+}
+
+// the group an `include_*` flag adds, named after its kind
+fn flag_group(kind: &str, flag: &str) -> GroupName {
+    GroupName {
+        name: kind.to_owned(),
+        path: flag.to_owned(),
+    }
+}
+
+// transactions and transaction statuses share their filter
+fn transaction_filters(
+    filters: &[TxFilter],
+    kind: &str,
+) -> HashMap<String, SubscribeRequestFilterTransactions> {
+    let names = group_names(kind, filters.iter().map(|tx| &tx.name));
+    names
+        .into_iter()
+        .zip(filters)
+        .map(|(group, tx)| {
+            let filter = SubscribeRequestFilterTransactions {
+                vote: tx.vote,
+                failed: tx.failed,
+                signature: None,
+                account_include: tx.account_include.clone(),
+                account_exclude: tx.account_exclude.clone(),
+                account_required: tx.account_required.clone(),
+            };
+            (group.name, filter)
+        })
+        .collect()
 }
 
 impl Filters {
+    // the names of the groups of every kind in the order they are subscribed,
+    // the generated ones and those of the `include_*` flags included. a name
+    // used twice within a kind leaves only the last of its groups
+    pub fn group_names(&self) -> Vec<(&'static str, Vec<GroupName>)> {
+        let mut blocks = group_names("blocks", self.blocks.iter().map(|b| &b.name));
+        if self.include_blocks {
+            blocks.push(flag_group("blocks", "include_blocks"));
+        }
+        let mut slots = group_names("slots", self.slots.iter().map(|s| &s.name));
+        if self.include_slots {
+            slots.push(flag_group("slots", "include_slots"));
+        }
+        let blocks_meta = match self.include_blocks_meta {
+            true => vec![flag_group("blocks_meta", "include_blocks_meta")],
+            false => vec![],
+        };
+
+        let accounts = group_names("accounts", self.accounts.iter().map(|a| &a.name));
+        let transactions = group_names("transactions", self.transactions.iter().map(|t| &t.name));
+        let transactions_status = group_names(
+            "transactions_status",
+            self.transactions_status.iter().map(|t| &t.name),
+        );
+        let entry = group_names("entry", self.entry.iter().map(|e| &e.name));
+
+        vec![
+            ("accounts", accounts),
+            ("transactions", transactions),
+            ("transactions_status", transactions_status),
+            ("entry", entry),
+            ("blocks", blocks),
+            ("blocks_meta", blocks_meta),
+            ("slots", slots),
+        ]
+    }
+
     // this function loads filters from the json file
     pub fn from_file(path: &str) -> Result<Self> {
         let s: String = fs::read_to_string(path)?;
@@ -74,10 +301,12 @@ impl Filters {
             .map_err(|e| anyhow::anyhow!("Failed to parse filters from file {}: {}", path, e))
     }
 
-    pub fn to_subscribe_request(&self) -> SubscribeRequest {
+    // builds the subscribe request, replaying from `from_slot` when resuming a stream
+    pub fn to_subscribe_request(&self, from_slot: Option<u64>) -> SubscribeRequest {
         // Accounts
         let mut accounts: HashMap<String, SubscribeRequestFilterAccounts> = HashMap::new();
-        for acc in &self.accounts {
+        let names = group_names("accounts", self.accounts.iter().map(|a| &a.name));
+        for (group, acc) in names.into_iter().zip(&self.accounts) {
             let mut filters = vec![];
 
             // add datasize if present
@@ -102,10 +331,10 @@ impl Filters {
             }
 
             accounts.insert(
-                "client".to_owned(),
+                group.name,
                 SubscribeRequestFilterAccounts {
                     account: acc.accounts.clone(),
-                    owner: acc.owners.clone(),     
+                    owner: acc.owners.clone(),
                     filters,
                     nonempty_txn_signature: None,
                 },
@@ -113,26 +342,34 @@ impl Filters {
         }
 
         // Transactions
-        let mut transactions: HashMap<String, SubscribeRequestFilterTransactions> = HashMap::new();
-        if let Some(tx) = &self.transactions {
-            transactions.insert(
-                "client".to_owned(),
-                SubscribeRequestFilterTransactions {
-                    vote: tx.vote,
-                    failed: tx.failed,
-                    signature: None,
-                    account_include: tx.account_include.clone(),
-                    account_exclude: tx.account_exclude.clone(),
-                    account_required: tx.account_required.clone(),
-                },
-            );
-        }
+        let transactions = transaction_filters(&self.transactions, "transactions");
+        let transactions_status =
+            transaction_filters(&self.transactions_status, "transactions_status");
+
+        // entries
+        let entry: HashMap<String, SubscribeRequestFilterEntry> =
+            group_names("entry", self.entry.iter().map(|e| &e.name))
+                .into_iter()
+                .map(|group| (group.name, SubscribeRequestFilterEntry {}))
+                .collect();
 
         // blocks
         let mut blocks: HashMap<String, SubscribeRequestFilterBlocks> = HashMap::new();
+        let names = group_names("blocks", self.blocks.iter().map(|b| &b.name));
+        for (group, block) in names.into_iter().zip(&self.blocks) {
+            blocks.insert(
+                group.name,
+                SubscribeRequestFilterBlocks {
+                    account_include: block.account_include.clone(),
+                    include_accounts: block.include_accounts,
+                    include_entries: block.include_entries,
+                    include_transactions: block.include_transactions,
+                },
+            );
+        }
         if self.include_blocks {
             blocks.insert(
-                "client".to_owned(),
+                flag_group("blocks", "include_blocks").name,
                 SubscribeRequestFilterBlocks {
                     account_include: self.block_accounts.clone().unwrap_or_default(),
                     include_accounts: self.blocks_include_accounts,
@@ -142,10 +379,30 @@ impl Filters {
             );
         }
 
+        // block metas
+        let mut blocks_meta: HashMap<String, SubscribeRequestFilterBlocksMeta> = HashMap::new();
+        if self.include_blocks_meta {
+            blocks_meta.insert(
+                flag_group("blocks_meta", "include_blocks_meta").name,
+                SubscribeRequestFilterBlocksMeta {},
+            );
+        }
+
+        // slots
         let mut slots: HashMap<String, SubscribeRequestFilterSlots> = HashMap::new();
+        let names = group_names("slots", self.slots.iter().map(|s| &s.name));
+        for (group, slot) in names.into_iter().zip(&self.slots) {
+            slots.insert(
+                group.name,
+                SubscribeRequestFilterSlots {
+                    filter_by_commitment: slot.filter_by_commitment,
+                    interslot_updates: slot.interslot_updates,
+                },
+            );
+        }
         if self.include_slots {
             slots.insert(
-                "client".to_owned(),
+                flag_group("slots", "include_slots").name,
                 SubscribeRequestFilterSlots {
                     filter_by_commitment: None,
                     interslot_updates: None,
@@ -153,22 +410,163 @@ impl Filters {
             );
         }
 
+        // the server wants the slices in order
+        let accounts_data_slice = match &self.accounts_data_slice {
+            Some(slices) => {
+                let mut slices: Vec<SubscribeRequestAccountsDataSlice> = slices
+                    .iter()
+                    .map(|slice| SubscribeRequestAccountsDataSlice {
+                        offset: slice.offset,
+                        length: slice.length,
+                    })
+                    .collect();
+                slices.sort_by_key(|slice| slice.offset);
+                slices
+            }
+            None => vec![SubscribeRequestAccountsDataSlice {
+                offset: 0,
+                length: u32::MAX as u64,
+            }],
+        };
+
         // Construct and return the SubscribeRequest
         SubscribeRequest {
             slots,
             accounts,
             blocks,
-            blocks_meta: HashMap::new(),
+            blocks_meta,
             transactions,
-            transactions_status: HashMap::new(),
-            entry: HashMap::new(),
-            accounts_data_slice: vec![SubscribeRequestAccountsDataSlice {
-                offset: 0,
-                length: u32::MAX as u64,
-            }],
-            commitment: None,
-            from_slot: None,
+            transactions_status,
+            entry,
+            accounts_data_slice,
+            commitment: self
+                .commitment
+                .map(|commitment| CommitmentLevel::from(commitment) as i32),
+            from_slot,
             ping: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(filters: serde_json::Value) -> SubscribeRequest {
+        let filters: Filters = serde_json::from_value(filters).unwrap();
+        filters.to_subscribe_request(None)
+    }
+
+    #[test]
+    fn block_metas_are_subscribed_when_included() {
+        let included = request(json!({ "accounts": [], "include_blocks_meta": true }));
+        assert_eq!(
+            included.blocks_meta.keys().collect::<Vec<_>>(),
+            ["blocks_meta"]
+        );
+        assert!(included.blocks.is_empty());
+
+        assert!(request(json!({ "accounts": [] })).blocks_meta.is_empty());
+    }
+
+    #[test]
+    fn commitment_is_sent_when_set() {
+        assert_eq!(request(json!({ "accounts": [] })).commitment, None);
+        for (name, level) in [
+            ("processed", CommitmentLevel::Processed),
+            ("confirmed", CommitmentLevel::Confirmed),
+            ("finalized", CommitmentLevel::Finalized),
+        ] {
+            let request = request(json!({ "accounts": [], "commitment": name }));
+            assert_eq!(request.commitment, Some(level as i32));
+        }
+        assert!(
+            serde_json::from_value::<Filters>(json!({ "accounts": [], "commitment": "max" }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn data_slices_are_sent_in_order() {
+        let request = request(json!({
+            "accounts": [],
+            "accounts_data_slice": [
+                { "offset": 32, "length": 8 },
+                { "offset": 0, "length": 4 },
+            ],
+        }));
+        let slices: Vec<_> = request
+            .accounts_data_slice
+            .iter()
+            .map(|slice| (slice.offset, slice.length))
+            .collect();
+        assert_eq!(slices, [(0, 4), (32, 8)]);
+    }
+
+    #[test]
+    fn whole_account_data_is_sent_without_slices() {
+        let request = request(json!({ "accounts": [] }));
+        let [slice] = request.accounts_data_slice.as_slice() else {
+            panic!("expected one slice");
+        };
+        assert_eq!((slice.offset, slice.length), (0, u32::MAX as u64));
+    }
+
+    #[test]
+    fn slot_options_reach_their_group() {
+        let request = request(json!({
+            "accounts": [],
+            "include_slots": true,
+            "slots": [{ "name": "commitments", "filter_by_commitment": true, "interslot_updates": false }],
+        }));
+        let group = &request.slots["commitments"];
+        assert_eq!(group.filter_by_commitment, Some(true));
+        assert_eq!(group.interslot_updates, Some(false));
+        let flag = &request.slots["slots"];
+        assert_eq!(flag.filter_by_commitment, None);
+        assert_eq!(flag.interslot_updates, None);
+    }
+
+    #[test]
+    fn transaction_status_groups_are_filtered_like_transactions() {
+        let request = request(json!({
+            "accounts": [],
+            "transactions_status": [
+                {
+                    "name": "failed_swaps",
+                    "vote": false,
+                    "failed": true,
+                    "account_include": ["a"],
+                    "account_exclude": ["b"],
+                    "account_required": ["c"],
+                },
+                { "account_include": [], "account_exclude": [], "account_required": [] },
+            ],
+        }));
+        assert!(request.transactions.is_empty());
+        let group = &request.transactions_status["failed_swaps"];
+        assert_eq!(group.vote, Some(false));
+        assert_eq!(group.failed, Some(true));
+        assert_eq!(group.account_include, ["a"]);
+        assert_eq!(group.account_exclude, ["b"]);
+        assert_eq!(group.account_required, ["c"]);
+        assert!(
+            request
+                .transactions_status
+                .contains_key("transactions_status_1")
+        );
+    }
+
+    #[test]
+    fn entry_groups_are_subscribed_by_name() {
+        // a single group doesn't need a list
+        let named = request(json!({ "accounts": [], "entry": { "name": "entries" } }));
+        assert_eq!(named.entry.keys().collect::<Vec<_>>(), ["entries"]);
+
+        let unnamed = request(json!({ "accounts": [], "entry": [{}, {}] }));
+        let mut names: Vec<_> = unnamed.entry.keys().collect();
+        names.sort();
+        assert_eq!(names, ["entry_0", "entry_1"]);
+    }
+}
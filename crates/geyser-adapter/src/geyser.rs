@@ -1,36 +1,211 @@
-use anyhow::{Context, Result};
-use colored::Colorize;
-use core::{
-    AccountUpdate, BlockUpdate, EntryUpdate, SlotUpdate, TransactionStatusUpdate, TransactionUpdate,
-};
-use futures::StreamExt;
-use redis_adapter::Publisher;
-use serde_json::to_string;
+use anyhow::{Context, Result, bail};
+use core::Update;
+use futures::{SinkExt, StreamExt};
+use std::{fmt, time::Duration};
+use tokio::sync::watch;
+use tonic::{Code, Status};
 // use tonic::transport::Certificate;
-use yellowstone_grpc_client::{ClientTlsConfig, GeyserGrpcClient};
-use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_client::{ClientTlsConfig, GeyserGrpcClient, GeyserGrpcClientError};
+use yellowstone_grpc_proto::geyser::{SubscribeUpdate, subscribe_update::UpdateOneof};
+
+use crate::{
+    filter::Filters,
+    keepalive::{Keepalive, KeepaliveConfig, LatencyStats, SERVER_PING_REPLY_ID, ping_request},
+    queue::{QueueSender, QueuedUpdate},
+};
+
+/// Delay before the first reconnect attempt, doubled after every failed session
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound for the reconnect delay
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Counters kept across sessions
+#[derive(Debug, Default)]
+struct StreamStats {
+    /// malformed updates skipped since the adapter started
+    skipped: u64,
+    /// round trip times of the keepalive pings
+    pong_latency: LatencyStats,
+}
+
+/// Tracks how far into the chain the stream got, so a new subscription can
+/// resume where the previous one stopped
+#[derive(Debug, Default)]
+pub struct SlotTracker {
+    /// highest slot seen in any update, still possibly in flight
+    highest_slot: Option<u64>,
+    /// highest slot for which a later slot has already been seen
+    last_processed_slot: Option<u64>,
+    /// updates received since the last call to `take_progress`
+    received: u64,
+    /// first slot left out after the geyser refused to replay it, the next
+    /// subscription starts at the tip until an update arrives
+    gap_from: Option<u64>,
+}
+
+impl SlotTracker {
+    // records that an update for `slot` has been handled
+    pub fn observe(&mut self, slot: u64) {
+        self.received += 1;
+        if let Some(from) = self.gap_from.take().filter(|&from| from < slot) {
+            eprintln!(
+                "⚠️ Slots {} to {} weren't replayed, their updates are missing",
+                from,
+                slot - 1
+            );
+        }
+        match self.highest_slot {
+            Some(highest) if slot <= highest => {}
+            Some(highest) => {
+                self.last_processed_slot = Some(highest);
+                self.highest_slot = Some(slot);
+            }
+            None => self.highest_slot = Some(slot),
+        }
+    }
+
+    pub fn last_processed_slot(&self) -> Option<u64> {
+        self.last_processed_slot
+    }
+
+    // the slot after the last fully processed one is replayed in full,
+    // as some of its updates may not have arrived before the disconnect
+    pub fn resume_slot(&self) -> Option<u64> {
+        if self.gap_from.is_some() {
+            return None;
+        }
+        self.last_processed_slot.map(|slot| slot + 1)
+    }
+
+    // gives up on replaying the missed slots, the next subscription starts
+    // at the tip. a gap already open keeps its first slot
+    pub fn skip_replay(&mut self) {
+        if self.gap_from.is_none() {
+            self.gap_from = self.last_processed_slot.map(|slot| slot + 1);
+        }
+        if let Some(from) = self.gap_from {
+            eprintln!(
+                "⚠️ Slots from {} to the tip won't be replayed, the gap is logged once the stream resumes",
+                from
+            );
+        }
+    }
+
+    // returns whether any update arrived since the previous call
+    pub fn take_progress(&mut self) -> bool {
+        std::mem::take(&mut self.received) > 0
+    }
+}
+
+/// The geyser refused to replay the stream from the requested slot, most
+/// likely because it is older than what the geyser keeps
+#[derive(Debug)]
+struct ReplayRejected {
+    from_slot: u64,
+    status: Status,
+}
+
+impl fmt::Display for ReplayRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The geyser can't replay from slot {}: {}",
+            self.from_slot, self.status
+        )
+    }
+}
+
+impl std::error::Error for ReplayRejected {}
+
+// whether a failed subscription was the geyser refusing `from_slot`. only
+// an invalid or out of range slot is, anything else (auth, rate limits,
+// server errors) is retried from the same slot after the usual backoff
+fn replay_rejected(from_slot: Option<u64>, status: Status) -> anyhow::Error {
+    match from_slot {
+        Some(from_slot) if matches!(status.code(), Code::InvalidArgument | Code::OutOfRange) => {
+            ReplayRejected { from_slot, status }.into()
+        }
+        _ => status.into(),
+    }
+}
 
-use crate::filter::Filters;
+// supervises the geyser stream, reconnecting with exponential backoff
+// and resuming from the last fully processed slot on every failure. filter
+// changes sent over `filters` are pushed to the running subscription
+pub async fn run_geyser(
+    rpc_url: &str,
+    x_token: Option<String>,
+    mut filters: watch::Receiver<Filters>,
+    queue: QueueSender,
+    keepalive: KeepaliveConfig,
+) -> Result<()> {
+    let mut tracker = SlotTracker::default();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut stats = StreamStats::default();
 
-// Channels we publish to the redis
-pub const CH_ACCOUNTS: &str = "accounts";
-pub const CH_BLOCKS: &str = "blocks";
-pub const CH_TRANSACTIONS: &str = "transactions";
-pub const CH_SLOTS: &str = "slots";
-pub const CH_ENTRIES: &str = "entries";
-pub const CH_TRANSACTION_STATUS: &str = "transaction_status";
+    loop {
+        let session = run_session(
+            rpc_url,
+            x_token.clone(),
+            &mut filters,
+            &queue,
+            &mut tracker,
+            &mut stats,
+            keepalive,
+        );
+        match session.await {
+            Ok(()) => eprintln!("Geyser stream closed by the server"),
+            // retrying the same slot would fail the same way on every
+            // reconnect, so the next session starts at the tip right away
+            Err(e) if e.is::<ReplayRejected>() => {
+                eprintln!("⚠️ {:#}, resubscribing without replay", e);
+                tracker.skip_replay();
+                continue;
+            }
+            Err(e) => eprintln!("Geyser session failed: {:?}", e),
+        }
+        if queue.is_closed() {
+            bail!("The publisher stopped, not reconnecting");
+        }
+
+        // a session that made progress was healthy, so start over with a short delay
+        if tracker.take_progress() {
+            backoff = INITIAL_BACKOFF;
+        }
 
-pub async fn run_geyser<P: Publisher>(
+        eprintln!(
+            "Reconnecting to geyser in {:?} (last processed slot: {:?}, skipped updates: {}, pong latency: {}, publish queue: {})",
+            backoff,
+            tracker.last_processed_slot(),
+            stats.skipped,
+            stats.pong_latency,
+            queue.stats()
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+// runs a single subscription until the stream ends or fails
+async fn run_session(
     rpc_url: &str,
     x_token: Option<String>,
-    filters: &Filters,
-    publisher: P,
+    filters: &mut watch::Receiver<Filters>,
+    queue: &QueueSender,
+    tracker: &mut SlotTracker,
+    stats: &mut StreamStats,
+    keepalive: KeepaliveConfig,
 ) -> Result<()> {
     println!("Getting subscribe request from filters");
-    let request = filters.to_subscribe_request();
+    let request = filters
+        .borrow_and_update()
+        .to_subscribe_request(tracker.resume_slot());
 
-    println!("Connecting to geyser at {}", rpc_url);
-    let tls_config = ClientTlsConfig::new().with_native_roots();
+    let from_slot = request.from_slot;
+    println!(
+        "Connecting to geyser at {} (from slot: {:?})",
+        rpc_url, from_slot
+    );
 
     // this is the optional CA cert loading from env var
     // if you have a custom rpc then you can set the CA_CERT env var to point to the cert file
@@ -41,91 +216,462 @@ pub async fn run_geyser<P: Publisher>(
     // }
 
     // create the connection
-    let builder = GeyserGrpcClient::build_from_shared(rpc_url.to_string())?
-        .x_token(x_token)?
-        .tls_config(tls_config)?;
+    let mut builder = GeyserGrpcClient::build_from_shared(rpc_url.to_string())?.x_token(x_token)?;
 
-    // connect to the geyser
-    let mut client = builder.connect().await?;
+    // plaintext endpoints (like a local geyser) are connected to without tls
+    if rpc_url.starts_with("https") {
+        builder = builder.tls_config(ClientTlsConfig::new().with_native_roots())?;
+    }
 
-    // subscribe to the geyser
-    let (_tx, mut stream) = client
-        .subscribe_with_request(Some(request))
+    // connect to the geyser
+    let mut client = builder
+        .connect()
         .await
-        .expect("Failed to subscribe to the geyser");
+        .context("Failed to connect to the geyser")?;
+
+    // subscribe to the geyser, the sink stays open to push filter changes
+    let (mut tx, mut stream) = match client.subscribe_with_request(Some(request)).await {
+        Ok(subscription) => subscription,
+        Err(GeyserGrpcClientError::TonicStatus(status)) => {
+            return Err(replay_rejected(from_slot, status))
+                .context("Failed to subscribe to the geyser");
+        }
+        Err(e) => return Err(e).context("Failed to subscribe to the geyser"),
+    };
+    // cleared when the filter watcher is gone, so the closed channel isn't polled again
+    let mut reloading = true;
+
+    // client pings keep load balancers from closing an idle stream, and
+    // unanswered ones reveal a connection that died silently
+    let mut pings = Keepalive::new(keepalive);
+    let mut ping_interval = keepalive
+        .ping_interval
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
 
     // processing the stream
-    while let Some(msg) = stream.next().await {
-        let msg = match msg {
-            Ok(msg) => msg,
-            Err(e) => {
-                eprintln!("Error receiving message from geyser: {:?}", e);
-                break;
-            }
-        };
-        match msg.update_oneof {
-            Some(UpdateOneof::Account(a)) => {
-                let update = AccountUpdate::try_from(a)
-                    .map_err(|e| anyhow::anyhow!(e))
-                    .context("Failed to convert AccountUpdate")?;
-                let data = to_string(&update.info)?;
-                println!("Account data: {}", data.green());
-                let _ = publisher.publisher(CH_ACCOUNTS, data.as_bytes()).await;
-            }
-            Some(UpdateOneof::Transaction(tx)) => {
-                let update = TransactionUpdate::try_from(tx)
-                    .map_err(|e| anyhow::anyhow!(e))
-                    .context("Failed to convert TransactionUpdate")?;
-                let data = to_string(&update)?;
-                println!("Received Transaction update {}", data.blue());
-                let _ = publisher.publisher(CH_TRANSACTIONS, data.as_bytes()).await;
-            }
-            Some(UpdateOneof::Slot(s)) => {
-                let update = SlotUpdate::try_from(s)
-                    .map_err(|e| anyhow::anyhow!(e))
-                    .context("Failed to convert SlotUpdate")?;
-                let data = to_string(&update)?;
-                let _ = publisher.publisher(CH_SLOTS, data.as_bytes()).await;
-            }
-            Some(UpdateOneof::Block(b)) => {
-                let update = BlockUpdate::try_from(b)
-                    .map_err(|e| anyhow::anyhow!(e))
-                    .context("Failed to convert BlockUpdate")?;
-                let data = to_string(&update)?;
-                println!("Received Block update {}", data.yellow());
-                let _ = publisher.publisher(CH_BLOCKS, data.as_bytes()).await;
-            }
-            Some(UpdateOneof::Entry(e)) => {
-                println!("Received Entry update {:?}", format!("{:?}", e).cyan());
-                let update = EntryUpdate::try_from(e)
-                    .map_err(|e| anyhow::anyhow!(e))
-                    .context("Failed to convert EntryUpdate")?;
-                let data = to_string(&update)?;
-                let _ = publisher.publisher(CH_ENTRIES, data.as_bytes()).await;
-            }
-            Some(UpdateOneof::TransactionStatus(ts)) => {
-                let update = TransactionStatusUpdate::try_from(ts)
-                    .map_err(|e| anyhow::anyhow!(e))
-                    .context("Failed to convert TransactionStatusUpdate")?;
-                let data = to_string(&update)?;
-                let _ = publisher.publisher(CH_TRANSACTION_STATUS, data.as_bytes()).await;
-            }
-            Some(UpdateOneof::Ping(p)) => {
-                // Handle Ping update
-                eprintln!("Received Ping update: {:?}", p);
+    loop {
+        tokio::select! {
+            msg = stream.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(status)) => {
+                        return Err(replay_rejected(from_slot, status))
+                            .context("Error receiving message from geyser");
+                    }
+                    None => return Ok(()),
+                };
+                match &msg.update_oneof {
+                    // the server pings idle streams and expects an answer
+                    Some(UpdateOneof::Ping(_)) => {
+                        tx.send(ping_request(SERVER_PING_REPLY_ID))
+                            .await
+                            .context("Failed to answer the geyser ping")?;
+                    }
+                    Some(UpdateOneof::Pong(pong)) => {
+                        if let Some(latency) = pings.pong(pong.id) {
+                            stats.pong_latency.record(latency);
+                            println!("🏓 Pong {}: {}", pong.id, stats.pong_latency);
+                        }
+                    }
+                    _ => handle_message(msg, queue, tracker, &mut stats.skipped).await?,
+                }
             }
-            Some(UpdateOneof::Pong(pong)) => {
-                // Handle Pong update
-                eprintln!("Received Pong update: {:?}", pong);
+            _ = async { ping_interval.as_mut().unwrap().tick().await }, if ping_interval.is_some() => {
+                let id = pings.ping()?;
+                tx.send(ping_request(id))
+                    .await
+                    .context("Failed to ping the geyser")?;
             }
-            Some(UpdateOneof::BlockMeta(block_meta)) => {
-                // Handle BlockMeta update
-                eprintln!("Received BlockMeta update: {:?}", block_meta);
-            }
-            None => {
-                eprintln!("Received empty update from geyser");
+            changed = filters.changed(), if reloading => {
+                if changed.is_err() {
+                    reloading = false;
+                    continue;
+                }
+                // a new request replaces the filters of the subscription, the
+                // stream goes on without replaying anything
+                let request = filters.borrow_and_update().to_subscribe_request(None);
+                tx.send(request)
+                    .await
+                    .context("Failed to push the updated filters to the geyser")?;
+                println!("🔄 Pushed the updated filters to the geyser");
             }
         }
     }
+}
+
+async fn handle_message(
+    msg: SubscribeUpdate,
+    queue: &QueueSender,
+    tracker: &mut SlotTracker,
+    skipped: &mut u64,
+) -> Result<()> {
+    let slot = update_slot(&msg.update_oneof);
+    // names of the filter groups the update matched
+    let groups = msg.filters.clone();
+    // the protobuf codec publishes the update as received
+    let raw = queue.keeps_raw().then(|| msg.clone());
+
+    // a malformed update is logged and skipped, it would fail the same
+    // way after a reconnect
+    match Update::try_from(msg) {
+        Ok(update) => {
+            queue
+                .send(QueuedUpdate {
+                    update,
+                    groups,
+                    raw,
+                })
+                .await?
+        }
+        Err(e) => {
+            *skipped += 1;
+            eprintln!("⚠️ Skipping malformed update ({} so far): {}", skipped, e);
+        }
+    }
+
+    if let Some(slot) = slot {
+        tracker.observe(slot);
+    }
     Ok(())
 }
+
+// the slot an update belongs to, if it carries one
+fn update_slot(update: &Option<UpdateOneof>) -> Option<u64> {
+    match update.as_ref()? {
+        UpdateOneof::Account(a) => Some(a.slot),
+        UpdateOneof::Slot(s) => Some(s.slot),
+        UpdateOneof::Transaction(tx) => Some(tx.slot),
+        UpdateOneof::TransactionStatus(ts) => Some(ts.slot),
+        UpdateOneof::Block(b) => Some(b.slot),
+        UpdateOneof::BlockMeta(bm) => Some(bm.slot),
+        UpdateOneof::Entry(e) => Some(e.slot),
+        UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{self, OverflowPolicy, QueueReceiver};
+    use std::{collections::VecDeque, future::Future, pin::Pin, sync::Mutex};
+    use tokio::{net::TcpListener, sync::mpsc, task::JoinHandle, time::timeout};
+    use tonic::{
+        Request, Response, Streaming, codegen::tokio_stream::wrappers::TcpListenerStream,
+        transport::Server,
+    };
+    use yellowstone_grpc_proto::geyser::{
+        GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+        GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+        GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+        PongResponse, SubscribeReplayInfoRequest, SubscribeReplayInfoResponse, SubscribeRequest,
+        SubscribeUpdateSlot,
+        geyser_server::{Geyser, GeyserServer},
+    };
+
+    type Session = Vec<Result<SubscribeUpdate, Status>>;
+    type UpdateStream =
+        Pin<Box<dyn futures::Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+    // what `async_trait` expands the methods of the service to, the macro
+    // can't be used as the `core` crate shadows the one it refers to
+    type RpcFuture<'a, T> = Pin<Box<dyn Future<Output = Result<Response<T>, Status>> + Send + 'a>>;
+
+    // rpcs the adapter doesn't call
+    macro_rules! unimplemented_rpcs {
+        ($($name:ident($request:ty) -> $response:ty;)*) => {
+            $(fn $name<'a, 'b>(&'a self, _: Request<$request>) -> RpcFuture<'b, $response>
+            where
+                'a: 'b,
+            {
+                Box::pin(async { Err(Status::unimplemented("mock")) })
+            })*
+        };
+    }
+
+    /// A geyser answering each subscription with the next scripted session,
+    /// a subscription stays open once its session is streamed unless that
+    /// ended with an error
+    struct MockGeyser {
+        /// every request received, on any subscription
+        requests: mpsc::UnboundedSender<SubscribeRequest>,
+        sessions: Mutex<VecDeque<Session>>,
+    }
+
+    impl Geyser for MockGeyser {
+        type SubscribeStream = UpdateStream;
+
+        fn subscribe<'a, 'b>(
+            &'a self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> RpcFuture<'b, UpdateStream>
+        where
+            'a: 'b,
+        {
+            let mut incoming = request.into_inner();
+            let requests = self.requests.clone();
+            tokio::spawn(async move {
+                while let Some(Ok(request)) = incoming.next().await {
+                    let _ = requests.send(request);
+                }
+            });
+            let session = self
+                .sessions
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_default();
+            let stream: UpdateStream =
+                Box::pin(futures::stream::iter(session).chain(futures::stream::pending()));
+            Box::pin(async { Ok(Response::new(stream)) })
+        }
+
+        unimplemented_rpcs! {
+            subscribe_replay_info(SubscribeReplayInfoRequest) -> SubscribeReplayInfoResponse;
+            ping(PingRequest) -> PongResponse;
+            get_latest_blockhash(GetLatestBlockhashRequest) -> GetLatestBlockhashResponse;
+            get_block_height(GetBlockHeightRequest) -> GetBlockHeightResponse;
+            get_slot(GetSlotRequest) -> GetSlotResponse;
+            is_blockhash_valid(IsBlockhashValidRequest) -> IsBlockhashValidResponse;
+            get_version(GetVersionRequest) -> GetVersionResponse;
+        }
+    }
+
+    /// The adapter streaming from a mock geyser
+    struct Harness {
+        requests: mpsc::UnboundedReceiver<SubscribeRequest>,
+        /// reloads the filters of the adapter
+        filters: watch::Sender<Filters>,
+        // kept so the queue stays open
+        _queue: QueueReceiver,
+        geyser: JoinHandle<Result<()>>,
+    }
+
+    impl Harness {
+        async fn start(sessions: Vec<Session>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let (requests_tx, requests) = mpsc::unbounded_channel();
+            let mock = MockGeyser {
+                requests: requests_tx,
+                sessions: Mutex::new(sessions.into()),
+            };
+            tokio::spawn(
+                Server::builder()
+                    .add_service(GeyserServer::new(mock))
+                    .serve_with_incoming(TcpListenerStream::new(listener)),
+            );
+
+            let (filters_tx, filters_rx) = watch::channel(filters());
+            let (queue_tx, queue_rx) = queue::bounded(100, OverflowPolicy::Block);
+            let keepalive = KeepaliveConfig {
+                ping_interval: None,
+                max_missed_pongs: Some(3),
+            };
+            let geyser = tokio::spawn(async move {
+                run_geyser(&url, None, filters_rx, queue_tx, keepalive).await
+            });
+            Harness {
+                requests,
+                filters: filters_tx,
+                _queue: queue_rx,
+                geyser,
+            }
+        }
+
+        async fn next_request(&mut self) -> SubscribeRequest {
+            timeout(Duration::from_secs(10), self.requests.recv())
+                .await
+                .expect("no request within 10s")
+                .expect("the mock geyser stopped")
+        }
+    }
+
+    impl Drop for Harness {
+        fn drop(&mut self) {
+            self.geyser.abort();
+        }
+    }
+
+    // `tokio::test` has the same problem as `async_trait`
+    fn run(test: impl Future<Output = ()>) {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(test)
+    }
+
+    fn filters() -> Filters {
+        serde_json::from_value(serde_json::json!({
+            "accounts": [],
+            "include_slots": true,
+        }))
+        .unwrap()
+    }
+
+    fn slot(slot: u64) -> Result<SubscribeUpdate, Status> {
+        Ok(SubscribeUpdate {
+            filters: vec!["slots".to_string()],
+            update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot,
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn tracker_processes_a_slot_once_a_later_one_arrives() {
+        let mut tracker = SlotTracker::default();
+        assert_eq!(tracker.resume_slot(), None);
+
+        tracker.observe(10);
+        tracker.observe(10);
+        assert_eq!(tracker.last_processed_slot(), None);
+        assert_eq!(tracker.resume_slot(), None);
+
+        tracker.observe(11);
+        assert_eq!(tracker.last_processed_slot(), Some(10));
+        assert_eq!(tracker.resume_slot(), Some(11));
+
+        // late updates for older slots change nothing
+        tracker.observe(9);
+        tracker.observe(11);
+        assert_eq!(tracker.last_processed_slot(), Some(10));
+
+        tracker.observe(15);
+        assert_eq!(tracker.last_processed_slot(), Some(11));
+        assert_eq!(tracker.resume_slot(), Some(12));
+    }
+
+    #[test]
+    fn tracker_reports_progress_once() {
+        let mut tracker = SlotTracker::default();
+        assert!(!tracker.take_progress());
+        tracker.observe(1);
+        assert!(tracker.take_progress());
+        assert!(!tracker.take_progress());
+    }
+
+    #[test]
+    fn tracker_skips_the_replay_until_an_update_arrives() {
+        let mut tracker = SlotTracker::default();
+        tracker.observe(10);
+        tracker.observe(11);
+        tracker.skip_replay();
+        assert_eq!(tracker.resume_slot(), None);
+
+        tracker.observe(20);
+        assert_eq!(tracker.last_processed_slot(), Some(11));
+        tracker.observe(21);
+        assert_eq!(tracker.resume_slot(), Some(21));
+    }
+
+    #[test]
+    fn tracker_keeps_the_gap_start_when_skipping_again() {
+        let mut tracker = SlotTracker::default();
+        tracker.observe(10);
+        tracker.observe(11);
+        tracker.skip_replay();
+        tracker.skip_replay();
+        assert_eq!(tracker.gap_from, Some(11));
+        assert_eq!(tracker.resume_slot(), None);
+    }
+
+    #[test]
+    fn reconnect_resumes_after_the_last_processed_slot() {
+        run(async {
+            let dropped = vec![
+                slot(10),
+                slot(11),
+                slot(12),
+                Err(Status::unavailable("connection reset")),
+            ];
+            let mut harness = Harness::start(vec![dropped]).await;
+
+            assert_eq!(harness.next_request().await.from_slot, None);
+            // 12 may not have been streamed in full, 11 was
+            assert_eq!(harness.next_request().await.from_slot, Some(12));
+        });
+    }
+
+    #[test]
+    fn rejected_replay_is_retried_from_the_tip() {
+        run(async {
+            let dropped = vec![
+                slot(10),
+                slot(11),
+                slot(12),
+                Err(Status::unavailable("reset")),
+            ];
+            let rejected = vec![Err(Status::invalid_argument("slot 12 is too old"))];
+            let mut harness = Harness::start(vec![dropped, rejected]).await;
+
+            assert_eq!(harness.next_request().await.from_slot, None);
+            assert_eq!(harness.next_request().await.from_slot, Some(12));
+            assert_eq!(harness.next_request().await.from_slot, None);
+        });
+    }
+
+    #[test]
+    fn out_of_range_replay_is_retried_from_the_tip() {
+        run(async {
+            let dropped = vec![
+                slot(10),
+                slot(11),
+                slot(12),
+                Err(Status::unavailable("reset")),
+            ];
+            let rejected = vec![Err(Status::out_of_range("slot 12 is gone"))];
+            let mut harness = Harness::start(vec![dropped, rejected]).await;
+
+            assert_eq!(harness.next_request().await.from_slot, None);
+            assert_eq!(harness.next_request().await.from_slot, Some(12));
+            assert_eq!(harness.next_request().await.from_slot, None);
+        });
+    }
+
+    #[test]
+    fn other_failures_before_the_first_update_keep_the_replay() {
+        run(async {
+            let dropped = vec![
+                slot(10),
+                slot(11),
+                slot(12),
+                Err(Status::unavailable("reset")),
+            ];
+            let rate_limited = vec![Err(Status::resource_exhausted("slow down"))];
+            let unauthenticated = vec![Err(Status::unauthenticated("bad token"))];
+            let failed = vec![Err(Status::internal("replay unavailable"))];
+            let mut harness =
+                Harness::start(vec![dropped, rate_limited, unauthenticated, failed]).await;
+
+            assert_eq!(harness.next_request().await.from_slot, None);
+            // every retry asks for the same slot again
+            for _ in 0..3 {
+                assert_eq!(harness.next_request().await.from_slot, Some(12));
+            }
+        });
+    }
+
+    #[test]
+    fn reloaded_filters_are_pushed_to_the_subscription() {
+        run(async {
+            let streaming = vec![slot(10), slot(11), slot(12)];
+            let mut harness = Harness::start(vec![streaming]).await;
+
+            let first = harness.next_request().await;
+            assert!(first.blocks_meta.is_empty());
+
+            let mut reloaded = filters();
+            reloaded.include_blocks_meta = true;
+            harness.filters.send(reloaded).unwrap();
+
+            // sent on the open subscription, without replaying anything
+            let second = harness.next_request().await;
+            assert!(second.blocks_meta.contains_key("blocks_meta"));
+            assert!(second.slots.contains_key("slots"));
+            assert_eq!(second.from_slot, None);
+            assert!(!harness.geyser.is_finished());
+        });
+    }
+}
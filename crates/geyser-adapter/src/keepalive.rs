@@ -0,0 +1,181 @@
+use std::{fmt, time::Duration};
+
+use anyhow::{Result, bail};
+use tokio::time::Instant;
+use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeRequestPing};
+
+/// Id used to answer the pings of the server, its pongs aren't waited for
+pub const SERVER_PING_REPLY_ID: i32 = 0;
+
+/// How the adapter keeps an idle subscription alive
+#[derive(Debug, Clone, Copy)]
+pub struct KeepaliveConfig {
+    /// delay between two client pings, none when the client doesn't ping
+    pub ping_interval: Option<Duration>,
+    /// unanswered pings in a row after which the connection is considered
+    /// dead, none when it is never dropped for missing pongs
+    pub max_missed_pongs: Option<u32>,
+}
+
+// a request carrying only a ping, it leaves the filters of the subscription
+// as they are
+pub fn ping_request(id: i32) -> SubscribeRequest {
+    SubscribeRequest {
+        ping: Some(SubscribeRequestPing { id }),
+        ..Default::default()
+    }
+}
+
+/// Pings sent by the client during one session
+#[derive(Debug)]
+pub struct Keepalive {
+    config: KeepaliveConfig,
+    /// id of the next ping, never `SERVER_PING_REPLY_ID`
+    next_id: i32,
+    /// the last ping sent and when, until its pong arrives
+    pending: Option<(i32, Instant)>,
+    /// pings sent in a row while the previous one was still unanswered
+    missed: u32,
+}
+
+impl Keepalive {
+    pub fn new(config: KeepaliveConfig) -> Self {
+        Keepalive {
+            config,
+            next_id: 1,
+            pending: None,
+            missed: 0,
+        }
+    }
+
+    // returns the id of the next ping to send, or fails once too many pings
+    // went unanswered
+    pub fn ping(&mut self) -> Result<i32> {
+        if let Some((id, sent_at)) = self.pending {
+            self.missed += 1;
+            if self
+                .config
+                .max_missed_pongs
+                .is_some_and(|max| self.missed >= max)
+            {
+                bail!(
+                    "No pong for {} ping(s) in a row (waiting for ping {} since {:?}), dropping the connection",
+                    self.missed,
+                    id,
+                    sent_at.elapsed()
+                );
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.pending = Some((id, Instant::now()));
+        Ok(id)
+    }
+
+    // any pong shows the connection is alive, the round trip is only known
+    // for the pong of the pending ping
+    pub fn pong(&mut self, id: i32) -> Option<Duration> {
+        self.missed = 0;
+        match self.pending {
+            Some((pending, sent_at)) if pending == id => {
+                self.pending = None;
+                Some(sent_at.elapsed())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Round trip times of the client pings, kept across sessions
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    count: u64,
+    total: Duration,
+    last: Duration,
+    max: Duration,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total += latency;
+        self.last = latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        (self.count > 0)
+            .then(|| Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64))
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.average() {
+            Some(avg) => write!(
+                f,
+                "last {:?}, avg {:?}, max {:?} over {} pong(s)",
+                self.last, avg, self.max, self.count
+            ),
+            None => write!(f, "no pongs yet"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keepalive(max_missed_pongs: Option<u32>) -> Keepalive {
+        Keepalive::new(KeepaliveConfig {
+            ping_interval: Some(Duration::from_secs(1)),
+            max_missed_pongs,
+        })
+    }
+
+    #[test]
+    fn ping_fails_after_max_missed_pongs() {
+        let mut pings = keepalive(Some(3));
+        // the first ping goes out, the next ones count as missed pongs until
+        // the third of them
+        for _ in 0..3 {
+            pings.ping().unwrap();
+        }
+        assert!(pings.ping().is_err());
+    }
+
+    #[test]
+    fn pong_resets_the_missed_count() {
+        let mut pings = keepalive(Some(2));
+        pings.ping().unwrap();
+        let id = pings.ping().unwrap();
+        pings.pong(id);
+
+        pings.ping().unwrap();
+        pings.ping().unwrap();
+        assert!(pings.ping().is_err());
+    }
+
+    #[test]
+    fn latency_is_only_known_for_the_pending_ping() {
+        let mut pings = keepalive(Some(3));
+        let first = pings.ping().unwrap();
+        let second = pings.ping().unwrap();
+        assert_ne!(first, second);
+
+        assert_eq!(pings.pong(first), None);
+        assert_eq!(pings.pong(SERVER_PING_REPLY_ID), None);
+        assert!(pings.pong(second).is_some());
+        // answered already
+        assert_eq!(pings.pong(second), None);
+    }
+
+    #[test]
+    fn no_limit_never_drops_the_connection() {
+        let mut pings = keepalive(None);
+        for _ in 0..100 {
+            pings.ping().unwrap();
+        }
+    }
+}
@@ -1,24 +1,146 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use config::CONFIG;
+use core::Codec;
 use filter::Filters;
 use geyser::run_geyser;
-use redis_adapter::Redis;
+use keepalive::KeepaliveConfig;
+use publish::{BatchConfig, RetryConfig, UpdatePublisher, run_publisher};
+use queue::OverflowPolicy;
+use redis_adapter::{Redis, StreamConfig};
+use reload::watch_filters;
 use rustls::crypto::{CryptoProvider, ring::default_provider};
+use spill::SpillBuffer;
+use std::time::Duration;
+use validate::{FilterIssue, Severity, request_to_json, validate_filters};
 mod filter;
 mod geyser;
+mod keepalive;
+mod publish;
+mod queue;
+mod reload;
+mod spill;
+mod validate;
+
+const DEFAULT_FILTERS_PATH: &str = "filters.json";
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        // geyser-adapter validate-filters [path]
+        Some("validate-filters") => {
+            return validate_command(args.get(1).map_or(DEFAULT_FILTERS_PATH, String::as_str));
+        }
+        Some(other) => bail!("Unknown command '{}', expected validate-filters", other),
+        None => {}
+    }
+
     CryptoProvider::install_default(default_provider()).unwrap();
     println!("Starting geyser adapter...");
     let rpc_url = &CONFIG.rpc_url;
     let redis_url = &CONFIG.redis_url;
     let x_token = &CONFIG.x_token;
 
-    let filters_path = "filters.json";
+    let filters = load_filters(DEFAULT_FILTERS_PATH)?;
+    let (filters_tx, filters_rx) = tokio::sync::watch::channel(filters);
+    tokio::spawn(async move {
+        if let Err(e) = watch_filters(DEFAULT_FILTERS_PATH.to_owned(), filters_tx).await {
+            eprintln!("❌ Filter hot-reload stopped: {:?}", e);
+        }
+    });
+
+    let codec: Codec = CONFIG.wire_codec.parse()?;
+    println!("Publishing updates as {}", codec);
+    let defaults = StreamConfig::default();
+    let redis = Redis::new(redis_url)?.with_stream_config(StreamConfig {
+        max_len: CONFIG.redis_stream_max_len.or(defaults.max_len),
+        ..defaults
+    });
+    let batch = BatchConfig {
+        max_items: CONFIG.publish_batch_size.max(1),
+        flush_interval: Duration::from_millis(CONFIG.publish_flush_interval_ms.max(1)),
+    };
+    let mut publisher = UpdatePublisher::new(redis, codec, batch).with_retry(RetryConfig {
+        attempts: CONFIG.publish_retries.max(1),
+        backoff: Duration::from_millis(CONFIG.publish_retry_backoff_ms),
+    });
+    // batches redis doesn't take are kept on disk until it is back
+    if CONFIG.publish_spill_max_mb > 0 {
+        let spill = SpillBuffer::open(
+            &CONFIG.publish_spill_dir,
+            CONFIG.publish_spill_max_mb * 1024 * 1024,
+        )?;
+        publisher = publisher.with_spill(spill);
+    }
+
+    // the stream only queues the decoded updates, so a slow redis doesn't
+    // stall the reads of the stream
+    let overflow: OverflowPolicy = CONFIG.publish_queue_overflow.parse()?;
+    println!(
+        "Publish queue of {} updates, {:?} on overflow",
+        CONFIG.publish_queue_capacity, overflow
+    );
+    let (queue_tx, queue_rx) = queue::bounded(CONFIG.publish_queue_capacity.max(1), overflow);
+    let queue_tx = queue_tx.with_raw_updates(publisher.needs_raw());
+    tokio::spawn(run_publisher(queue_rx, publisher));
+    let keepalive = KeepaliveConfig {
+        // 0 turns the client pings off
+        ping_interval: Some(Duration::from_secs(CONFIG.geyser_ping_interval_secs))
+            .filter(|interval| !interval.is_zero()),
+        // 0 never drops the connection for missing pongs
+        max_missed_pongs: Some(CONFIG.geyser_max_missed_pongs).filter(|&max| max > 0),
+    };
+    run_geyser(rpc_url, x_token.clone(), filters_rx, queue_tx, keepalive).await
+}
+
+// loads and validates the filters, refusing to start on errors the server
+// would reject the subscription for
+fn load_filters(path: &str) -> Result<Filters> {
+    let filters = Filters::from_file(path)?;
+    let issues = validate_filters(&filters);
+    report_issues(&issues);
+    if has_errors(&issues) {
+        bail!(
+            "Invalid filters in {}, check them with `geyser-adapter validate-filters`",
+            path
+        );
+    }
+    Ok(filters)
+}
+
+// prints the issues and the subscribe request the filters turn into, without
+// connecting to the geyser
+fn validate_command(path: &str) -> Result<()> {
+    let filters = Filters::from_file(path)?;
+    let issues = validate_filters(&filters);
+    report_issues(&issues);
+
+    let request = filters.to_subscribe_request(None);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&request_to_json(&request))?
+    );
 
-    let filters = Filters::from_file(filters_path)?;
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("{} has {} error(s)", path, errors);
+    }
+    eprintln!("✅ {} is valid", path);
+    Ok(())
+}
+
+fn report_issues(issues: &[FilterIssue]) {
+    for issue in issues {
+        match issue.severity {
+            Severity::Warning => eprintln!("⚠️ {}", issue),
+            Severity::Error => eprintln!("❌ {}", issue),
+        }
+    }
+}
 
-    let publisher = Redis::new(&redis_url)?;
-    run_geyser::<Redis>(rpc_url, x_token.clone(), &filters, publisher).await
+fn has_errors(issues: &[FilterIssue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
 }
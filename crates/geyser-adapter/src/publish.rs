@@ -0,0 +1,604 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use colored::Colorize;
+use core::{Codec, Update, UpdateKind, wire};
+use redis_adapter::Publisher;
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+
+use crate::{
+    queue::QueueReceiver,
+    spill::{SpillBuffer, SpilledMessage},
+};
+
+/// How often the depth and drops of the queue are logged
+const QUEUE_STATS_INTERVAL: Duration = Duration::from_secs(30);
+/// Upper bound for the delay between two replays of the spill buffer
+const MAX_REPLAY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How published updates are grouped into pipelined batches
+#[derive(Clone, Copy, Debug)]
+pub struct BatchConfig {
+    /// updates sent per round trip at most
+    pub max_items: usize,
+    /// how long updates are collected before they are sent
+    pub flush_interval: Duration,
+}
+
+/// How a batch that failed to publish is retried before it is spilled
+#[derive(Clone, Copy, Debug)]
+pub struct RetryConfig {
+    /// attempts per batch, the first one included
+    pub attempts: u32,
+    /// delay before the first retry, doubled after every failed attempt
+    pub backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Publishes updates on the channel of their kind, framed with the
+/// configured codec and sent in batches
+pub struct UpdatePublisher<P: Publisher> {
+    publisher: P,
+    codec: Codec,
+    batch: BatchConfig,
+    /// encoded updates waiting for the next flush, with their channel
+    pending: Vec<SpilledMessage>,
+    retry: RetryConfig,
+    /// batches that failed to publish, without it they are dropped
+    spill: Option<SpillBuffer>,
+    /// when the spilled batches are replayed next, set while redis is down
+    replay_at: Option<Instant>,
+    replay_backoff: Duration,
+}
+
+impl<P: Publisher> UpdatePublisher<P> {
+    pub fn new(publisher: P, codec: Codec, batch: BatchConfig) -> Self {
+        UpdatePublisher {
+            publisher,
+            codec,
+            batch,
+            pending: Vec::with_capacity(batch.max_items),
+            retry: RetryConfig::default(),
+            spill: None,
+            replay_at: None,
+            replay_backoff: RetryConfig::default().backoff,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self.replay_backoff = retry.backoff;
+        self
+    }
+
+    pub fn with_spill(mut self, spill: SpillBuffer) -> Self {
+        // batches left by a previous run are replayed right away
+        if !spill.is_empty() {
+            println!("💾 Replaying spilled updates: {}", spill);
+            self.replay_at = Some(Instant::now());
+        }
+        self.spill = Some(spill);
+        self
+    }
+
+    pub fn spill(&self) -> Option<&SpillBuffer> {
+        self.spill.as_ref()
+    }
+
+    pub fn flush_interval(&self) -> Duration {
+        self.batch.flush_interval
+    }
+
+    // whether the geyser update has to be kept around for `publish`
+    pub fn needs_raw(&self) -> bool {
+        self.codec == Codec::Protobuf
+    }
+
+    // queues an update tagged with the names of the filter groups it
+    // matched, `raw` is the geyser update it was converted from. a full
+    // batch is sent right away
+    pub async fn publish(
+        &mut self,
+        update: Update,
+        groups: Vec<String>,
+        raw: Option<&SubscribeUpdate>,
+    ) -> Result<()> {
+        // answered in the session
+        let Some((kind, slot)) = kind_and_slot(&update) else {
+            return Ok(());
+        };
+        let payload = wire::encode(self.codec, &update, groups, raw)?;
+
+        let line = format!(
+            "Received {} update for slot {} ({} bytes of {})",
+            kind,
+            slot,
+            payload.len(),
+            self.codec
+        );
+        match kind {
+            UpdateKind::Account => println!("{}", line.green()),
+            UpdateKind::Transaction => println!("{}", line.blue()),
+            UpdateKind::Block | UpdateKind::BlockMeta => println!("{}", line.yellow()),
+            UpdateKind::Entry => println!("{}", line.cyan()),
+            UpdateKind::Slot | UpdateKind::TransactionStatus => {}
+        }
+
+        self.pending.push((kind.channel(), payload));
+        if self.pending.len() >= self.batch.max_items {
+            self.flush().await;
+        }
+        Ok(())
+    }
+
+    // sends the queued updates in a single pipelined round trip. spilled
+    // batches go out first so redis gets every update in order, and while
+    // they can't be replayed the new ones are spilled behind them
+    pub async fn flush(&mut self) {
+        let replay_due = self.replay_at.is_some_and(|at| at <= Instant::now());
+        if self.pending.is_empty() && !replay_due {
+            return;
+        }
+        let batch: Vec<SpilledMessage> = self.pending.drain(..).collect();
+
+        if self.replay_at.is_some() && !(replay_due && self.replay().await) {
+            self.spill_batch(batch);
+            return;
+        }
+        if batch.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.publish_with_retries(&batch).await {
+            eprintln!(
+                "❌ Failed to publish {} update(s) after {} attempt(s): {}",
+                batch.len(),
+                self.retry.attempts,
+                e
+            );
+            if self.spill.is_some() {
+                self.replay_at = Some(Instant::now() + self.replay_backoff);
+            }
+            self.spill_batch(batch);
+        }
+    }
+
+    async fn publish_with_retries(&self, batch: &[SpilledMessage]) -> Result<()> {
+        let mut backoff = self.retry.backoff;
+        let mut attempt = 1;
+        loop {
+            match self.publisher.publish_batch(batch).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.retry.attempts.max(1) => return Err(e),
+                Err(e) => eprintln!(
+                    "⚠️ Publishing {} update(s) failed (attempt {}/{}), retrying in {:?}: {}",
+                    batch.len(),
+                    attempt,
+                    self.retry.attempts,
+                    backoff,
+                    e
+                ),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    // replays the spilled batches in order, returns whether all of them
+    // were published. a failed replay is tried again after a growing delay
+    async fn replay(&mut self) -> bool {
+        let Some(spill) = self.spill.as_mut() else {
+            self.replay_at = None;
+            return true;
+        };
+
+        let mut replayed = 0;
+        let result: Result<()> = async {
+            while let Some(chunk) = spill.next_batch(self.batch.max_items)? {
+                self.publisher.publish_batch(chunk).await?;
+                let count = chunk.len();
+                spill.mark_replayed(count)?;
+                replayed += count;
+            }
+            Ok(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                if replayed > 0 {
+                    println!("✅ Replayed {} spilled update(s)", replayed);
+                }
+                self.replay_at = None;
+                self.replay_backoff = self.retry.backoff;
+                true
+            }
+            Err(e) => {
+                eprintln!(
+                    "⚠️ Replaying spilled updates failed ({} replayed), retrying in {:?}: {}",
+                    replayed, self.replay_backoff, e
+                );
+                self.replay_at = Some(Instant::now() + self.replay_backoff);
+                self.replay_backoff = (self.replay_backoff * 2).min(MAX_REPLAY_BACKOFF);
+                false
+            }
+        }
+    }
+
+    fn spill_batch(&mut self, batch: Vec<SpilledMessage>) {
+        if batch.is_empty() {
+            return;
+        }
+        let Some(spill) = self.spill.as_mut() else {
+            eprintln!("❌ Dropping {} update(s), spilling is off", batch.len());
+            return;
+        };
+        match spill.append(&batch) {
+            Ok(true) => {}
+            Ok(false) => eprintln!("❌ Spill buffer full, dropping {} update(s)", batch.len()),
+            Err(e) => eprintln!("❌ Failed to spill {} update(s): {:?}", batch.len(), e),
+        }
+    }
+}
+
+// publishes the queued updates until the stream side is gone
+pub async fn run_publisher<P: Publisher>(
+    mut queue: QueueReceiver,
+    mut publisher: UpdatePublisher<P>,
+) {
+    let mut flush_interval = tokio::time::interval(publisher.flush_interval());
+    let mut stats_interval = tokio::time::interval(QUEUE_STATS_INTERVAL);
+
+    loop {
+        tokio::select! {
+            item = queue.recv() => {
+                let Some(item) = item else {
+                    publisher.flush().await;
+                    return;
+                };
+                if let Err(e) = publisher.publish(item.update, item.groups, item.raw.as_ref()).await {
+                    eprintln!("❌ Failed to encode update: {}", e);
+                }
+            }
+            // updates are pipelined to redis in batches, flushed at least this often
+            _ = flush_interval.tick() => publisher.flush().await,
+            _ = stats_interval.tick() => {
+                println!("📊 Publish queue: {}", queue.stats());
+                if let Some(spill) = publisher.spill().filter(|s| !s.is_empty()) {
+                    println!("💾 Spilled updates: {}", spill);
+                }
+            }
+        }
+    }
+}
+
+pub fn kind_and_slot(update: &Update) -> Option<(UpdateKind, u64)> {
+    Some(match update {
+        Update::Account(u) => (UpdateKind::Account, u.slot),
+        Update::Block(u) => (UpdateKind::Block, u.slot),
+        Update::BlockMeta(u) => (UpdateKind::BlockMeta, u.slot),
+        Update::Transaction(u) => (UpdateKind::Transaction, u.slot),
+        Update::TransactionStatus(u) => (UpdateKind::TransactionStatus, u.slot),
+        Update::Entry(u) => (UpdateKind::Entry, u.slot),
+        Update::Slot(u) => (UpdateKind::Slot, u.slot),
+        Update::Ping | Update::Pong(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        queue::{self, OverflowPolicy, QueuedUpdate},
+        spill::tests::TestDir,
+    };
+    use core::{SlotStatus, SlotUpdate};
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+    };
+
+    // `tokio::test` can't be used, the `core` crate shadows the one it expands to
+    fn run(test: impl Future<Output = ()>) {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(test)
+    }
+
+    #[derive(Default)]
+    struct MockState {
+        /// payloads published, in order
+        published: Vec<Vec<u8>>,
+        /// calls made, failed ones included
+        calls: usize,
+        /// calls that fail before redis answers again
+        failures: usize,
+        /// calls that succeed before redis goes down, none for no limit
+        up_for: Option<usize>,
+    }
+
+    /// A redis that fails the scripted calls
+    #[derive(Clone, Default)]
+    struct MockRedis(Arc<Mutex<MockState>>);
+
+    impl MockRedis {
+        fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+            self.0.lock().unwrap()
+        }
+
+        fn published(&self) -> Vec<u8> {
+            self.state().published.iter().map(|p| p[0]).collect()
+        }
+    }
+
+    // what `async_trait` expands the method to, the macro can't be used as
+    // the `core` crate shadows the one it refers to
+    impl Publisher for MockRedis {
+        fn publisher<'a, 'b, 'c, 'd>(
+            &'a self,
+            _channel: &'b str,
+            payload: &'c [u8],
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'd>>
+        where
+            'a: 'd,
+            'b: 'd,
+            'c: 'd,
+            Self: 'd,
+        {
+            let mut state = self.state();
+            state.calls += 1;
+            let result = if state.failures > 0 {
+                state.failures -= 1;
+                Err(anyhow::anyhow!("redis is down"))
+            } else if state.up_for == Some(0) {
+                Err(anyhow::anyhow!("redis went down"))
+            } else {
+                state.up_for = state.up_for.map(|n| n - 1);
+                state.published.push(payload.to_vec());
+                Ok(())
+            };
+            Box::pin(async { result })
+        }
+    }
+
+    const RETRY: RetryConfig = RetryConfig {
+        attempts: 3,
+        backoff: Duration::from_millis(10),
+    };
+
+    fn publisher(redis: &MockRedis, max_items: usize) -> UpdatePublisher<MockRedis> {
+        let batch = BatchConfig {
+            max_items,
+            flush_interval: Duration::from_secs(60),
+        };
+        UpdatePublisher::new(redis.clone(), Codec::Bincode, batch).with_retry(RETRY)
+    }
+
+    // queues updates with a one byte payload each
+    fn queue(publisher: &mut UpdatePublisher<MockRedis>, payloads: impl IntoIterator<Item = u8>) {
+        for n in payloads {
+            publisher
+                .pending
+                .push((UpdateKind::Slot.channel(), vec![n]));
+        }
+    }
+
+    // waits for the spilled updates to be due for a replay
+    async fn replay_due(publisher: &UpdatePublisher<MockRedis>) {
+        if let Some(at) = publisher.replay_at {
+            tokio::time::sleep(at.saturating_duration_since(Instant::now())).await;
+        }
+    }
+
+    fn slot(slot: u64) -> Update {
+        Update::Slot(SlotUpdate {
+            slot,
+            parent: None,
+            status: SlotStatus::Processed,
+            dead_error: None,
+        })
+    }
+
+    // the slots of the published updates, in order
+    fn published_slots(redis: &MockRedis) -> Vec<u64> {
+        let state = redis.state();
+        state
+            .published
+            .iter()
+            .map(|payload| wire::decode::<SlotUpdate>(payload).unwrap().update.slot)
+            .collect()
+    }
+
+    #[test]
+    fn a_full_batch_is_sent_in_order_right_away() {
+        run(async {
+            let redis = MockRedis::default();
+            let mut publisher = publisher(&redis, 3);
+
+            for n in 1..=4 {
+                publisher.publish(slot(n), vec![], None).await.unwrap();
+            }
+            assert_eq!(published_slots(&redis), vec![1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn a_partial_batch_waits_for_the_flush() {
+        run(async {
+            let redis = MockRedis::default();
+            let mut publisher = publisher(&redis, 10);
+
+            publisher.publish(slot(1), vec![], None).await.unwrap();
+            publisher.publish(slot(2), vec![], None).await.unwrap();
+            assert!(published_slots(&redis).is_empty());
+
+            publisher.flush().await;
+            publisher.flush().await;
+            assert_eq!(published_slots(&redis), vec![1, 2]);
+        });
+    }
+
+    #[test]
+    fn a_partial_batch_is_flushed_after_the_flush_interval() {
+        run(async {
+            let redis = MockRedis::default();
+            let batch = BatchConfig {
+                max_items: 10,
+                flush_interval: Duration::from_millis(20),
+            };
+            let publisher = UpdatePublisher::new(redis.clone(), Codec::Bincode, batch);
+            let (queue_tx, queue_rx) = queue::bounded(10, OverflowPolicy::Block);
+            let running = tokio::spawn(run_publisher(queue_rx, publisher));
+
+            for slot in [1, 2] {
+                let update = Update::Slot(SlotUpdate {
+                    slot,
+                    parent: None,
+                    status: SlotStatus::Processed,
+                    dead_error: None,
+                });
+                let queued = QueuedUpdate {
+                    update,
+                    groups: vec![],
+                    raw: None,
+                };
+                queue_tx.send(queued).await.unwrap();
+            }
+
+            // the queue stays open, only the interval can flush the two updates
+            let started = Instant::now();
+            while redis.state().published.len() < 2 {
+                assert!(started.elapsed() < Duration::from_secs(10), "never flushed");
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            assert!(!running.is_finished());
+            running.abort();
+        });
+    }
+
+    #[test]
+    fn a_failed_batch_is_retried_with_backoff() {
+        run(async {
+            let redis = MockRedis::default();
+            redis.state().failures = 2;
+            let mut publisher = publisher(&redis, 10);
+
+            queue(&mut publisher, [1, 2]);
+            let started = Instant::now();
+            publisher.flush().await;
+
+            // 10ms then 20ms before the third attempt
+            assert!(started.elapsed() >= Duration::from_millis(30));
+            assert_eq!(redis.state().calls, 4);
+            assert_eq!(redis.published(), vec![1, 2]);
+            assert!(publisher.replay_at.is_none());
+        });
+    }
+
+    #[test]
+    fn a_batch_is_spilled_once_the_retries_run_out() {
+        run(async {
+            let dir = TestDir::new("publish-spill");
+            let redis = MockRedis::default();
+            redis.state().failures = usize::MAX;
+            let mut publisher =
+                publisher(&redis, 10).with_spill(SpillBuffer::open(&dir.0, u64::MAX).unwrap());
+
+            queue(&mut publisher, [1, 2]);
+            publisher.flush().await;
+
+            assert_eq!(redis.state().calls, RETRY.attempts as usize);
+            assert!(redis.published().is_empty());
+            assert!(!publisher.spill().unwrap().is_empty());
+            assert!(publisher.replay_at.is_some());
+        });
+    }
+
+    #[test]
+    fn a_batch_is_dropped_without_a_spill_buffer() {
+        run(async {
+            let redis = MockRedis::default();
+            redis.state().failures = usize::MAX;
+            let mut publisher = publisher(&redis, 10);
+
+            queue(&mut publisher, [1]);
+            publisher.flush().await;
+            assert!(publisher.pending.is_empty());
+            assert!(publisher.replay_at.is_none());
+        });
+    }
+
+    #[test]
+    fn new_batches_are_spilled_behind_the_backlog_and_replayed_in_order() {
+        run(async {
+            let dir = TestDir::new("publish-order");
+            let redis = MockRedis::default();
+            redis.state().failures = usize::MAX;
+            let mut publisher =
+                publisher(&redis, 2).with_spill(SpillBuffer::open(&dir.0, u64::MAX).unwrap());
+
+            queue(&mut publisher, [1, 2]);
+            publisher.flush().await;
+            // not due for a replay yet, spilled without trying redis
+            let calls = redis.state().calls;
+            queue(&mut publisher, [3, 4]);
+            publisher.flush().await;
+            assert_eq!(redis.state().calls, calls);
+
+            redis.state().failures = 0;
+            replay_due(&publisher).await;
+            queue(&mut publisher, [5]);
+            publisher.flush().await;
+
+            assert_eq!(redis.published(), vec![1, 2, 3, 4, 5]);
+            assert!(publisher.spill().unwrap().is_empty());
+            assert!(publisher.replay_at.is_none());
+        });
+    }
+
+    #[test]
+    fn a_failed_replay_resumes_after_the_replayed_updates() {
+        run(async {
+            let dir = TestDir::new("publish-resume");
+            let redis = MockRedis::default();
+            redis.state().failures = usize::MAX;
+            let mut publisher =
+                publisher(&redis, 2).with_spill(SpillBuffer::open(&dir.0, u64::MAX).unwrap());
+
+            queue(&mut publisher, [1, 2]);
+            publisher.flush().await;
+            queue(&mut publisher, [3, 4]);
+            publisher.flush().await;
+
+            // redis takes the first chunk of the replay, then goes down again
+            {
+                let mut state = redis.state();
+                state.failures = 0;
+                state.up_for = Some(2);
+            }
+            replay_due(&publisher).await;
+            queue(&mut publisher, [5]);
+            publisher.flush().await;
+            assert_eq!(redis.published(), vec![1, 2]);
+            assert!(publisher.replay_at.is_some());
+
+            redis.state().up_for = None;
+            replay_due(&publisher).await;
+            publisher.flush().await;
+            assert_eq!(redis.published(), vec![1, 2, 3, 4, 5]);
+            assert!(publisher.replay_at.is_none());
+        });
+    }
+}
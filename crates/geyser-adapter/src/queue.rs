@@ -0,0 +1,487 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, anyhow, bail};
+use core::{Update, UpdateKind};
+use tokio::sync::Notify;
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+
+use crate::publish::kind_and_slot;
+
+/// A decoded update waiting to be published
+pub struct QueuedUpdate {
+    pub update: Update,
+    /// names of the filter groups the update matched
+    pub groups: Vec<String>,
+    /// the geyser update as received, only kept for the protobuf codec
+    pub raw: Option<SubscribeUpdate>,
+}
+
+/// Updates the drop-by-kind policy can shed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropKind {
+    /// vote transactions and their statuses
+    Votes,
+    Kind(UpdateKind),
+}
+
+impl DropKind {
+    fn matches(self, update: &Update) -> bool {
+        match self {
+            DropKind::Votes => is_vote(update),
+            DropKind::Kind(kind) => kind_and_slot(update).is_some_and(|(k, _)| k == kind),
+        }
+    }
+}
+
+impl FromStr for DropKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "votes" => Ok(DropKind::Votes),
+            name => UpdateKind::from_channel(name)
+                .map(DropKind::Kind)
+                .ok_or_else(|| anyhow!("Unknown update kind '{}'", name)),
+        }
+    }
+}
+
+/// What happens to an update arriving on a full queue
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// wait for the publisher to make room, stalling the stream
+    Block,
+    /// drop the oldest queued update
+    DropOldest,
+    /// drop the oldest queued update of the first listed kind that has one
+    /// queued, or the new update when it is of a listed kind. waits for room
+    /// when neither is
+    DropByKind(Vec<DropKind>),
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = anyhow::Error;
+
+    // block, drop-oldest or drop-by-kind:votes,entries,...
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "block" => Ok(OverflowPolicy::Block),
+            None if s == "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            Some(("drop-by-kind", kinds)) => {
+                let kinds = kinds
+                    .split(',')
+                    .map(DropKind::from_str)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(OverflowPolicy::DropByKind(kinds))
+            }
+            _ => bail!(
+                "Unknown overflow policy '{}', expected block, drop-oldest or drop-by-kind:<kinds>",
+                s
+            ),
+        }
+    }
+}
+
+/// Depth and drops of the queue since the adapter started
+#[derive(Clone, Debug, Default)]
+pub struct QueueStats {
+    pub capacity: usize,
+    pub depth: usize,
+    /// deepest the queue has been
+    pub max_depth: usize,
+    /// updates dropped on overflow, by kind
+    pub dropped: BTreeMap<&'static str, u64>,
+}
+
+impl QueueStats {
+    fn record_drop(&mut self, update: &Update) {
+        let label = match kind_and_slot(update) {
+            _ if is_vote(update) => "votes",
+            Some((kind, _)) => kind.channel(),
+            None => "other",
+        };
+        *self.dropped.entry(label).or_default() += 1;
+    }
+}
+
+impl fmt::Display for QueueStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "depth {}/{} (max {}), dropped {}",
+            self.depth,
+            self.capacity,
+            self.max_depth,
+            self.dropped.values().sum::<u64>()
+        )?;
+        if !self.dropped.is_empty() {
+            let by_kind: Vec<String> = self
+                .dropped
+                .iter()
+                .map(|(kind, count)| format!("{}: {}", kind, count))
+                .collect();
+            write!(f, " ({})", by_kind.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+struct State {
+    items: VecDeque<QueuedUpdate>,
+    stats: QueueStats,
+    /// set once either side is gone
+    closed: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    policy: OverflowPolicy,
+    /// wakes the sender waiting for room
+    space: Notify,
+    /// wakes the receiver waiting for updates
+    ready: Notify,
+}
+
+impl Shared {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.space.notify_one();
+        self.ready.notify_one();
+    }
+}
+
+// a bounded queue between the geyser stream and the publisher, so a slow
+// redis doesn't hold back the reads of the stream
+pub fn bounded(capacity: usize, policy: OverflowPolicy) -> (QueueSender, QueueReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity),
+            stats: QueueStats {
+                capacity,
+                ..Default::default()
+            },
+            closed: false,
+        }),
+        policy,
+        space: Notify::new(),
+        ready: Notify::new(),
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+            keep_raw: false,
+        },
+        QueueReceiver { shared },
+    )
+}
+
+pub struct QueueSender {
+    shared: Arc<Shared>,
+    keep_raw: bool,
+}
+
+impl QueueSender {
+    pub fn with_raw_updates(mut self, keep_raw: bool) -> Self {
+        self.keep_raw = keep_raw;
+        self
+    }
+
+    // whether the geyser update has to be queued along with the decoded one
+    pub fn keeps_raw(&self) -> bool {
+        self.keep_raw
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.shared.state.lock().unwrap().stats.clone()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+
+    // queues an update, applying the overflow policy when the queue is full.
+    // fails once the publisher is gone
+    pub async fn send(&self, item: QueuedUpdate) -> Result<()> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.closed {
+                    bail!("The publisher stopped");
+                }
+                if state.items.len() >= state.stats.capacity
+                    && !make_room(&mut state, &self.shared.policy, &item)
+                {
+                    // only the new update could go, it is dropped
+                    if self.drops_incoming(&item) {
+                        state.stats.record_drop(&item.update);
+                        return Ok(());
+                    }
+                } else {
+                    state.items.push_back(item);
+                    state.stats.depth = state.items.len();
+                    state.stats.max_depth = state.stats.max_depth.max(state.stats.depth);
+                    drop(state);
+                    self.shared.ready.notify_one();
+                    return Ok(());
+                }
+            }
+            self.shared.space.notified().await;
+        }
+    }
+
+    fn drops_incoming(&self, item: &QueuedUpdate) -> bool {
+        match &self.shared.policy {
+            OverflowPolicy::DropByKind(kinds) => kinds.iter().any(|k| k.matches(&item.update)),
+            OverflowPolicy::Block | OverflowPolicy::DropOldest => false,
+        }
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+// drops a queued update to make room for `item` if the policy allows it
+fn make_room(state: &mut State, policy: &OverflowPolicy, item: &QueuedUpdate) -> bool {
+    let position = match policy {
+        OverflowPolicy::Block => None,
+        OverflowPolicy::DropOldest => (!state.items.is_empty()).then_some(0),
+        // only kinds listed before the one of the new update are shed for
+        // it, otherwise the new update is the one dropped
+        OverflowPolicy::DropByKind(kinds) => kinds
+            .iter()
+            .take_while(|k| !k.matches(&item.update))
+            .find_map(|k| {
+                state
+                    .items
+                    .iter()
+                    .position(|queued| k.matches(&queued.update))
+            }),
+    };
+    match position.and_then(|i| state.items.remove(i)) {
+        Some(dropped) => {
+            state.stats.record_drop(&dropped.update);
+            true
+        }
+        None => false,
+    }
+}
+
+pub struct QueueReceiver {
+    shared: Arc<Shared>,
+}
+
+impl QueueReceiver {
+    pub fn stats(&self) -> QueueStats {
+        self.shared.state.lock().unwrap().stats.clone()
+    }
+
+    // waits for the next update, `None` once the sender is gone and the
+    // queue drained
+    pub async fn recv(&mut self) -> Option<QueuedUpdate> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(item) = state.items.pop_front() {
+                    state.stats.depth = state.items.len();
+                    drop(state);
+                    self.shared.space.notify_one();
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.shared.ready.notified().await;
+        }
+    }
+}
+
+impl Drop for QueueReceiver {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+fn is_vote(update: &Update) -> bool {
+    match update {
+        Update::Transaction(tx) => tx.transaction.as_ref().is_some_and(|info| info.is_vote),
+        Update::TransactionStatus(status) => status.is_vote,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{EntryUpdate, SlotStatus, SlotUpdate, TransactionStatusUpdate};
+    use std::{future::Future, time::Duration};
+    use tokio::time::timeout;
+
+    // `tokio::test` can't be used, the `core` crate shadows the one it expands to
+    fn run(test: impl Future<Output = ()>) {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(test)
+    }
+
+    fn queued(update: Update) -> QueuedUpdate {
+        QueuedUpdate {
+            update,
+            groups: vec![],
+            raw: None,
+        }
+    }
+
+    fn slot(slot: u64) -> QueuedUpdate {
+        queued(Update::Slot(SlotUpdate {
+            slot,
+            parent: None,
+            status: SlotStatus::Processed,
+            dead_error: None,
+        }))
+    }
+
+    fn vote(slot: u64) -> QueuedUpdate {
+        queued(Update::TransactionStatus(TransactionStatusUpdate {
+            slot,
+            signature: vec![],
+            is_vote: true,
+            index: 0,
+            err: None,
+        }))
+    }
+
+    fn entry(slot: u64) -> QueuedUpdate {
+        queued(Update::Entry(EntryUpdate {
+            slot,
+            index: 0,
+            num_hashes: 0,
+            hash: vec![],
+            executed_transaction_count: 0,
+            starting_transaction_index: 0,
+        }))
+    }
+
+    // the kind and slot of an update, votes are told apart
+    fn label(item: &QueuedUpdate) -> (&'static str, u64) {
+        let (kind, slot) = kind_and_slot(&item.update).unwrap();
+        match is_vote(&item.update) {
+            true => ("votes", slot),
+            false => (kind.channel(), slot),
+        }
+    }
+
+    // the queued updates, in order
+    async fn drain(rx: &mut QueueReceiver) -> Vec<(&'static str, u64)> {
+        let mut drained = vec![];
+        while rx.stats().depth > 0 {
+            drained.push(label(&rx.recv().await.unwrap()));
+        }
+        drained
+    }
+
+    // whether the send is still waiting for room after a while
+    async fn stays_pending(send: impl Future<Output = Result<()>>) -> bool {
+        timeout(Duration::from_millis(50), send).await.is_err()
+    }
+
+    #[test]
+    fn block_waits_for_the_publisher() {
+        run(async {
+            let (tx, mut rx) = bounded(1, OverflowPolicy::Block);
+            tx.send(slot(1)).await.unwrap();
+
+            let sender = tokio::spawn(async move {
+                tx.send(slot(2)).await.unwrap();
+                tx
+            });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(!sender.is_finished());
+
+            assert_eq!(label(&rx.recv().await.unwrap()), ("slots", 1));
+            let tx = sender.await.unwrap();
+            assert_eq!(label(&rx.recv().await.unwrap()), ("slots", 2));
+            assert!(tx.stats().dropped.is_empty());
+        });
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_for_the_new_update() {
+        run(async {
+            let (tx, mut rx) = bounded(2, OverflowPolicy::DropOldest);
+            for n in 1..=3 {
+                tx.send(slot(n)).await.unwrap();
+            }
+            assert_eq!(drain(&mut rx).await, vec![("slots", 2), ("slots", 3)]);
+            assert_eq!(tx.stats().dropped.get("slots"), Some(&1));
+            assert_eq!(tx.stats().max_depth, 2);
+        });
+    }
+
+    #[test]
+    fn drop_by_kind_sheds_the_listed_kinds_in_order() {
+        run(async {
+            let policy = OverflowPolicy::DropByKind(vec![
+                DropKind::Votes,
+                DropKind::Kind(UpdateKind::Entry),
+            ]);
+            let (tx, mut rx) = bounded(2, policy);
+            tx.send(entry(1)).await.unwrap();
+            tx.send(vote(2)).await.unwrap();
+
+            // votes go first, then entries
+            tx.send(slot(3)).await.unwrap();
+            tx.send(slot(4)).await.unwrap();
+            // a new update of a listed kind is dropped itself when nothing
+            // listed before it is queued
+            tx.send(vote(5)).await.unwrap();
+            tx.send(entry(6)).await.unwrap();
+            // with nothing to shed the sender waits
+            assert!(stays_pending(tx.send(slot(7))).await);
+
+            assert_eq!(drain(&mut rx).await, vec![("slots", 3), ("slots", 4)]);
+            let dropped = tx.stats().dropped;
+            assert_eq!(dropped.get("votes"), Some(&2));
+            assert_eq!(dropped.get("entries"), Some(&2));
+        });
+    }
+
+    #[test]
+    fn closing_the_sender_drains_the_queue_first() {
+        run(async {
+            let (tx, mut rx) = bounded(4, OverflowPolicy::Block);
+            tx.send(slot(1)).await.unwrap();
+            tx.send(slot(2)).await.unwrap();
+            drop(tx);
+
+            assert_eq!(label(&rx.recv().await.unwrap()), ("slots", 1));
+            assert_eq!(label(&rx.recv().await.unwrap()), ("slots", 2));
+            assert!(rx.recv().await.is_none());
+        });
+    }
+
+    #[test]
+    fn closing_the_receiver_fails_the_sender() {
+        run(async {
+            let (tx, rx) = bounded(1, OverflowPolicy::Block);
+            tx.send(slot(1)).await.unwrap();
+
+            let sender = tokio::spawn(async move { tx.send(slot(2)).await });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(rx);
+
+            // the waiting sender is woken up
+            assert!(sender.await.unwrap().is_err());
+        });
+    }
+}
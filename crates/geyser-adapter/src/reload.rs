@@ -0,0 +1,128 @@
+use std::{fs, path::Path, time::Duration, time::SystemTime};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::watch,
+};
+use yellowstone_grpc_proto::geyser::SubscribeRequest;
+
+use crate::{
+    filter::Filters,
+    validate::{Severity, request_to_json, validate_filters},
+};
+
+/// How often the filters file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// reloads the filters whenever the file changes or a SIGHUP arrives, and
+// hands every valid change to the running sessions. invalid files are
+// reported and the previous filters kept
+pub async fn watch_filters(path: String, filters: watch::Sender<Filters>) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut modified = modified_at(&path);
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                println!("🔄 SIGHUP received, reloading {}", path);
+            }
+            _ = interval.tick() => {
+                let current = modified_at(&path);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+                println!("🔄 {} changed, reloading", path);
+            }
+        }
+
+        let Some(new_filters) = load(&path) else {
+            continue;
+        };
+        let changes = request_diff(
+            &filters.borrow().to_subscribe_request(None),
+            &new_filters.to_subscribe_request(None),
+        );
+        if changes.is_empty() {
+            println!("🔄 Filters unchanged");
+            continue;
+        }
+
+        println!("🔄 Filters changed:");
+        for change in &changes {
+            println!("    {}", change);
+        }
+        // only fails once every session is gone, which ends the adapter anyway
+        if filters.send(new_filters).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(Path::new(path))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+fn load(path: &str) -> Option<Filters> {
+    let filters = match Filters::from_file(path) {
+        Ok(filters) => filters,
+        Err(e) => {
+            eprintln!("❌ Keeping the current filters: {}", e);
+            return None;
+        }
+    };
+
+    let issues = validate_filters(&filters);
+    for issue in &issues {
+        eprintln!("⚠️ {}", issue);
+    }
+    if issues.iter().any(|i| i.severity == Severity::Error) {
+        eprintln!("❌ Keeping the current filters, {} is invalid", path);
+        return None;
+    }
+    Some(filters)
+}
+
+// lists the filter groups that were added (+), removed (-) or changed (~)
+// between two requests, along with the changed request wide options
+fn request_diff(old: &SubscribeRequest, new: &SubscribeRequest) -> Vec<String> {
+    let (Value::Object(old), Value::Object(new)) = (request_to_json(old), request_to_json(new))
+    else {
+        return vec![];
+    };
+
+    let mut changes = vec![];
+    for (key, new_value) in &new {
+        let old_value = old.get(key).unwrap_or(&Value::Null);
+        match (old_value, new_value) {
+            // maps of named filter groups
+            (Value::Object(old_groups), Value::Object(new_groups)) => {
+                for (name, group) in new_groups {
+                    match old_groups.get(name) {
+                        None => changes.push(format!("+ {}.{}: {}", key, name, group)),
+                        Some(old_group) if old_group != group => {
+                            changes.push(format!("~ {}.{}: {} -> {}", key, name, old_group, group))
+                        }
+                        Some(_) => {}
+                    }
+                }
+                for name in old_groups
+                    .keys()
+                    .filter(|name| !new_groups.contains_key(*name))
+                {
+                    changes.push(format!("- {}.{}", key, name));
+                }
+            }
+            (old_value, new_value) if old_value != new_value => {
+                changes.push(format!("~ {}: {} -> {}", key, old_value, new_value))
+            }
+            _ => {}
+        }
+    }
+    changes
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use core::UpdateKind;
+
+/// Size after which a new segment is started
+const SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+const SEGMENT_EXTENSION: &str = "spill";
+/// File keeping how far the oldest segment was replayed, as `<seq> <count>`
+const REPLAYED_FILE: &str = "replayed";
+
+/// A spilled message, the channel it goes to and its payload
+pub type SpilledMessage = (&'static str, Vec<u8>);
+
+/// Messages that couldn't be published, kept on disk in the order they were
+/// spilled until redis is reachable again. the messages are appended to
+/// numbered segment files, a segment is deleted once all of it is replayed
+pub struct SpillBuffer {
+    dir: PathBuf,
+    /// bytes the segments may take up at most
+    max_bytes: u64,
+    /// every segment on disk, oldest first, the last one being appended to
+    segments: VecDeque<Segment>,
+    /// the last segment, while it is open for appending
+    writer: Option<BufWriter<File>>,
+    /// size after which a new segment is started
+    segment_bytes: u64,
+    /// the messages of the oldest segment, read once it is replayed
+    oldest: Option<Vec<SpilledMessage>>,
+    /// messages of the oldest segment already replayed, kept in
+    /// `REPLAYED_FILE` so a restart doesn't replay them again
+    replayed: usize,
+    /// messages dropped because the buffer was full
+    dropped: u64,
+}
+
+#[derive(Debug)]
+struct Segment {
+    seq: u64,
+    path: PathBuf,
+    bytes: u64,
+}
+
+impl SpillBuffer {
+    // opens the buffer in `dir`, segments left by a previous run are
+    // replayed first
+    pub fn open(dir: impl AsRef<Path>, max_bytes: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create spill dir {}", dir.display()))?;
+
+        let mut segments = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let Some(seq) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+            else {
+                continue;
+            };
+            let bytes = fs::metadata(&path)?.len();
+            segments.push(Segment { seq, path, bytes });
+        }
+        segments.sort_by_key(|s| s.seq);
+
+        // the offset only counts for the segment it was written for
+        let replayed = match (read_replayed(&dir)?, segments.first()) {
+            (Some((seq, count)), Some(oldest)) if seq == oldest.seq => count,
+            _ => 0,
+        };
+
+        Ok(SpillBuffer {
+            dir,
+            max_bytes,
+            segments: segments.into(),
+            writer: None,
+            segment_bytes: SEGMENT_BYTES,
+            oldest: None,
+            replayed,
+            dropped: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.segments.iter().map(|s| s.bytes).sum()
+    }
+
+    // appends the messages and syncs them to disk. returns false, writing
+    // nothing, when they would take the buffer over its size
+    pub fn append(&mut self, messages: &[SpilledMessage]) -> Result<bool> {
+        let size: u64 = messages.iter().map(|(c, p)| record_len(c, p)).sum();
+        if self.bytes() + size > self.max_bytes {
+            self.dropped += messages.len() as u64;
+            return Ok(false);
+        }
+
+        // a segment that can't be created leaves nothing to undo
+        let writer = self.writer()?;
+        if let Err(e) = write_records(writer, messages) {
+            // the writer is dropped before truncating, it flushes what it
+            // still buffers when dropped. the next append starts a new segment
+            self.writer = None;
+            if let Some(segment) = self.segments.back()
+                && let Err(t) = truncate(&segment.path, segment.bytes)
+            {
+                eprintln!(
+                    "⚠️ Failed to truncate {} after a failed write: {:?}",
+                    segment.path.display(),
+                    t
+                );
+            }
+            return Err(e);
+        }
+
+        let segment = self.segments.back_mut().expect("segment being written");
+        segment.bytes += size;
+        if segment.bytes >= self.segment_bytes {
+            self.writer = None;
+        }
+        Ok(true)
+    }
+
+    // the next messages to replay, at most `max` of the oldest segment.
+    // none once everything is replayed
+    pub fn next_batch(&mut self, max: usize) -> Result<Option<&[SpilledMessage]>> {
+        loop {
+            let Some(segment) = self.segments.front() else {
+                return Ok(None);
+            };
+            if self.oldest.is_none() {
+                // the segment is done with once replayed, nothing more goes into it
+                if self.segments.len() == 1 {
+                    self.writer = None;
+                }
+                let data = fs::read(&segment.path)
+                    .with_context(|| format!("Failed to read {}", segment.path.display()))?;
+                self.oldest = Some(read_records(&data, &segment.path));
+            }
+
+            let len = self.oldest.as_ref().map_or(0, |m| m.len());
+            if self.replayed < len {
+                let end = len.min(self.replayed + max);
+                let messages = self.oldest.as_deref().expect("read above");
+                return Ok(Some(&messages[self.replayed..end]));
+            }
+            self.remove_oldest()?;
+        }
+    }
+
+    // records that the `count` messages returned by `next_batch` were
+    // published, the oldest segment is deleted once all of it is
+    pub fn mark_replayed(&mut self, count: usize) -> Result<()> {
+        self.replayed += count;
+        let total = self.oldest.as_ref().map_or(0, |m| m.len());
+        if self.replayed >= total {
+            return self.remove_oldest();
+        }
+        let seq = self.segments.front().expect("segment being replayed").seq;
+        write_replayed(&self.dir, seq, self.replayed)
+    }
+
+    fn remove_oldest(&mut self) -> Result<()> {
+        if let Some(segment) = self.segments.pop_front() {
+            fs::remove_file(&segment.path)
+                .with_context(|| format!("Failed to delete {}", segment.path.display()))?;
+        }
+        self.oldest = None;
+        self.replayed = 0;
+        let path = self.dir.join(REPLAYED_FILE);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to delete {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn writer(&mut self) -> Result<&mut BufWriter<File>> {
+        if self.writer.is_none() {
+            let seq = self.segments.back().map_or(0, |s| s.seq + 1);
+            let path = self.dir.join(format!("{:020}.{}", seq, SEGMENT_EXTENSION));
+            let file = OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            self.segments.push_back(Segment {
+                seq,
+                path,
+                bytes: 0,
+            });
+            self.writer = Some(BufWriter::new(file));
+        }
+        Ok(self.writer.as_mut().expect("writer opened above"))
+    }
+}
+
+impl fmt::Display for SpillBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} segment(s), {} of {} bytes used, dropped {}",
+            self.segments.len(),
+            self.bytes(),
+            self.max_bytes,
+            self.dropped
+        )
+    }
+}
+
+// writes the messages and syncs them to disk
+fn write_records(writer: &mut BufWriter<File>, messages: &[SpilledMessage]) -> Result<()> {
+    for (channel, payload) in messages {
+        writer.write_all(&[channel.len() as u8])?;
+        writer.write_all(channel.as_bytes())?;
+        writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        writer.write_all(payload)?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_data()?;
+    Ok(())
+}
+
+fn truncate(path: &Path, len: u64) -> Result<()> {
+    OpenOptions::new().write(true).open(path)?.set_len(len)?;
+    Ok(())
+}
+
+// the segment and the number of its messages replayed, if any were
+fn read_replayed(dir: &Path) -> Result<Option<(u64, usize)>> {
+    let path = dir.join(REPLAYED_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let parsed = text
+        .split_once(' ')
+        .and_then(|(seq, count)| Some((seq.parse().ok()?, count.trim().parse().ok()?)));
+    if parsed.is_none() {
+        eprintln!("⚠️ Ignoring unreadable {}", path.display());
+    }
+    Ok(parsed)
+}
+
+// written to a temporary file first so a crash leaves the old offset or the
+// new one. an offset lost to a crash replays some messages twice
+fn write_replayed(dir: &Path, seq: u64, count: usize) -> Result<()> {
+    let path = dir.join(REPLAYED_FILE);
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{} {}\n", seq, count))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// channel length, channel, payload length and payload
+fn record_len(channel: &str, payload: &[u8]) -> u64 {
+    (1 + channel.len() + 4 + payload.len()) as u64
+}
+
+// reads the records of a segment, a record cut short by a crash ends it
+fn read_records(mut data: &[u8], path: &Path) -> Vec<SpilledMessage> {
+    let mut messages = vec![];
+    while !data.is_empty() {
+        let Some((channel, payload, rest)) = read_record(data) else {
+            eprintln!(
+                "⚠️ Ignoring {} truncated byte(s) at the end of {}",
+                data.len(),
+                path.display()
+            );
+            break;
+        };
+        data = rest;
+        match UpdateKind::from_channel(channel) {
+            Some(kind) => messages.push((kind.channel(), payload.to_vec())),
+            None => eprintln!(
+                "⚠️ Skipping spilled message for unknown channel '{}'",
+                channel
+            ),
+        }
+    }
+    messages
+}
+
+fn read_record(data: &[u8]) -> Option<(&str, &[u8], &[u8])> {
+    let (&channel_len, data) = data.split_first()?;
+    let (channel, data) = data.split_at_checked(channel_len as usize)?;
+    let (len, data) = data.split_at_checked(4)?;
+    let len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+    let (payload, rest) = data.split_at_checked(len)?;
+    Some((std::str::from_utf8(channel).ok()?, payload, rest))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // a fresh spill dir, removed when dropped
+    pub(crate) struct TestDir(pub(crate) PathBuf);
+
+    impl TestDir {
+        pub(crate) fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("spill-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TestDir(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn message(n: u8) -> SpilledMessage {
+        (UpdateKind::Slot.channel(), vec![n; 10])
+    }
+
+    // replays everything left, `max` messages at a time
+    fn replay_all(spill: &mut SpillBuffer, max: usize) -> Vec<SpilledMessage> {
+        let mut replayed = vec![];
+        while let Some(chunk) = spill.next_batch(max).unwrap() {
+            let count = chunk.len();
+            replayed.extend_from_slice(chunk);
+            spill.mark_replayed(count).unwrap();
+        }
+        replayed
+    }
+
+    #[test]
+    fn replays_in_spill_order_across_segments() {
+        let dir = TestDir::new("order");
+        let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+        spill.segment_bytes = 40;
+
+        let messages: Vec<_> = (0..10).map(message).collect();
+        for pair in messages.chunks(2) {
+            assert!(spill.append(pair).unwrap());
+        }
+        assert!(spill.segments.len() > 1);
+
+        assert_eq!(replay_all(&mut spill, 3), messages);
+        assert!(spill.is_empty());
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn reopening_continues_after_the_replayed_messages() {
+        let dir = TestDir::new("offset");
+        let messages: Vec<_> = (0..6).map(message).collect();
+        {
+            let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+            assert!(spill.append(&messages).unwrap());
+            let count = spill.next_batch(4).unwrap().unwrap().len();
+            spill.mark_replayed(count).unwrap();
+        }
+
+        let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+        assert_eq!(replay_all(&mut spill, 4), messages[4..]);
+    }
+
+    #[test]
+    fn a_truncated_record_is_dropped_and_the_rest_replayed() {
+        let dir = TestDir::new("truncated");
+        let messages: Vec<_> = (0..3).map(message).collect();
+        let path = {
+            let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+            assert!(spill.append(&messages).unwrap());
+            spill.segments[0].path.clone()
+        };
+        // a record cut short by a crash while it was written
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[4, b's', b'l']).unwrap();
+
+        let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+        assert_eq!(replay_all(&mut spill, 10), messages);
+        assert!(spill.is_empty());
+    }
+
+    #[test]
+    fn a_segment_that_cant_be_created_fails_the_append() {
+        let dir = TestDir::new("create");
+        let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+        // takes the name of the first segment
+        fs::create_dir(dir.0.join(format!("{:020}.{}", 0, SEGMENT_EXTENSION))).unwrap();
+
+        assert!(spill.append(&[message(0)]).is_err());
+        assert!(spill.is_empty());
+    }
+
+    #[test]
+    fn a_failed_new_segment_leaves_the_older_ones_alone() {
+        let dir = TestDir::new("older");
+        let messages: Vec<_> = (0..3).map(message).collect();
+        {
+            let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+            assert!(spill.append(&messages).unwrap());
+        }
+        // reopened, so the next append starts segment 1
+        let mut spill = SpillBuffer::open(&dir.0, u64::MAX).unwrap();
+        let bytes = spill.bytes();
+        fs::create_dir(dir.0.join(format!("{:020}.{}", 1, SEGMENT_EXTENSION))).unwrap();
+
+        assert!(spill.append(&[message(3)]).is_err());
+        assert_eq!(spill.bytes(), bytes);
+        assert_eq!(replay_all(&mut spill, 10), messages);
+    }
+
+    #[test]
+    fn a_full_buffer_drops_the_batch() {
+        let dir = TestDir::new("full");
+        let mut spill = SpillBuffer::open(&dir.0, 40).unwrap();
+        assert!(spill.append(&[message(0), message(1)]).unwrap());
+        assert!(!spill.append(&[message(2)]).unwrap());
+        assert_eq!(replay_all(&mut spill, 10), vec![message(0), message(1)]);
+    }
+}
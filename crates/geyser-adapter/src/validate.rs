@@ -0,0 +1,533 @@
+use std::{collections::HashMap, fmt};
+
+use serde_json::{Map, Value, json};
+use yellowstone_grpc_proto::geyser::{
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccountsFilter,
+    SubscribeRequestFilterTransactions,
+    subscribe_request_filter_accounts_filter::Filter as AccountsFilterOneof,
+    subscribe_request_filter_accounts_filter_memcmp::Data as MemcmpData,
+};
+
+use crate::filter::{DataSlice, Filters, GroupName, TxFilter};
+
+/// Longest memcmp data the geyser plugin accepts, in bytes
+const MAX_MEMCMP_BYTES: usize = 128;
+/// Pubkeys per list above which a warning is given when filters.json sets no
+/// `max_pubkeys` limit of its own. Providers cap these lists, often far below
+const DEFAULT_MAX_PUBKEYS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// the request is sent, but probably doesn't do what was meant
+    Warning,
+    /// the request would be rejected by the server
+    Error,
+}
+
+/// A problem found in filters.json, `path` points at the offending value
+#[derive(Debug, Clone)]
+pub struct FilterIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for FilterIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Issues(Vec<FilterIssue>);
+
+impl Issues {
+    fn error(&mut self, path: String, message: String) {
+        self.0.push(FilterIssue {
+            severity: Severity::Error,
+            path,
+            message,
+        });
+    }
+
+    fn warning(&mut self, path: String, message: String) {
+        self.0.push(FilterIssue {
+            severity: Severity::Warning,
+            path,
+            message,
+        });
+    }
+
+    // every entry has to be a base58 encoded 32 byte pubkey, and the list has
+    // to stay below the limit of the provider
+    fn pubkeys(&mut self, path: &str, keys: &[String], max_pubkeys: Option<usize>) {
+        for (i, key) in keys.iter().enumerate() {
+            match bs58::decode(key).into_vec() {
+                Ok(bytes) if bytes.len() == 32 => {}
+                Ok(bytes) => self.error(
+                    format!("{}[{}]", path, i),
+                    format!(
+                        "'{}' decodes to {} bytes, a pubkey has 32",
+                        key,
+                        bytes.len()
+                    ),
+                ),
+                Err(e) => self.error(
+                    format!("{}[{}]", path, i),
+                    format!("'{}' is not a base58 pubkey: {}", key, e),
+                ),
+            }
+        }
+
+        match max_pubkeys {
+            Some(max) if keys.len() > max => self.error(
+                path.to_owned(),
+                format!("{} pubkeys, the provider accepts {}", keys.len(), max),
+            ),
+            None if keys.len() > DEFAULT_MAX_PUBKEYS => self.warning(
+                path.to_owned(),
+                format!(
+                    "{} pubkeys, providers usually reject more than {}",
+                    keys.len(),
+                    DEFAULT_MAX_PUBKEYS
+                ),
+            ),
+            _ => {}
+        }
+    }
+
+    // transactions and transaction statuses share their filter
+    fn transaction_filters(
+        &mut self,
+        kind: &str,
+        filters: &[TxFilter],
+        max_pubkeys: Option<usize>,
+    ) {
+        for (i, tx) in filters.iter().enumerate() {
+            let path = format!("{}[{}]", kind, i);
+            self.pubkeys(
+                &format!("{}.account_include", path),
+                &tx.account_include,
+                max_pubkeys,
+            );
+            self.pubkeys(
+                &format!("{}.account_exclude", path),
+                &tx.account_exclude,
+                max_pubkeys,
+            );
+            self.pubkeys(
+                &format!("{}.account_required", path),
+                &tx.account_required,
+                max_pubkeys,
+            );
+        }
+    }
+
+    // a second group of a kind with the same name would replace the first
+    // one, whether the names were given, generated or come from a flag
+    fn group_names(&mut self, kind: &str, groups: &[GroupName]) {
+        let mut seen: HashMap<&str, &str> = HashMap::new();
+        for group in groups {
+            if group.name.is_empty() {
+                self.error(group.path.clone(), "the name is empty".to_owned());
+            } else if let Some(earlier) = seen.insert(&group.name, &group.path) {
+                self.error(
+                    group.path.clone(),
+                    format!(
+                        "'{}' is already the name of the {} group of {}, only the last one is kept",
+                        group.name, kind, earlier
+                    ),
+                );
+            }
+        }
+    }
+}
+
+// checks filters.json for everything the server would reject or silently
+// ignore, without connecting
+pub fn validate_filters(filters: &Filters) -> Vec<FilterIssue> {
+    let mut issues = Issues::default();
+    let max_pubkeys = filters.limits.as_ref().and_then(|l| l.max_pubkeys);
+
+    for (kind, groups) in filters.group_names() {
+        issues.group_names(kind, &groups);
+    }
+
+    for (i, acc) in filters.accounts.iter().enumerate() {
+        let path = format!("accounts[{}]", i);
+        issues.pubkeys(&format!("{}.accounts", path), &acc.accounts, max_pubkeys);
+        issues.pubkeys(&format!("{}.owners", path), &acc.owners, max_pubkeys);
+
+        if acc.accounts.is_empty() && acc.owners.is_empty() && acc.filters.is_empty() {
+            issues.warning(
+                path.clone(),
+                "no accounts, owners or filters, every account write on chain matches".to_owned(),
+            );
+        }
+
+        let datasizes: Vec<u64> = acc.filters.iter().filter_map(|f| f.datasize).collect();
+        if datasizes.len() > 1 {
+            issues.warning(
+                format!("{}.filters", path),
+                format!(
+                    "{} datasize values {:?}, only the first one ({}) is used",
+                    datasizes.len(),
+                    datasizes,
+                    datasizes[0]
+                ),
+            );
+        }
+
+        for (j, filter) in acc.filters.iter().enumerate() {
+            for (k, memcmp) in filter.memcmp.iter().enumerate() {
+                let path = format!("{}.filters[{}].memcmp[{}].base58", path, j, k);
+                match bs58::decode(&memcmp.base58).into_vec() {
+                    Ok(bytes) if bytes.len() > MAX_MEMCMP_BYTES => issues.error(
+                        path,
+                        format!(
+                            "{} bytes of data, at most {} are allowed",
+                            bytes.len(),
+                            MAX_MEMCMP_BYTES
+                        ),
+                    ),
+                    Ok(bytes) if bytes.is_empty() => {
+                        issues.warning(path, "empty data matches every account".to_owned())
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        issues.error(path, format!("'{}' is not base58: {}", memcmp.base58, e))
+                    }
+                }
+            }
+        }
+    }
+
+    issues.transaction_filters("transactions", &filters.transactions, max_pubkeys);
+    issues.transaction_filters(
+        "transactions_status",
+        &filters.transactions_status,
+        max_pubkeys,
+    );
+
+    for (i, block) in filters.blocks.iter().enumerate() {
+        let path = format!("blocks[{}]", i);
+        issues.pubkeys(
+            &format!("{}.account_include", path),
+            &block.account_include,
+            max_pubkeys,
+        );
+    }
+    if let Some(keys) = &filters.block_accounts {
+        issues.pubkeys("block_accounts", keys, max_pubkeys);
+        if !filters.include_blocks {
+            issues.warning(
+                "block_accounts".to_owned(),
+                "ignored, include_blocks is false".to_owned(),
+            );
+        }
+    }
+
+    // the server rejects overlapping slices, they are sent ordered by offset
+    if let Some(slices) = &filters.accounts_data_slice {
+        let mut ordered: Vec<(usize, &DataSlice)> = slices.iter().enumerate().collect();
+        ordered.sort_by_key(|(_, slice)| slice.offset);
+        for pair in ordered.windows(2) {
+            let ((i, a), (j, b)) = (pair[0], pair[1]);
+            if a.offset.saturating_add(a.length) > b.offset {
+                issues.error(
+                    format!("accounts_data_slice[{}]", j),
+                    format!(
+                        "overlaps accounts_data_slice[{}] ({}..{})",
+                        i,
+                        a.offset,
+                        a.offset.saturating_add(a.length)
+                    ),
+                );
+            }
+        }
+        for (i, slice) in slices.iter().enumerate() {
+            if slice.length == 0 {
+                issues.warning(
+                    format!("accounts_data_slice[{}]", i),
+                    "empty slice".to_owned(),
+                );
+            }
+        }
+    }
+
+    issues.0
+}
+
+// the subscribe request in the shape of its protobuf definition, the
+// generated types don't implement serialize
+pub fn request_to_json(request: &SubscribeRequest) -> Value {
+    let accounts: Map<String, Value> = request
+        .accounts
+        .iter()
+        .map(|(name, acc)| {
+            let filter = json!({
+                "account": acc.account,
+                "owner": acc.owner,
+                "filters": acc.filters.iter().map(accounts_filter_to_json).collect::<Vec<_>>(),
+                "nonempty_txn_signature": acc.nonempty_txn_signature,
+            });
+            (name.clone(), filter)
+        })
+        .collect();
+
+    let slots: Map<String, Value> = request
+        .slots
+        .iter()
+        .map(|(name, slot)| {
+            let filter = json!({
+                "filter_by_commitment": slot.filter_by_commitment,
+                "interslot_updates": slot.interslot_updates,
+            });
+            (name.clone(), filter)
+        })
+        .collect();
+
+    let blocks: Map<String, Value> = request
+        .blocks
+        .iter()
+        .map(|(name, block)| {
+            let filter = json!({
+                "account_include": block.account_include,
+                "include_transactions": block.include_transactions,
+                "include_accounts": block.include_accounts,
+                "include_entries": block.include_entries,
+            });
+            (name.clone(), filter)
+        })
+        .collect();
+
+    json!({
+        "accounts": accounts,
+        "slots": slots,
+        "transactions": transactions_to_json(&request.transactions),
+        "transactions_status": transactions_to_json(&request.transactions_status),
+        "blocks": blocks,
+        "blocks_meta": names_to_json(request.blocks_meta.keys()),
+        "entry": names_to_json(request.entry.keys()),
+        "commitment": request
+            .commitment
+            .and_then(|c| CommitmentLevel::try_from(c).ok())
+            .map(|c| c.as_str_name().to_lowercase()),
+        "accounts_data_slice": request
+            .accounts_data_slice
+            .iter()
+            .map(|slice| json!({ "offset": slice.offset, "length": slice.length }))
+            .collect::<Vec<_>>(),
+        "ping": request.ping.as_ref().map(|ping| json!({ "id": ping.id })),
+        "from_slot": request.from_slot,
+    })
+}
+
+fn transactions_to_json(
+    filters: &HashMap<String, SubscribeRequestFilterTransactions>,
+) -> Map<String, Value> {
+    filters
+        .iter()
+        .map(|(name, tx)| {
+            let filter = json!({
+                "vote": tx.vote,
+                "failed": tx.failed,
+                "signature": tx.signature,
+                "account_include": tx.account_include,
+                "account_exclude": tx.account_exclude,
+                "account_required": tx.account_required,
+            });
+            (name.clone(), filter)
+        })
+        .collect()
+}
+
+// groups of filters without options
+fn names_to_json<'a>(names: impl Iterator<Item = &'a String>) -> Map<String, Value> {
+    names.map(|name| (name.clone(), json!({}))).collect()
+}
+
+fn accounts_filter_to_json(filter: &SubscribeRequestFilterAccountsFilter) -> Value {
+    match &filter.filter {
+        Some(AccountsFilterOneof::Memcmp(memcmp)) => {
+            let data = match &memcmp.data {
+                Some(MemcmpData::Bytes(bytes)) => json!({ "bytes": bytes }),
+                Some(MemcmpData::Base58(data)) => json!({ "base58": data }),
+                Some(MemcmpData::Base64(data)) => json!({ "base64": data }),
+                None => Value::Null,
+            };
+            json!({ "memcmp": { "offset": memcmp.offset, "data": data } })
+        }
+        Some(AccountsFilterOneof::Datasize(size)) => json!({ "datasize": size }),
+        Some(AccountsFilterOneof::TokenAccountState(state)) => {
+            json!({ "token_account_state": state })
+        }
+        Some(AccountsFilterOneof::Lamports(lamports)) => {
+            json!({ "lamports": format!("{:?}", lamports.cmp) })
+        }
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+
+    fn issues(filters: serde_json::Value) -> Vec<(Severity, String)> {
+        let filters: Filters = serde_json::from_value(filters).unwrap();
+        validate_filters(&filters)
+            .into_iter()
+            .map(|issue| (issue.severity, issue.path))
+            .collect()
+    }
+
+    fn errors(filters: serde_json::Value) -> Vec<String> {
+        issues(filters)
+            .into_iter()
+            .filter(|(severity, _)| *severity == Severity::Error)
+            .map(|(_, path)| path)
+            .collect()
+    }
+
+    fn account_filters(filters: serde_json::Value) -> serde_json::Value {
+        json!({
+            "accounts": [{ "accounts": [], "owners": [SYSTEM_PROGRAM], "filters": filters }],
+        })
+    }
+
+    fn memcmp(bytes: usize) -> serde_json::Value {
+        let data = bs58::encode(vec![1u8; bytes]).into_string();
+        account_filters(json!([{ "memcmp": [{ "offset": 0, "base58": data }] }]))
+    }
+
+    #[test]
+    fn invalid_pubkeys_are_errors() {
+        let filters = json!({
+            "accounts": [{
+                "accounts": ["not base58!"],
+                // valid base58, but 3 bytes
+                "owners": ["abc", SYSTEM_PROGRAM],
+                "filters": [],
+            }],
+        });
+        assert_eq!(
+            issues(filters),
+            vec![
+                (Severity::Error, "accounts[0].accounts[0]".to_owned()),
+                (Severity::Error, "accounts[0].owners[0]".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn memcmp_data_over_128_bytes_is_an_error() {
+        assert!(issues(memcmp(128)).is_empty());
+        assert_eq!(
+            issues(memcmp(129)),
+            vec![(
+                Severity::Error,
+                "accounts[0].filters[0].memcmp[0].base58".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn several_datasizes_are_a_warning() {
+        let filters = account_filters(json!([
+            { "memcmp": [], "datasize": 165 },
+            { "memcmp": [], "datasize": 82 },
+        ]));
+        assert_eq!(
+            issues(filters),
+            vec![(Severity::Warning, "accounts[0].filters".to_owned())]
+        );
+    }
+
+    #[test]
+    fn account_include_over_max_pubkeys_is_an_error() {
+        let transactions = json!({
+            "account_include": [SYSTEM_PROGRAM, SYSTEM_PROGRAM, SYSTEM_PROGRAM],
+            "account_exclude": [],
+            "account_required": [],
+        });
+        let filters = json!({
+            "accounts": [],
+            "transactions": transactions.clone(),
+            "limits": { "max_pubkeys": 2 },
+        });
+        assert_eq!(
+            issues(filters),
+            vec![(
+                Severity::Error,
+                "transactions[0].account_include".to_owned()
+            )]
+        );
+
+        let filters = json!({
+            "accounts": [],
+            "transactions": transactions,
+            "limits": { "max_pubkeys": 3 },
+        });
+        assert!(issues(filters).is_empty());
+    }
+
+    #[test]
+    fn account_include_over_the_usual_limit_is_a_warning() {
+        let filters = json!({
+            "accounts": [],
+            "transactions": {
+                "account_include": vec![SYSTEM_PROGRAM; DEFAULT_MAX_PUBKEYS + 1],
+                "account_exclude": [],
+                "account_required": [],
+            },
+        });
+        assert_eq!(
+            issues(filters),
+            vec![(
+                Severity::Warning,
+                "transactions[0].account_include".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn flag_groups_collide_with_named_groups() {
+        let filters = json!({
+            "accounts": [],
+            "include_blocks": true,
+            "include_slots": true,
+            "blocks": { "name": "blocks" },
+            "slots": [{ "name": "slots" }],
+        });
+        assert_eq!(errors(filters), vec!["include_blocks", "include_slots"]);
+    }
+
+    #[test]
+    fn generated_names_collide_with_given_ones() {
+        let filters = json!({
+            "accounts": [
+                { "name": "accounts_1", "accounts": [], "owners": [], "filters": [] },
+                { "accounts": [], "owners": [], "filters": [] },
+            ],
+            "entry": [{}, { "name": "entry_0" }],
+        });
+        assert_eq!(errors(filters), vec!["accounts[1]", "entry[1].name"]);
+    }
+
+    #[test]
+    fn kinds_have_their_own_names() {
+        let filters = json!({
+            "accounts": [{ "name": "main", "accounts": [], "owners": [], "filters": [] }],
+            "transactions": { "name": "main", "account_include": [], "account_exclude": [], "account_required": [] },
+            "include_slots": true,
+            "include_blocks_meta": true,
+        });
+        assert!(errors(filters).is_empty());
+    }
+}
@@ -1,67 +1,750 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
-use futures::StreamExt;
-use redis::Client;
+use redis::{
+    AsyncCommands, Client,
+    aio::ConnectionManager,
+    streams::{
+        StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamMaxlen,
+        StreamPendingCountReply, StreamReadOptions, StreamReadReply,
+    },
+};
+use tokio::sync::OnceCell;
+
+// field of the stream entry holding the published payload
+const PAYLOAD_FIELD: &str = "payload";
+/// Delay before a handler that is unavailable is retried, doubled after
+/// every failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound for the delay between two attempts of an unavailable handler,
+/// or of a consumer that lost redis
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A handler failure unrelated to the messages it was given, like a lost
+/// database connection. the messages stay pending and the handler is retried
+/// with backoff, without counting it as a failed delivery
+#[derive(Debug)]
+pub struct Unavailable(pub anyhow::Error);
 
-// A trait for publishing messages to a channel
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+// A trait for publishing messages to a channel, payloads are raw bytes
+// stored as they are
 #[async_trait::async_trait]
 pub trait Publisher: Send + Sync {
     async fn publisher(&self, channel: &str, payload: &[u8]) -> Result<(), anyhow::Error>;
+
+    // publishes `(channel, payload)` messages in order, implementations may
+    // send them all in a single round trip
+    async fn publish_batch(&self, messages: &[(&str, Vec<u8>)]) -> Result<(), anyhow::Error> {
+        for (channel, payload) in messages {
+            self.publisher(channel, payload).await?;
+        }
+        Ok(())
+    }
 }
 
+// handlers are async so blocking work can be moved off the runtime, nothing
+// new is read until the future of the previous messages resolved
 #[async_trait::async_trait]
 pub trait Consumer: Send + Sync {
-    async fn consumer<F>(&self, channel: &str, handler: F) -> Result<(), anyhow::Error>
+    async fn consumer<F, Fut>(&self, channel: &str, handler: F) -> Result<(), anyhow::Error>
+    where
+        F: FnMut(Vec<u8>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static;
+
+    // hands messages to the handler in batches of up to `max_items`, waiting at
+    // most `max_wait` after the first message for the batch to fill up
+    async fn consumer_batch<F, Fut>(
+        &self,
+        channel: &str,
+        max_items: usize,
+        max_wait: Duration,
+        handler: F,
+    ) -> Result<(), anyhow::Error>
     where
-        F: FnMut(String) -> Result<()> + Send + 'static;
+        F: FnMut(Vec<Vec<u8>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static;
 }
 
+/// Settings for the redis streams backing every channel
+#[derive(Clone, Debug)]
+pub struct StreamConfig {
+    /// consumer group shared by every consumer of a channel
+    pub group: String,
+    /// name of this consumer within the group, must be unique per process
+    pub consumer: String,
+    /// approximate number of entries kept per stream, unset keeps them all.
+    /// trimming drops the oldest entries whether they were consumed or not
+    pub max_len: Option<usize>,
+    /// entries claimed per XAUTOCLAIM call
+    pub batch_size: usize,
+    /// how long XREADGROUP waits for new entries
+    pub block_ms: usize,
+    /// entries pending for longer than this are claimed from other consumers
+    pub claim_idle_ms: usize,
+    /// failed deliveries after which an entry is moved to the dead letter stream
+    pub max_deliveries: u32,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            group: "db".to_owned(),
+            consumer: format!(
+                "{}-{}",
+                std::env::var("HOSTNAME").unwrap_or_else(|_| "consumer".to_owned()),
+                std::process::id()
+            ),
+            max_len: None,
+            batch_size: 100,
+            block_ms: 5_000,
+            claim_idle_ms: 30_000,
+            max_deliveries: 5,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Redis {
     pub client: Client,
+    pub config: StreamConfig,
+    /// connection shared by every publish, opened on first use
+    publish_conn: Arc<OnceCell<ConnectionManager>>,
 }
 
 impl Redis {
     pub fn new(rpc_url: &str) -> Result<Self> {
         let client = Client::open(rpc_url)?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            config: StreamConfig::default(),
+            publish_conn: Arc::new(OnceCell::new()),
+        })
     }
-}
 
-#[async_trait::async_trait]
-impl Consumer for Redis {
-    async fn consumer<F>(&self, channel: &str, mut handler: F) -> Result<(), anyhow::Error>
+    pub fn with_stream_config(mut self, config: StreamConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // the manager reconnects on its own once redis is back, a failed first
+    // connect is retried on the next publish
+    async fn publish_connection(&self) -> Result<ConnectionManager> {
+        let conn = self
+            .publish_conn
+            .get_or_try_init(|| self.client.get_connection_manager())
+            .await?;
+        Ok(conn.clone())
+    }
+
+    // creates the consumer group along with the stream, starting from the
+    // oldest entry so nothing published before the first consumer is lost
+    async fn ensure_group(&self, conn: &mut ConnectionManager, channel: &str) -> Result<()> {
+        let created: redis::RedisResult<()> = conn
+            .xgroup_create_mkstream(channel, &self.config.group, "0")
+            .await;
+        match created {
+            Ok(()) => Ok(()),
+            Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // claims entries left pending by crashed consumers or failed handlers
+    async fn claim_stuck<F, Fut>(
+        &self,
+        conn: &mut ConnectionManager,
+        channel: &str,
+        handler: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(Vec<Vec<u8>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut start = "0-0".to_owned();
+        loop {
+            let reply: StreamAutoClaimReply = conn
+                .xautoclaim_options(
+                    channel,
+                    &self.config.group,
+                    &self.config.consumer,
+                    self.config.claim_idle_ms,
+                    &start,
+                    StreamAutoClaimOptions::default().count(self.config.batch_size),
+                )
+                .await?;
+
+            let deliveries = self.delivery_counts(conn, channel, &reply.claimed).await?;
+            let claimed = reply
+                .claimed
+                .into_iter()
+                .map(|entry| {
+                    let count = deliveries.get(&entry.id).copied().unwrap_or(1);
+                    (entry, count)
+                })
+                .collect();
+            self.handle_batch(conn, channel, claimed, handler).await?;
+
+            // a cursor of 0-0 means the whole pending list was scanned
+            if reply.next_stream_id == "0-0" {
+                return Ok(());
+            }
+            start = reply.next_stream_id;
+        }
+    }
+
+    // how often each entry was delivered, the claim included, as counted by
+    // redis. the lookups go out in a single round trip
+    async fn delivery_counts(
+        &self,
+        conn: &mut ConnectionManager,
+        channel: &str,
+        entries: &[StreamId],
+    ) -> Result<HashMap<String, usize>> {
+        if entries.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut pipe = redis::pipe();
+        for entry in entries {
+            pipe.xpending_count(channel, &self.config.group, &entry.id, &entry.id, 1);
+        }
+        let replies: Vec<StreamPendingCountReply> = pipe.query_async(conn).await?;
+        Ok(replies
+            .into_iter()
+            .flat_map(|reply| reply.ids)
+            .map(|pending| (pending.id, pending.times_delivered))
+            .collect())
+    }
+
+    // waits for new entries, then keeps reading until the batch is full or
+    // `max_wait` passed since the first entry arrived
+    async fn read_batch(
+        &self,
+        conn: &mut ConnectionManager,
+        channel: &str,
+        max_items: usize,
+        max_wait: Duration,
+    ) -> Result<Vec<StreamId>> {
+        let mut entries: Vec<StreamId> = Vec::new();
+        let mut deadline: Option<Instant> = None;
+
+        while entries.len() < max_items {
+            let block_ms = match deadline {
+                None => self.config.block_ms,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    // BLOCK 0 would wait forever
+                    (remaining.as_millis() as usize).max(1)
+                }
+            };
+
+            let options = StreamReadOptions::default()
+                .group(&self.config.group, &self.config.consumer)
+                .count(max_items - entries.len())
+                .block(block_ms);
+            let reply: StreamReadReply = conn.xread_options(&[channel], &[">"], &options).await?;
+            let read: Vec<StreamId> = reply.keys.into_iter().flat_map(|key| key.ids).collect();
+
+            if read.is_empty() && deadline.is_none() {
+                // nothing arrived while blocking, let the caller run its housekeeping
+                break;
+            }
+            deadline.get_or_insert_with(|| Instant::now() + max_wait);
+            entries.extend(read);
+        }
+
+        Ok(entries)
+    }
+
+    // runs the handler on a batch and acknowledges all of it once handled, a
+    // failed batch is retried entry by entry so a single bad entry can't hold
+    // back the others. every entry comes with its delivery count
+    async fn handle_batch<F, Fut>(
+        &self,
+        conn: &mut ConnectionManager,
+        channel: &str,
+        entries: Vec<(StreamId, usize)>,
+        handler: &mut F,
+    ) -> Result<()>
     where
-        F: FnMut(String) -> Result<()> + Send + 'static,
+        F: FnMut(Vec<Vec<u8>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
     {
-        let mut conn = self.client.get_async_pubsub().await?;
-        conn.subscribe(channel).await?;
-        let mut msg_stream = conn.on_message();
+        let mut ids: Vec<String> = Vec::with_capacity(entries.len());
+        let mut payloads: Vec<(String, Vec<u8>, usize)> = Vec::with_capacity(entries.len());
+        for (entry, deliveries) in entries {
+            match entry.get::<Vec<u8>>(PAYLOAD_FIELD) {
+                Some(payload) => payloads.push((entry.id, payload, deliveries)),
+                None => {
+                    eprintln!("Dropping stream entry {} without a payload", entry.id);
+                    ids.push(entry.id);
+                }
+            }
+        }
 
-        while let Some(msg) = msg_stream.next().await {
-            let payload: String = msg.get_payload()?;
+        // only an error about the messages is worth retrying one by one, an
+        // unavailable handler is waited for with the batch as it is
+        if payloads.len() > 1 {
+            let batch = payloads.iter().map(|(_, p, _)| p.clone()).collect();
+            match run_handler(handler, batch).await {
+                Ok(()) => ids.extend(payloads.drain(..).map(|(id, _, _)| id)),
+                Err(e) => eprintln!("Error handling batch, retrying one by one: {}", e),
+            }
+        }
 
-            if let Err(e) = handler(payload) {
-                eprintln!("Error handling message: {}", e);
+        for (id, payload, deliveries) in payloads {
+            if self
+                .handle_entry(conn, channel, &id, payload, deliveries, handler)
+                .await?
+            {
+                ids.push(id);
             }
         }
 
+        if !ids.is_empty() {
+            let _: usize = conn.xack(channel, &self.config.group, &ids).await?;
+        }
         Ok(())
     }
+
+    // runs the handler on a single entry, returning whether it can be
+    // acknowledged. an entry the handler fails on stays pending until it is
+    // claimed again, or is dead lettered once redis delivered it
+    // `max_deliveries` times
+    async fn handle_entry<F, Fut>(
+        &self,
+        conn: &mut ConnectionManager,
+        channel: &str,
+        id: &str,
+        payload: Vec<u8>,
+        deliveries: usize,
+        handler: &mut F,
+    ) -> Result<bool>
+    where
+        F: FnMut(Vec<Vec<u8>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let e = match run_handler(handler, vec![payload.clone()]).await {
+            Ok(()) => return Ok(true),
+            Err(e) => e,
+        };
+
+        eprintln!(
+            "Error handling message {} (delivery {}/{}): {}",
+            id, deliveries, self.config.max_deliveries, e
+        );
+        if deliveries < self.config.max_deliveries as usize {
+            return Ok(false);
+        }
+
+        // park the entry so a poison message doesn't block the group forever
+        let dead_letter = format!("{}:dead", channel);
+        let error = e.to_string();
+        let _: Option<String> = conn
+            .xadd(
+                &dead_letter,
+                "*",
+                &[
+                    (PAYLOAD_FIELD, payload.as_slice()),
+                    ("error", error.as_bytes()),
+                ],
+            )
+            .await?;
+        eprintln!("Moved entry {} to {}", id, dead_letter);
+        Ok(true)
+    }
+
+    // consumes the channel for good. a redis error is waited out with
+    // backoff while the connection manager reconnects, entries read but not
+    // acknowledged stay pending and are claimed again
+    async fn consume_group<F, Fut>(
+        &self,
+        channel: &str,
+        max_items: usize,
+        max_wait: Duration,
+        mut handler: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Vec<Vec<u8>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let claim_interval = Duration::from_millis(self.config.claim_idle_ms as u64);
+        let mut conn: Option<ConnectionManager> = None;
+        // checked again after every failure, a restarted redis may have lost it
+        let mut group_ready = false;
+        let mut last_claim: Option<Instant> = None;
+        let mut backoff = INITIAL_BACKOFF;
+
+        // nothing new is read while the handler works through a batch, so a
+        // slow handler leaves the backlog in redis instead of in memory
+        loop {
+            let step: Result<()> = async {
+                if conn.is_none() {
+                    conn = Some(self.client.get_connection_manager().await?);
+                }
+                let conn = conn.as_mut().expect("connected above");
+                if !group_ready {
+                    self.ensure_group(conn, channel).await?;
+                    group_ready = true;
+                }
+
+                // entries pending since before a restart are picked up right away
+                if last_claim.is_none_or(|at| at.elapsed() >= claim_interval) {
+                    self.claim_stuck(conn, channel, &mut handler).await?;
+                    last_claim = Some(Instant::now());
+                }
+
+                let entries = self.read_batch(conn, channel, max_items, max_wait).await?;
+                if !entries.is_empty() {
+                    // entries read for the first time were delivered once
+                    let entries = entries.into_iter().map(|entry| (entry, 1)).collect();
+                    self.handle_batch(conn, channel, entries, &mut handler)
+                        .await?;
+                }
+                Ok(())
+            }
+            .await;
+
+            match step {
+                Ok(()) => backoff = INITIAL_BACKOFF,
+                Err(e) => {
+                    eprintln!(
+                        "⚠️ Consuming '{}' failed, retrying in {:?}: {:#}",
+                        channel, backoff, e
+                    );
+                    group_ready = false;
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+// runs the handler, waiting out the failures it marks as `Unavailable`. the
+// error returned is about the messages themselves
+async fn run_handler<F, Fut>(handler: &mut F, batch: Vec<Vec<u8>>) -> Result<()>
+where
+    F: FnMut(Vec<Vec<u8>>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match handler(batch.clone()).await {
+            Err(e) if e.is::<Unavailable>() => {
+                eprintln!("⚠️ Handler unavailable, retrying in {:?}: {}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Consumer for Redis {
+    async fn consumer<F, Fut>(&self, channel: &str, mut handler: F) -> Result<(), anyhow::Error>
+    where
+        F: FnMut(Vec<u8>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        // claimed entries still come in batches, they are handled in order
+        self.consume_group(channel, 1, Duration::ZERO, move |batch| {
+            let handled: Vec<Fut> = batch.into_iter().map(&mut handler).collect();
+            async move {
+                for message in handled {
+                    message.await?;
+                }
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    async fn consumer_batch<F, Fut>(
+        &self,
+        channel: &str,
+        max_items: usize,
+        max_wait: Duration,
+        handler: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        F: FnMut(Vec<Vec<u8>>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.consume_group(channel, max_items.max(1), max_wait, handler)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
 impl Publisher for Redis {
     async fn publisher(&self, channel: &str, payload: &[u8]) -> Result<(), anyhow::Error> {
-        // getting a client connection
-        let mut connection = self.client.get_multiplexed_tokio_connection().await?;
-        // converting the bytes which we get from the geyser into string
-        let payload_str = String::from_utf8(payload.to_vec())?;
+        let mut connection = self.publish_connection().await?;
+
+        // appending the message to the stream of the channel, trimmed only
+        // when a max length is set
+        let fields = [(PAYLOAD_FIELD, payload)];
+        let _: Option<String> = match self.config.max_len {
+            Some(max_len) => {
+                connection
+                    .xadd_maxlen(channel, StreamMaxlen::Approx(max_len), "*", &fields)
+                    .await?
+            }
+            None => connection.xadd(channel, "*", &fields).await?,
+        };
 
-        // publishing the message which is the data to the respective channel
-        redis::cmd("PUBLISH")
-            .arg(channel)
-            .arg(payload_str)
-            .query_async::<()>(&mut connection).await?;
+        Ok(())
+    }
+
+    // pipelines every XADD of the batch in a single round trip
+    async fn publish_batch(&self, messages: &[(&str, Vec<u8>)]) -> Result<(), anyhow::Error> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.publish_connection().await?;
+
+        let mut pipe = redis::pipe();
+        for (channel, payload) in messages {
+            let fields = [(PAYLOAD_FIELD, payload.as_slice())];
+            match self.config.max_len {
+                Some(max_len) => {
+                    pipe.xadd_maxlen(*channel, StreamMaxlen::Approx(max_len), "*", &fields)
+                }
+                None => pipe.xadd(*channel, "*", &fields),
+            }
+            .ignore();
+        }
+        pipe.query_async::<()>(&mut connection).await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::VecDeque, sync::Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        time::timeout,
+    };
+
+    /// Just enough of a redis for a consumer: every connection is handed
+    /// the next entry of the stream and dropped once it is acknowledged
+    struct FakeRedis {
+        entries: Mutex<VecDeque<(String, Vec<u8>)>>,
+    }
+
+    impl FakeRedis {
+        async fn start(entries: Vec<(&str, &[u8])>) -> String {
+            let fake = Arc::new(FakeRedis {
+                entries: Mutex::new(
+                    entries
+                        .into_iter()
+                        .map(|(id, payload)| (id.to_owned(), payload.to_vec()))
+                        .collect(),
+                ),
+            });
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("redis://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    tokio::spawn(fake.clone().serve(socket));
+                }
+            });
+            url
+        }
+
+        async fn serve(self: Arc<Self>, socket: TcpStream) {
+            let mut socket = BufReader::new(socket);
+            while let Some(command) = read_command(&mut socket).await {
+                let reply = match command[0].to_ascii_uppercase().as_slice() {
+                    b"XAUTOCLAIM" => b"*3\r\n$3\r\n0-0\r\n*0\r\n*0\r\n".to_vec(),
+                    b"XREADGROUP" => {
+                        let entry = self.entries.lock().unwrap().pop_front();
+                        match entry {
+                            Some((id, payload)) => read_reply(&command, &id, &payload),
+                            None => {
+                                tokio::time::sleep(Duration::from_millis(10)).await;
+                                b"*-1\r\n".to_vec()
+                            }
+                        }
+                    }
+                    b"XACK" => {
+                        let _ = socket.write_all(b":1\r\n").await;
+                        // the connection breaks after every acknowledged entry
+                        return;
+                    }
+                    _ => b"+OK\r\n".to_vec(),
+                };
+                if socket.write_all(&reply).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    // reads a command sent as an array of bulk strings
+    async fn read_command(socket: &mut BufReader<TcpStream>) -> Option<Vec<Vec<u8>>> {
+        let mut line = String::new();
+        socket.read_line(&mut line).await.ok()?;
+        let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            socket.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            socket.read_exact(&mut arg).await.ok()?;
+            arg.truncate(len);
+            args.push(arg);
+        }
+        Some(args)
+    }
+
+    // bulk string
+    fn bulk(value: &[u8]) -> Vec<u8> {
+        let mut bulk = format!("${}\r\n", value.len()).into_bytes();
+        bulk.extend_from_slice(value);
+        bulk.extend_from_slice(b"\r\n");
+        bulk
+    }
+
+    // the reply to an XREADGROUP `command`, holding a single entry of the
+    // stream it reads
+    fn read_reply(command: &[Vec<u8>], id: &str, payload: &[u8]) -> Vec<u8> {
+        let streams = command.iter().position(|arg| arg == b"STREAMS").unwrap();
+        let mut reply = b"*1\r\n*2\r\n".to_vec();
+        reply.extend(bulk(&command[streams + 1]));
+        reply.extend_from_slice(b"*1\r\n*2\r\n");
+        reply.extend(bulk(id.as_bytes()));
+        reply.extend_from_slice(b"*2\r\n");
+        reply.extend(bulk(PAYLOAD_FIELD.as_bytes()));
+        reply.extend(bulk(payload));
+        reply
+    }
+
+    // a redis holding back its answers to XADD until `batch` of them arrived,
+    // so a client waiting for each answer before the next command stalls.
+    // every XADD is sent on as its stream and payload
+    async fn pipelining_redis(
+        batch: usize,
+    ) -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut held = 0;
+            while let Some(command) = read_command(&mut socket).await {
+                if !command[0].eq_ignore_ascii_case(b"XADD") {
+                    socket.write_all(b"+OK\r\n").await.unwrap();
+                    continue;
+                }
+                let stream = String::from_utf8(command[1].clone()).unwrap();
+                let _ = tx.send((stream, command.last().unwrap().clone()));
+                held += 1;
+                if held == batch {
+                    socket.write_all(&bulk(b"1-0").repeat(held)).await.unwrap();
+                    held = 0;
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn batch_is_published_in_one_round_trip_in_order() {
+        let (url, mut rx) = pipelining_redis(3).await;
+        let redis = Redis::new(&url).unwrap();
+        let messages = [
+            ("slots", b"1".to_vec()),
+            ("accounts", b"2".to_vec()),
+            ("slots", b"3".to_vec()),
+        ];
+
+        timeout(Duration::from_secs(10), redis.publish_batch(&messages))
+            .await
+            .expect("the batch wasn't pipelined")
+            .unwrap();
+
+        for (channel, payload) in messages {
+            assert_eq!(rx.recv().await.unwrap(), (channel.to_owned(), payload));
+        }
+    }
+
+    #[tokio::test]
+    async fn consumer_survives_a_dropped_connection() {
+        let url = FakeRedis::start(vec![("1-0", b"first"), ("2-0", b"second")]).await;
+        let redis = Redis::new(&url).unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let consumer = tokio::spawn(async move {
+            redis
+                .consumer("accounts", move |payload| {
+                    let _ = tx.send(payload);
+                    async { Ok(()) }
+                })
+                .await
+        });
+
+        for expected in [b"first".as_slice(), b"second"] {
+            let payload = timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("no message within 10s")
+                .unwrap();
+            assert_eq!(payload, expected);
+        }
+        assert!(!consumer.is_finished());
+        consumer.abort();
+    }
+
+    #[tokio::test]
+    async fn unavailable_handler_is_retried_with_the_same_batch() {
+        let mut calls: Vec<Vec<Vec<u8>>> = vec![];
+        let mut handler = |batch: Vec<Vec<u8>>| {
+            calls.push(batch);
+            let result = if calls.len() == 1 {
+                Err(Unavailable(anyhow::anyhow!("database down")).into())
+            } else {
+                Ok(())
+            };
+            async { result }
+        };
+        run_handler(&mut handler, vec![b"a".to_vec()])
+            .await
+            .unwrap();
+        assert_eq!(calls, vec![vec![b"a".to_vec()], vec![b"a".to_vec()]]);
+    }
+
+    #[tokio::test]
+    async fn message_errors_are_returned_right_away() {
+        let mut calls = 0;
+        let mut handler = |_: Vec<Vec<u8>>| {
+            calls += 1;
+            async { Err(anyhow::anyhow!("bad message")) }
+        };
+        assert!(run_handler(&mut handler, vec![vec![]]).await.is_err());
+        assert_eq!(calls, 1);
+    }
+}